@@ -1,7 +1,42 @@
-use clap::{Parser, Subcommand};
+use std::{
+    fmt,
+    io::{stdout, IsTerminal},
+    str::FromStr,
+    time::Duration,
+};
+
+use clap::{Args, Parser, Subcommand};
 use itertools::Itertools;
+use regex::Regex;
+use serde_json::json;
 
-use crate::{db_manager::creation::create_default_tables, page_rank::ranker::rank_pages};
+use crate::{
+    db_manager::{
+        control::{get_crawl_state, set_crawl_state, CrawlState},
+        creation::{create_default_tables, open_existing},
+        lease::MIN_LEASE_DURATION,
+        ranking::GraphLink,
+        selecting::{
+            calculate_links_from, count_outlinks, get_inlinks, get_outlinks, get_page_fields,
+            get_page_id,
+        },
+    },
+    indexer::{
+        config::CrawlConfig,
+        frontier::FrontierStrategy,
+        scope::{CrawlScope, DomainBudget},
+    },
+    page_rank::{
+        bm25::Bm25Config,
+        pagerank::{compute_pagerank, PageRankConfig},
+        query::parse,
+        ranker::{rank_pages, RankedPage, RankingConfig, RankingMode},
+        snippet::{escape_html, Snippet},
+    },
+    page_scraper::{
+        fetcher::FetchLimits, html_getter::MAX_REDIRECTS, url_normalizer::normalize_url,
+    },
+};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)] // Read from `Cargo.toml`
@@ -29,8 +64,71 @@ enum Commands {
             required = false
         )]
         start_values: Vec<String>,
-        #[clap(value_parser = clap::value_parser!(u8), help = "Amount of threads to use", default_value_t = 8)]
-        threads: u8,
+        #[clap(flatten)]
+        crawl: CrawlArgs,
+    },
+    // continue the crawl of a database
+    #[clap(
+        about = "Continues the crawl of an existing database, or a paused crawl that is still running"
+    )]
+    Resume {
+        #[clap(short, long, action, help = "output logs")]
+        verbose: bool,
+        #[clap(short, long, value_parser, help = "Sets the path for the database")]
+        db_path: Option<String>,
+        #[clap(flatten)]
+        crawl: CrawlArgs,
+    },
+    // pause a running crawl
+    #[clap(about = "Pauses the running crawl of the database, no new pages are fetched")]
+    Pause {
+        #[clap(short, long, value_parser, help = "Sets the path for the database")]
+        db_path: Option<String>,
+    },
+    // stop a running crawl
+    #[clap(
+        about = "Stops the running crawl of the database after the pages that are being fetched"
+    )]
+    Stop {
+        #[clap(short, long, value_parser, help = "Sets the path for the database")]
+        db_path: Option<String>,
+    },
+    // compute the pagerank of all pages
+    #[clap(about = "Computes the pagerank of all pages in the database")]
+    Rank {
+        #[clap(short, long, action, help = "output logs")]
+        verbose: bool,
+        #[clap(short, long, value_parser, help = "Sets the path for the database")]
+        db_path: Option<String>,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = 0.85,
+            help = "Probability that a link is followed instead of jumping to a random page"
+        )]
+        damping: f64,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = 1e-6,
+            help = "The computation stops once the ranks change less than this"
+        )]
+        tolerance: f64,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = 100,
+            help = "Maximum amount of iterations"
+        )]
+        max_iterations: usize,
+    },
+    // show the link graph around a page
+    #[clap(about = "Shows the pages that link to a page and the pages it links to")]
+    Links {
+        #[clap(short, long, value_parser, help = "Sets the path for the database")]
+        db_path: Option<String>,
+        #[clap(value_parser, help = "the url of the page")]
+        url: String,
     },
     // search in the db for a value
     #[clap(about = "Searches the database for the keyword")]
@@ -41,7 +139,7 @@ enum Commands {
         db_path: Option<String>,
         #[clap(
             value_parser,
-            help = "the key for which the database should be searched"
+            help = "the query for which the database should be searched, supports \"phrases\", AND, OR, -exclude, (groups), site:, inurl:, intitle:, lang: and filetype:"
         )]
         search_word: String,
         #[clap(
@@ -50,9 +148,270 @@ enum Commands {
             help = "the amount of results displayed"
         )]
         amount: u32,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = 0,
+            help = "the amount of results skipped (for the next pages of results)"
+        )]
+        offset: u32,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = RankingMode::PageRank,
+            help = "Link analysis used for ranking (pagerank or hits)"
+        )]
+        mode: RankingMode,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = 1.2,
+            help = "BM25 k1, how fast repeated words stop raising the relevance"
+        )]
+        k1: f64,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = 0.75,
+            help = "BM25 b, how much long pages are penalized (0 to 1)"
+        )]
+        b: f64,
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = OutputFormat::Text,
+            help = "How the results are printed (text, html or json)"
+        )]
+        format: OutputFormat,
     },
 }
 
+/// How a crawl is run, shared by `start` and `resume`
+#[derive(Args)]
+struct CrawlArgs {
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 64,
+        help = "Amount of pages fetched at the same time"
+    )]
+    concurrency: usize,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 1000,
+        help = "Minimum time in milliseconds between two requests to the same host"
+    )]
+    min_delay: u64,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 2,
+        help = "Maximum amount of requests sent to the same host at the same time"
+    )]
+    max_per_host: usize,
+    #[clap(
+        long,
+        action,
+        help = "Removes tracking parameters (utm_source, fbclid, ...) from found links"
+    )]
+    strip_tracking: bool,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = MAX_REDIRECTS,
+        help = "Maximum amount of redirects followed for a page"
+    )]
+    max_redirects: usize,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 10_000,
+        help = "Time in milliseconds after which connecting to a host is given up"
+    )]
+    connect_timeout: u64,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 30_000,
+        help = "Time in milliseconds a host may stay silent while sending a page"
+    )]
+    read_timeout: u64,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 60_000,
+        help = "Time in milliseconds after which fetching a page (including its redirects) is given up"
+    )]
+    total_timeout: u64,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 5 * 1024 * 1024,
+        help = "Pages are cut off after this many bytes"
+    )]
+    max_body_size: usize,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 3,
+        help = "How often a page is tried again after a timeout or server error"
+    )]
+    max_retries: u32,
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = FrontierStrategy::BreadthFirst,
+        help = "Order in which pages are crawled (bfs, dfs, best-first or round-robin)"
+    )]
+    frontier: FrontierStrategy,
+    #[clap(
+        long,
+        value_parser,
+        help = "Pages further than this many links away from the start values aren't crawled"
+    )]
+    max_depth: Option<u32>,
+    #[clap(
+        long,
+        value_parser,
+        help = "No new pages are added once the database contains this many pages"
+    )]
+    max_pages: Option<usize>,
+    #[clap(
+        long,
+        value_parser,
+        help = "No new pages of a site are added once the database contains this many pages of it (a site is the allowed domain of the host or the host without www.)"
+    )]
+    max_pages_per_host: Option<usize>,
+    #[clap(
+        long = "max-pages-per-domain",
+        value_parser,
+        value_name = "DOMAIN=PAGES",
+        help = "No new pages of this domain and its subdomains are added once the database contains this many pages of them, instead of --max-pages-per-host (can be repeated)"
+    )]
+    domain_budgets: Vec<DomainBudget>,
+    #[clap(
+        long = "allow-domain",
+        value_parser,
+        help = "Only crawls pages of this domain and its subdomains (can be repeated)"
+    )]
+    allow_domains: Vec<String>,
+    #[clap(
+        long = "deny-domain",
+        value_parser,
+        help = "Never crawls pages of this domain and its subdomains (can be repeated)"
+    )]
+    deny_domains: Vec<String>,
+    #[clap(
+        long,
+        value_parser,
+        help = "Only crawls urls matching this regex (can be repeated)"
+    )]
+    include: Vec<Regex>,
+    #[clap(
+        long,
+        value_parser,
+        help = "Never crawls urls matching this regex (can be repeated)"
+    )]
+    exclude: Vec<Regex>,
+    #[clap(
+        long = "scheme",
+        value_parser,
+        default_values = &["http", "https"],
+        help = "Only crawls urls with this scheme (can be repeated)"
+    )]
+    schemes: Vec<String>,
+    #[clap(
+        long,
+        value_parser = parse_lease_duration,
+        default_value_t = 120_000,
+        help = "Time in milliseconds after which the pages of a killed crawler are crawled by the others (at least 5000)"
+    )]
+    lease_duration: u64,
+}
+
+/// parses the lease duration in milliseconds, shorter leases than `MIN_LEASE_DURATION` are rejected
+fn parse_lease_duration(millis: &str) -> Result<u64, String> {
+    let millis = millis.parse::<u64>().map_err(|err| err.to_string())?;
+
+    if u128::from(millis) < MIN_LEASE_DURATION.as_millis() {
+        return Err(format!(
+            "the lease duration has to be at least {} ms",
+            MIN_LEASE_DURATION.as_millis()
+        ));
+    }
+
+    Ok(millis)
+}
+
+impl CrawlArgs {
+    fn config(&self) -> CrawlConfig {
+        CrawlConfig {
+            concurrency: self.concurrency,
+            min_delay: Duration::from_millis(self.min_delay),
+            max_per_host: self.max_per_host,
+            strip_tracking: self.strip_tracking,
+            fetch: FetchLimits {
+                connect_timeout: Duration::from_millis(self.connect_timeout),
+                read_timeout: Duration::from_millis(self.read_timeout),
+                total_timeout: Duration::from_millis(self.total_timeout),
+                max_body_size: self.max_body_size,
+                max_redirects: self.max_redirects,
+            },
+            max_retries: self.max_retries,
+            frontier: self.frontier,
+            scope: CrawlScope {
+                max_depth: self.max_depth,
+                max_pages: self.max_pages,
+                max_pages_per_host: self.max_pages_per_host,
+                domain_budgets: self.domain_budgets.to_vec(),
+                allow_domains: self.allow_domains.to_vec(),
+                deny_domains: self.deny_domains.to_vec(),
+                include: self.include.to_vec(),
+                exclude: self.exclude.to_vec(),
+                schemes: self.schemes.to_vec(),
+            },
+            lease_duration: Duration::from_millis(self.lease_duration),
+        }
+    }
+}
+
+/// How the search results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// a results page for the terminal, matches are bold if the output is a terminal
+    Text,
+    /// an ordered list, matches are in `<mark>`
+    Html,
+    /// an array of results, matches in the snippet are in `<mark>`
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "html" => Ok(OutputFormat::Html),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "unknown output format {format} (expected text, html or json)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Html => write!(f, "html"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
 pub fn run() {
     let cli = Cli::parse();
 
@@ -61,45 +420,295 @@ pub fn run() {
             verbose,
             db_path,
             start_values,
-            threads,
-        } => start(*verbose, db_path.clone(), start_values.to_vec(), *threads),
+            crawl,
+        } => start(
+            *verbose,
+            db_path.clone(),
+            start_values.to_vec(),
+            crawl.config(),
+        ),
+        Commands::Resume {
+            verbose,
+            db_path,
+            crawl,
+        } => resume(*verbose, db_path.clone(), crawl.config()),
+        Commands::Pause { db_path } => pause(db_path.clone()),
+        Commands::Stop { db_path } => stop(db_path.clone()),
+        Commands::Rank {
+            verbose,
+            db_path,
+            damping,
+            tolerance,
+            max_iterations,
+        } => rank(
+            *verbose,
+            db_path.to_owned(),
+            PageRankConfig {
+                damping: *damping,
+                tolerance: *tolerance,
+                max_iterations: *max_iterations,
+            },
+        ),
+        Commands::Links { db_path, url } => links(db_path.to_owned(), url),
         Commands::Search {
             search_word,
             amount,
+            offset,
             verbose,
             db_path,
-        } => search(search_word, *amount, *verbose, db_path.to_owned()),
+            mode,
+            k1,
+            b,
+            format,
+        } => search(
+            search_word,
+            *amount,
+            *offset,
+            RankingConfig {
+                mode: *mode,
+                bm25: Bm25Config { k1: *k1, b: *b },
+            },
+            *format,
+            *verbose,
+            db_path.to_owned(),
+        ),
     }
 }
 
-fn start(verbose: bool, db_path: Option<String>, start_urls: Vec<String>, threads: u8) {
+fn start(verbose: bool, db_path: Option<String>, start_urls: Vec<String>, config: CrawlConfig) {
     if verbose {
         println!("Starting Indexer...");
     }
 
     let start_urls = start_urls.iter().map(|x| x.as_str()).collect_vec();
 
-    crate::run(start_urls, db_path, verbose, threads);
+    crate::run(start_urls, db_path, verbose, config);
 
     if verbose {
         println!("Crawler finished");
     }
 }
 
-fn search(search_word: &str, amount: u32, verbose: bool, db_path: Option<String>) {
+/// continues the frontier of the database, a paused crawl that is still running is resumed instead
+fn resume(verbose: bool, db_path: Option<String>, config: CrawlConfig) {
+    let db_path = db_path.unwrap_or("./database.db3".to_owned());
+
+    let conn = match open_existing(&db_path) {
+        Ok(conn) => conn,
+        Err(_) => {
+            println!("No database found at {db_path}, start a crawl with `start`");
+            return;
+        }
+    };
+
+    match get_crawl_state(&conn).unwrap() {
+        CrawlState::Paused => {
+            set_crawl_state(&conn, CrawlState::Running).unwrap();
+            println!("Resumed the crawl");
+        }
+        CrawlState::Running => println!("The crawl is already running"),
+        CrawlState::Stopping => println!("The crawl is stopping, resume it once it has stopped"),
+        CrawlState::Stopped => {
+            drop(conn);
+
+            if verbose {
+                println!("Resuming Indexer...");
+            }
+
+            crate::run(vec![], Some(db_path), verbose, config);
+
+            if verbose {
+                println!("Crawler finished");
+            }
+        }
+    }
+}
+
+fn pause(db_path: Option<String>) {
+    let db_path = db_path.unwrap_or("./database.db3".to_owned());
+
+    match open_existing(&db_path).and_then(|conn| Ok((get_crawl_state(&conn)?, conn))) {
+        Ok((CrawlState::Running, conn)) => {
+            set_crawl_state(&conn, CrawlState::Paused).unwrap();
+            println!("Paused the crawl, continue it with `resume`");
+        }
+        Ok((CrawlState::Paused, _)) => println!("The crawl is already paused"),
+        Ok((CrawlState::Stopping, _)) => println!("The crawl is already stopping"),
+        Ok((CrawlState::Stopped, _)) | Err(_) => println!("No crawl is running"),
+    }
+}
+
+fn stop(db_path: Option<String>) {
+    let db_path = db_path.unwrap_or("./database.db3".to_owned());
+
+    match open_existing(&db_path).and_then(|conn| Ok((get_crawl_state(&conn)?, conn))) {
+        Ok((CrawlState::Running | CrawlState::Paused, conn)) => {
+            set_crawl_state(&conn, CrawlState::Stopping).unwrap();
+            println!("Stopping the crawl after the pages that are being fetched");
+        }
+        Ok((CrawlState::Stopping, _)) => println!("The crawl is already stopping"),
+        Ok((CrawlState::Stopped, _)) | Err(_) => println!("No crawl is running"),
+    }
+}
+
+fn rank(verbose: bool, db_path: Option<String>, config: PageRankConfig) {
+    let db_path = db_path.unwrap_or("./database.db3".to_owned());
+
+    let conn = create_default_tables(&db_path).unwrap();
+
+    let result = compute_pagerank(&conn, &config, verbose).unwrap();
+
+    if !result.converged {
+        println!(
+            "Pagerank didn't converge after {} iterations",
+            result.iterations
+        );
+    }
+}
+
+fn links(db_path: Option<String>, url: &str) {
+    let db_path = db_path.unwrap_or("./database.db3".to_owned());
+
+    let conn = create_default_tables(&db_path).unwrap();
+
+    let url = normalize_url(url, false).unwrap_or_else(|| url.to_string());
+
+    let id = match get_page_id(&conn, &url).unwrap() {
+        Some(id) => id,
+        None => {
+            println!("{url} isn't in the database");
+            return;
+        }
+    };
+
+    println!(
+        "{url} is linked from {} pages and links to {} pages",
+        calculate_links_from(&conn, id).unwrap(),
+        count_outlinks(&conn, id).unwrap()
+    );
+
+    println!("\nInlinks:");
+    print_graph_links(&get_inlinks(&conn, id).unwrap());
+
+    println!("\nOutlinks:");
+    print_graph_links(&get_outlinks(&conn, id).unwrap());
+}
+
+fn print_graph_links(links: &[GraphLink]) {
+    for link in links {
+        let mut line = format!("   {}", link.url);
+
+        if let Some(anchor_text) = link.anchor_text.as_deref().filter(|text| !text.is_empty()) {
+            line.push_str(&format!(" \"{anchor_text}\""));
+        }
+        if let Some(rel) = &link.rel {
+            line.push_str(&format!(" ({rel})"));
+        }
+
+        println!("{line}");
+    }
+}
+
+fn search(
+    search_word: &str,
+    amount: u32,
+    offset: u32,
+    config: RankingConfig,
+    format: OutputFormat,
+    verbose: bool,
+    db_path: Option<String>,
+) {
     if verbose {
-        println!("Searchword: {search_word}, Amount: {amount}. Starting search....");
+        println!(
+            "Searchword: {search_word}, Amount: {amount}, Offset: {offset}, Mode: {}. Starting search....",
+            config.mode
+        );
     }
 
     let db_path = db_path.unwrap_or("./database.db3".to_owned());
 
     let conn = create_default_tables(&db_path).unwrap();
 
-    let results = rank_pages(&conn, search_word, amount).unwrap();
+    let results = rank_pages(&conn, search_word, amount, offset, &config).unwrap();
+
+    let terms = parse(search_word)
+        .map(|query| query.terms())
+        .unwrap_or_default();
+    let snippets = results
+        .iter()
+        .map(|result| {
+            let fields = get_page_fields(&conn, result.page.id).unwrap();
+
+            Snippet::new(&result.page.url, &fields, &terms)
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        OutputFormat::Text => print_text(&snippets, offset),
+        OutputFormat::Html => print_html(&snippets, offset),
+        OutputFormat::Json => print_json(&results, &snippets, offset),
+    }
+}
+
+/// prints the results like a results page: position and title, url and snippet
+fn print_text(snippets: &[Snippet], offset: u32) {
+    let terminal = stdout().is_terminal();
+
+    if snippets.is_empty() {
+        println!("No results");
+    }
+
+    for (i, snippet) in snippets.iter().enumerate() {
+        let title = snippet.title.as_deref().unwrap_or(&snippet.url);
+
+        if terminal {
+            println!("{}. \x1b[1;34m{title}\x1b[0m", offset as usize + i + 1);
+            println!("   \x1b[32m{}\x1b[0m", snippet.url);
+            println!("   {}", snippet.ansi());
+        } else {
+            println!("{}. {title}", offset as usize + i + 1);
+            println!("   {}", snippet.url);
+            println!("   {}", snippet.text());
+        }
+
+        println!();
+    }
+}
+
+fn print_html(snippets: &[Snippet], offset: u32) {
+    println!("<ol start=\"{}\">", offset as usize + 1);
 
-    for (i, result) in results.iter().enumerate() {
-        println!("{i}. {:?}", result);
+    for snippet in snippets {
+        let url = escape_html(&snippet.url);
+
+        println!(
+            "<li><a href=\"{url}\">{}</a><br><cite>{url}</cite><p>{}</p></li>",
+            escape_html(snippet.title.as_deref().unwrap_or(&snippet.url)),
+            snippet.html()
+        );
     }
+
+    println!("</ol>");
+}
+
+fn print_json(results: &[RankedPage], snippets: &[Snippet], offset: u32) {
+    let results = results
+        .iter()
+        .zip(snippets)
+        .enumerate()
+        .map(|(i, (result, snippet))| {
+            json!({
+                "position": offset as usize + i + 1,
+                "id": result.page.id,
+                "rank": result.rank,
+                "title": snippet.title,
+                "url": snippet.url,
+                "snippet": snippet.html(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    println!("{}", serde_json::to_string_pretty(&results).unwrap());
 }
 
 #[cfg(test)]
@@ -110,4 +719,14 @@ mod tests {
         use clap::CommandFactory;
         Cli::command().debug_assert()
     }
+
+    #[test]
+    fn rejects_short_leases() {
+        use super::parse_lease_duration;
+
+        assert_eq!(parse_lease_duration("5000"), Ok(5000));
+        assert!(parse_lease_duration("4999").is_err());
+        assert!(parse_lease_duration("0").is_err());
+        assert!(parse_lease_duration("-1").is_err());
+    }
 }
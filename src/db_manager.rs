@@ -1,5 +1,10 @@
+pub mod control;
 pub mod creation;
+pub mod full_text;
 pub mod helper;
+pub mod lease;
+pub mod outcome;
+pub mod postings;
 pub mod ranking;
 pub mod selecting;
 pub mod updating;
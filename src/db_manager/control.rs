@@ -0,0 +1,155 @@
+use std::time::{Duration, SystemTime};
+
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{creation::DatabaseConnection, helper::unix_millis};
+
+/// a crawler that hasn't updated its heartbeat for this long is assumed to be killed
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the crawl of a database is doing, stored in the `CrawlControl` table
+///
+/// other processes (`guugle pause`, `resume` and `stop`) control a running crawl by changing it,
+/// all crawlers of the database follow it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrawlState {
+    Running,
+    /// no new pages are claimed, the pages that are being fetched are finished
+    Paused,
+    /// the pages that are being fetched are finished, then the crawl ends
+    Stopping,
+    /// no crawl is running
+    Stopped,
+}
+
+impl CrawlState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CrawlState::Running => "running",
+            CrawlState::Paused => "paused",
+            CrawlState::Stopping => "stopping",
+            CrawlState::Stopped => "stopped",
+        }
+    }
+
+    fn from_str(state: &str) -> Self {
+        match state {
+            "running" => CrawlState::Running,
+            "paused" => CrawlState::Paused,
+            "stopping" => CrawlState::Stopping,
+            _ => CrawlState::Stopped,
+        }
+    }
+}
+
+/// creates the table with the single row that holds the state of the crawl
+/// and the table of the crawlers (processes) that take part in it
+pub fn create_control_table(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS CrawlControl (
+    id INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+    state TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS Crawlers (
+    owner TEXT NOT NULL PRIMARY KEY,
+    heartbeat INTEGER NOT NULL);",
+    )
+}
+
+/// returns the state of the crawl, `Stopped` if no crawler has given a sign of life for `HEARTBEAT_TIMEOUT`
+pub fn get_crawl_state(conn: &DatabaseConnection) -> Result<CrawlState, rusqlite::Error> {
+    let alive_since = unix_millis(SystemTime::now() - HEARTBEAT_TIMEOUT);
+
+    let crawlers: i64 = conn.connection.query_row(
+        "SELECT COUNT(*) FROM Crawlers WHERE heartbeat >= ?1;",
+        [alive_since],
+        |row| row.get(0),
+    )?;
+
+    if crawlers == 0 {
+        return Ok(CrawlState::Stopped);
+    }
+
+    conn.connection
+        .query_row("SELECT state FROM CrawlControl WHERE id = 1;", (), |row| {
+            row.get::<usize, String>(0)
+        })
+        .optional()
+        .map(|state| state.map_or(CrawlState::Running, |state| CrawlState::from_str(&state)))
+}
+
+/// changes the state of the crawl, a running crawl picks it up the next time it looks for a page
+pub fn set_crawl_state(
+    conn: &DatabaseConnection,
+    state: CrawlState,
+) -> Result<(), rusqlite::Error> {
+    conn.connection.execute(
+        "INSERT INTO CrawlControl (id, state) VALUES (1, ?1) ON CONFLICT (id) DO UPDATE SET state = ?1;",
+        [state.as_str()],
+    )?;
+
+    Ok(())
+}
+
+/// stores that the crawler `owner` is still alive, returns the state that was set by the other processes
+pub fn heartbeat(conn: &DatabaseConnection, owner: &str) -> Result<CrawlState, rusqlite::Error> {
+    conn.connection.execute(
+        "INSERT INTO Crawlers (owner, heartbeat) VALUES (?1, ?2) ON CONFLICT (owner) DO UPDATE SET heartbeat = ?2;",
+        (owner, unix_millis(SystemTime::now())),
+    )?;
+
+    conn.connection
+        .query_row("SELECT state FROM CrawlControl WHERE id = 1;", (), |row| {
+            row.get::<usize, String>(0)
+        })
+        .optional()
+        .map(|state| state.map_or(CrawlState::Running, |state| CrawlState::from_str(&state)))
+}
+
+/// the crawler `owner` is done, the crawl is `Stopped` once every crawler has left
+pub fn leave(conn: &DatabaseConnection, owner: &str) -> Result<(), rusqlite::Error> {
+    conn.connection
+        .execute("DELETE FROM Crawlers WHERE owner = ?1;", [owner])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::db_manager::{creation::create_default_tables, helper::gen_random_path};
+
+    use super::{get_crawl_state, heartbeat, leave, set_crawl_state, CrawlState};
+
+    #[test]
+    fn controls_crawl() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        // nothing has been crawled yet
+        assert_eq!(get_crawl_state(&conn).unwrap(), CrawlState::Stopped);
+
+        // the state only counts while a crawler is alive
+        set_crawl_state(&conn, CrawlState::Paused).unwrap();
+        assert_eq!(get_crawl_state(&conn).unwrap(), CrawlState::Stopped);
+
+        assert_eq!(heartbeat(&conn, "a").unwrap(), CrawlState::Paused);
+        assert_eq!(heartbeat(&conn, "b").unwrap(), CrawlState::Paused);
+        assert_eq!(get_crawl_state(&conn).unwrap(), CrawlState::Paused);
+
+        set_crawl_state(&conn, CrawlState::Stopping).unwrap();
+        assert_eq!(heartbeat(&conn, "a").unwrap(), CrawlState::Stopping);
+
+        // one crawler leaving doesn't end the crawl of the others
+        leave(&conn, "a").unwrap();
+        assert_eq!(get_crawl_state(&conn).unwrap(), CrawlState::Stopping);
+
+        // a crawler that was killed stops sending heartbeats
+        conn.connection
+            .execute("UPDATE Crawlers SET heartbeat = 0;", ())
+            .unwrap();
+        assert_eq!(get_crawl_state(&conn).unwrap(), CrawlState::Stopped);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,10 +1,26 @@
-use std::sync::{Arc, Mutex};
-
-use rusqlite::Connection;
-
-use crate::indexer::visit_types::ToVisit;
-
-use super::updating::reset_in_use;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::{
+    indexer::{scheduler::host_of, visit_types::ToVisit},
+    page_scraper::html_parser::Link,
+};
+
+use super::{
+    control::create_control_table,
+    full_text::fill_page_text,
+    helper::reverse_host,
+    lease::{claim_url, Lease},
+    outcome::Outcome,
+    postings::index_stored_pages,
+};
+
+/// how long a statement waits while another crawler (process) writes to the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 #[readonly::make]
@@ -20,6 +36,7 @@ pub fn create_default_tables(
     sqlite_path: &str,
 ) -> Result<DatabaseConnection, Box<dyn std::error::Error>> {
     let conn = Connection::open(sqlite_path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS Ranking (
@@ -27,12 +44,133 @@ pub fn create_default_tables(
     visited BOOLEAN NOT NULL DEFAULT false CHECK (visited IN (false, true)),
       url TEXT NOT NULL UNIQUE,
       content TEXT,
-      links_to TEXT,
-    in_use BOOLEAN NOT NULL DEFAULT false CHECK (visited IN (false, true)));",
+      links_to TEXT);",
         (),
     )?;
 
-    reset_in_use(&conn)?;
+    // url the page was finally loaded from if the requested url redirected
+    add_column_if_missing(&conn, "Ranking", "final_url", "TEXT")?;
+    // how often fetching the page failed with a transient error and when it may be tried again (unix time in ms)
+    add_column_if_missing(&conn, "Ranking", "retries", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(&conn, "Ranking", "retry_at", "INTEGER")?;
+
+    // what happened when the page was visited, see `Outcome`
+    if add_column_if_missing(&conn, "Ranking", "outcome", "TEXT")? {
+        migrate_outcomes(&conn)?;
+    }
+    add_column_if_missing(&conn, "Ranking", "status", "INTEGER")?;
+    add_column_if_missing(&conn, "Ranking", "content_type", "TEXT")?;
+    add_column_if_missing(&conn, "Ranking", "error", "TEXT")?;
+
+    // query independent score of the page, filled in by `compute_pagerank`
+    add_column_if_missing(&conn, "Ranking", "pagerank", "REAL")?;
+
+    // inverted index of the url, title and visible text of every page, see `index_page`
+    add_column_if_missing(&conn, "Ranking", "term_count", "INTEGER")?;
+    let has_postings = table_exists(&conn, "Postings")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Postings (
+    term TEXT NOT NULL,
+    page_id INTEGER NOT NULL REFERENCES Ranking(id),
+    frequency INTEGER NOT NULL,
+    positions TEXT NOT NULL,
+    PRIMARY KEY (term, page_id)) WITHOUT ROWID;",
+        (),
+    )?;
+
+    if !has_postings {
+        index_stored_pages(&conn)?;
+    }
+
+    // full text index used by `find`, the rowid is the id of the page
+    let has_page_text = table_exists(&conn, "PageText")?;
+    // language of the page from `<html lang>`, filled in together with the full text index
+    let lang_added = add_column_if_missing(&conn, "Ranking", "lang", "TEXT")?;
+
+    // the parts of the page extracted by `Document::fields`, filled in together with the full text index
+    let mut fields_added = false;
+    for column in ["title", "description", "keywords", "headings", "text"] {
+        fields_added |= add_column_if_missing(&conn, "Ranking", column, "TEXT")?;
+    }
+
+    // older versions stored a copy of the fields in the index (and only indexed the url, title and text before that),
+    // fts5 tables can't be altered
+    let page_text_outdated = has_page_text && !is_external_content(&conn, "PageText")?;
+    if page_text_outdated {
+        conn.execute("DROP TABLE PageText;", ())?;
+    }
+
+    // the columns are read from `Ranking`, so the text isn't stored twice
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS PageText USING fts5(url, title, description, keywords, headings, text, content = 'Ranking', content_rowid = 'id', tokenize = 'unicode61 remove_diacritics 2');",
+        (),
+    )?;
+
+    if !has_page_text || page_text_outdated || lang_added || fields_added {
+        fill_page_text(&conn)?;
+    }
+
+    // the link graph, older versions only stored the links in `links_to`
+    let has_links = table_exists(&conn, "Links")?;
+
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Links (
+    from_id INTEGER NOT NULL REFERENCES Ranking(id),
+    to_id INTEGER NOT NULL REFERENCES Ranking(id),
+    anchor_text TEXT,
+    rel TEXT,
+    PRIMARY KEY (from_id, to_id));
+CREATE INDEX IF NOT EXISTS links_to_id ON Links (to_id);",
+    )?;
+
+    if !has_links {
+        migrate_links(&conn)?;
+    }
+
+    // what the crawl frontier picks the next page by, see `Frontier`
+    // the host for round robin and the priority (amount of inlinks) for best-first
+    let host_added = add_column_if_missing(&conn, "Ranking", "host", "TEXT")?;
+    // the host with reversed labels, the pages of a domain are counted with its index (see `count_domain_pages`)
+    let reversed_host_added = add_column_if_missing(&conn, "Ranking", "reversed_host", "TEXT")?;
+    if host_added || reversed_host_added {
+        fill_hosts(&conn)?;
+    }
+    if add_column_if_missing(&conn, "Ranking", "priority", "REAL NOT NULL DEFAULT 0")? {
+        conn.execute(
+            "UPDATE Ranking SET priority = (SELECT COUNT(*) FROM Links WHERE to_id = Ranking.id AND from_id != to_id);",
+            (),
+        )?;
+    }
+
+    // how many links a page is away from a start url, see `CrawlScope`
+    // pages of older versions count as start urls
+    add_column_if_missing(&conn, "Ranking", "depth", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // the crawler that is visiting the page and when its claim expires, see `Lease`
+    // older versions claimed pages with `in_use`, which isn't used anymore
+    add_column_if_missing(&conn, "Ranking", "lease_owner", "TEXT")?;
+    add_column_if_missing(&conn, "Ranking", "lease_until", "INTEGER")?;
+
+    conn.execute_batch(
+        "DROP INDEX IF EXISTS ranking_priority;
+DROP INDEX IF EXISTS ranking_host;
+DROP INDEX IF EXISTS ranking_host_pages;
+CREATE INDEX IF NOT EXISTS ranking_frontier_priority ON Ranking (visited, priority);
+CREATE INDEX IF NOT EXISTS ranking_frontier_host ON Ranking (visited, host);
+CREATE INDEX IF NOT EXISTS ranking_reversed_host ON Ranking (reversed_host);
+CREATE INDEX IF NOT EXISTS ranking_lease ON Ranking (lease_owner);",
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Redirects (
+    from_url TEXT NOT NULL PRIMARY KEY,
+    to_url TEXT NOT NULL);",
+        (),
+    )?;
+
+    // the state of the crawl, used to pause and stop it from another process
+    create_control_table(&conn)?;
 
     Ok(DatabaseConnection {
         connection: conn,
@@ -40,31 +178,282 @@ pub fn create_default_tables(
     })
 }
 
-/// creates an entry in the database for a newly discovered page
-/// returns a new ToVisit instance
-pub fn unvisited_page(
+/// opens a database created by `create_default_tables` without upgrading it,
+/// used to control a crawl that is running in another process
+pub fn open_existing(sqlite_path: &str) -> Result<DatabaseConnection, rusqlite::Error> {
+    let conn = Connection::open_with_flags(
+        sqlite_path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+
+    create_control_table(&conn)?;
+
+    Ok(DatabaseConnection {
+        connection: conn,
+        _private: (),
+    })
+}
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2;",
+        [table, column],
+        |row| row.get::<usize, i64>(0),
+    )
+    .map(|count| count > 0)
+}
+
+/// adds a column to an existing table, used to upgrade databases created by older versions
+///
+/// returns true if the column was added
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &'static str,
+) -> Result<bool, rusqlite::Error> {
+    let exists = column_exists(conn, table, column)?;
+
+    // names can't be bound as parameters, so they are quoted
+    if !exists {
+        conn.execute(
+            &format!(
+                "ALTER TABLE {} ADD COLUMN {} {definition};",
+                quote_identifier(table),
+                quote_identifier(column)
+            ),
+            (),
+        )?;
+    }
+
+    Ok(!exists)
+}
+
+/// quotes a table or column name so it can be used in a statement
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// checks if the fts5 table reads its columns from another table
+fn is_external_content(conn: &Connection, table: &str) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1;",
+        [table],
+        |row| row.get::<usize, String>(0),
+    )
+    .map(|sql| sql.replace(' ', "").contains("content='"))
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1;",
+        [table],
+        |row| row.get::<usize, i64>(0),
+    )
+    .map(|count| count > 0)
+}
+
+/// stores the host (and reversed host) of every page, older versions didn't store them
+fn fill_hosts(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let pages = conn
+        .prepare("SELECT id, url FROM Ranking;")?
+        .query_map((), |row| {
+            Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let transaction = conn.unchecked_transaction()?;
+
+    {
+        let mut statement = transaction
+            .prepare("UPDATE Ranking SET host = ?1, reversed_host = ?2 WHERE id = ?3;")?;
+
+        for (id, url) in pages {
+            let (host, reversed_host) = host_columns(&url);
+            statement.execute((host, reversed_host, id))?;
+        }
+    }
+
+    transaction.commit()
+}
+
+/// the `host` and `reversed_host` of the page with the url
+fn host_columns(url: &str) -> (Option<String>, Option<String>) {
+    let host = host_of(url);
+    let reversed_host = host.as_deref().map(reverse_host);
+
+    (host, reversed_host)
+}
+
+/// fills the link graph from the `:::` joined `links_to` column of older versions
+///
+/// only links to urls that are stored as pages are added (older versions stored every found link as a page)
+pub fn migrate_links(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let pages = conn
+        .prepare("SELECT id, links_to FROM Ranking WHERE links_to IS NOT NULL AND links_to != '';")?
+        .query_map((), |row| {
+            Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut statement = conn.prepare(
+        "INSERT OR IGNORE INTO Links (from_id, to_id) SELECT ?1, id FROM Ranking WHERE url = ?2;",
+    )?;
+
+    let mut migrated = 0;
+
+    for (id, links_to) in pages {
+        for url in links_to.split(":::") {
+            migrated += statement.execute((id, url))?;
+        }
+    }
+
+    Ok(migrated)
+}
+
+/// older versions stored the outcome of a visit in the content column
+fn migrate_outcomes(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "UPDATE Ranking SET
+    outcome = CASE content
+        WHEN 'ERROR' THEN ?1
+        WHEN 'NOT HTML' THEN ?2
+        WHEN 'DISALLOWED BY ROBOTS' THEN ?3
+        WHEN 'REDIRECT' THEN ?4
+        ELSE ?5 END,
+    content = CASE WHEN content IN ('ERROR', 'NOT HTML', 'DISALLOWED BY ROBOTS', 'REDIRECT') THEN NULL ELSE content END
+WHERE visited = true;",
+        [
+            Outcome::Error,
+            Outcome::NotHtml,
+            Outcome::Disallowed,
+            Outcome::Redirect,
+            Outcome::Ok,
+        ]
+        .map(|outcome| outcome.as_str()),
+    )
+}
+
+/// stores the redirects that lead from the first url to `final_url`, every url points to the next one
+pub fn add_redirects(
+    conn: &DatabaseConnection,
+    redirects: &[String],
+    final_url: &str,
+) -> Result<(), rusqlite::Error> {
+    let mut statement = conn
+        .connection
+        .prepare("INSERT OR REPLACE INTO Redirects (from_url, to_url) VALUES (?1, ?2);")?;
+
+    let targets = redirects
+        .iter()
+        .skip(1)
+        .map(|url| url.as_str())
+        .chain([final_url]);
+
+    for (from, to) in redirects.iter().zip(targets) {
+        statement.execute((from, to))?;
+    }
+
+    Ok(())
+}
+
+/// returns the page a redirect points to so that its content can be stored
+///
+/// the page is created if it doesn't exist yet and claimed for the lease,
+/// `None` is returned if it has already been visited or another crawler is visiting it
+pub fn canonical_page(
     conn: Arc<Mutex<DatabaseConnection>>,
     url: &str,
+    lease: &Lease,
     verbose: bool,
-) -> Result<ToVisit, rusqlite::Error> {
-    let id: i64;
+) -> Result<Option<ToVisit>, rusqlite::Error> {
+    let id = {
+        let lock = conn.lock().unwrap();
+
+        let (host, reversed_host) = host_columns(url);
+        let inserted = lock.connection.execute(
+            "INSERT OR IGNORE INTO Ranking (url, host, reversed_host) VALUES (?1, ?2, ?3);",
+            (url, host, reversed_host),
+        )?;
+
+        if inserted > 0 && verbose {
+            println!("Found new page with url: {}", url);
+        }
 
-    {
-        let statement = conn.lock();
-        let statement = statement.unwrap();
+        claim_url(&lock, url, lease)?
+    };
 
-        let mut statement = statement
-            .connection
-            .prepare("INSERT INTO Ranking (url) VALUES (?);")?;
+    Ok(id.map(|id| ToVisit::new(url, id, &lease.owner, conn)))
+}
+
+/// stores the links of a page in the link graph
+///
+/// pages that don't exist yet are created as unvisited one link deeper than the linking page
+/// (as deep for redirects), returns the urls of the newly discovered pages
+pub fn add_links(
+    conn: &DatabaseConnection,
+    from_id: i64,
+    links: &[Link],
+    verbose: bool,
+) -> Result<Vec<String>, rusqlite::Error> {
+    let mut insert_page = conn.connection.prepare(
+        "INSERT OR IGNORE INTO Ranking (url, host, reversed_host, depth) VALUES (?1, ?2, ?3, COALESCE((SELECT depth FROM Ranking WHERE id = ?4), 0) + ?5);",
+    )?;
+    let mut insert_link = conn.connection.prepare(
+        "INSERT OR IGNORE INTO Links (from_id, to_id, anchor_text, rel) SELECT ?1, id, ?2, ?3 FROM Ranking WHERE url = ?4;",
+    )?;
+    // the priority of a page is the amount of pages linking to it
+    let mut raise_priority = conn
+        .connection
+        .prepare("UPDATE Ranking SET priority = priority + 1 WHERE url = ?1 AND id != ?2;")?;
 
-        id = statement.insert([url])?;
+    let mut discovered = vec![];
+
+    for link in links {
+        let step = u32::from(!link.is_redirect());
+        let (host, reversed_host) = host_columns(&link.href);
+
+        if insert_page.execute((&link.href, host, reversed_host, from_id, step))? > 0 {
+            if verbose {
+                println!("Found new page with url: {}", link.href);
+            }
+
+            discovered.push(link.href.clone());
+        }
+
+        let anchor_text = (!link.text.is_empty()).then_some(link.text.as_str());
+
+        if insert_link.execute((from_id, anchor_text, &link.rel, &link.href))? > 0 {
+            raise_priority.execute((&link.href, from_id))?;
+        }
+    }
+
+    Ok(discovered)
+}
+
+/// creates an entry in the database for a newly discovered page
+/// returns its id, `None` if the page is already stored
+pub fn unvisited_page(
+    conn: &DatabaseConnection,
+    url: &str,
+    verbose: bool,
+) -> Result<Option<i64>, rusqlite::Error> {
+    let (host, reversed_host) = host_columns(url);
+    let inserted = conn.connection.execute(
+        "INSERT OR IGNORE INTO Ranking (url, host, reversed_host) VALUES (?1, ?2, ?3);",
+        (url, host, reversed_host),
+    )?;
+
+    if inserted == 0 {
+        return Ok(None);
     }
 
     if verbose {
         println!("Found new page with url: {}", url);
     }
 
-    ToVisit::new(url, id, conn)
+    Ok(Some(conn.connection.last_insert_rowid()))
 }
 
 #[cfg(test)]
@@ -72,16 +461,23 @@ pub mod tests {
     use std::{
         fs,
         sync::{Arc, Mutex},
+        time::Duration,
     };
 
     use rusqlite::Connection;
 
-    use crate::db_manager::{
-        creation::create_default_tables,
-        helper::{gen_random_path, gen_vals},
+    use crate::{
+        db_manager::{
+            creation::create_default_tables,
+            helper::{gen_random_path, gen_vals},
+            lease::Lease,
+            ranking::GraphLink,
+            selecting::get_outlinks,
+        },
+        page_scraper::html_parser::Link,
     };
 
-    use super::unvisited_page;
+    use super::{add_column_if_missing, add_links, add_redirects, canonical_page, unvisited_page};
 
     #[test]
     fn file_created() {
@@ -120,22 +516,301 @@ pub mod tests {
             let conn = create_default_tables(path.to_str().unwrap()).unwrap();
             gen_vals(&conn);
 
-            let conn = Arc::new(Mutex::new(conn));
-
             // call function that is tested
-            let to_visit = unvisited_page(Arc::clone(&conn), WORD, false).unwrap();
-            let conn = conn.lock().unwrap();
+            let id = unvisited_page(&conn, WORD, false).unwrap().unwrap();
             let mut statement = conn
                 .connection
                 .prepare("SELECT url FROM Ranking WHERE id = ?1;")
                 .unwrap();
             let result = statement
-                .query_row([to_visit.id], |r| r.get::<usize, String>(0))
+                .query_row([id], |r| r.get::<usize, String>(0))
                 .unwrap();
 
-            assert_eq!(to_visit.url, WORD);
             assert_eq!(result, WORD);
+
+            // start urls of an earlier crawl are already stored
+            assert_eq!(unvisited_page(&conn, WORD, false).unwrap(), None);
         }
         fs::remove_file(path).unwrap();
     }
+
+    /// databases of older versions get the new columns
+    #[test]
+    fn upgrades_old_tables() {
+        let path = gen_random_path();
+
+        Connection::open(path.to_str().unwrap())
+            .unwrap()
+            .execute(
+                "CREATE TABLE Ranking (id INTEGER NOT NULL PRIMARY KEY, visited BOOLEAN NOT NULL DEFAULT false, url TEXT NOT NULL UNIQUE, content TEXT, links_to TEXT, in_use BOOLEAN NOT NULL DEFAULT false);",
+                (),
+            )
+            .unwrap();
+        Connection::open(path.to_str().unwrap())
+            .unwrap()
+            .execute(
+                "INSERT INTO Ranking (url, visited, content) VALUES ('a.ch', true, 'ERROR'), ('b.ch', true, '<html></html>'), ('c.ch', true, 'NOT HTML'), ('d.ch', false, NULL);",
+                (),
+            )
+            .unwrap();
+
+        // opening twice doesn't add the column twice
+        create_default_tables(path.to_str().unwrap()).unwrap();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        let result = conn
+            .connection
+            .execute("UPDATE Ranking SET final_url = 'a.ch';", ());
+
+        // the outcomes that were stored as content are moved to their own column
+        let outcomes = conn
+            .connection
+            .prepare("SELECT content, outcome FROM Ranking ORDER BY url;")
+            .unwrap()
+            .query_map((), |row| {
+                Ok((
+                    row.get::<usize, Option<String>>(0)?,
+                    row.get::<usize, Option<String>>(1)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        result.unwrap();
+        assert_eq!(
+            outcomes,
+            vec![
+                (None, Some("error".to_string())),
+                (Some("<html></html>".to_string()), Some("ok".to_string())),
+                (None, Some("not_html".to_string())),
+                (None, None)
+            ]
+        );
+    }
+
+    /// older versions stored a copy of the fields in the full text index
+    #[test]
+    fn upgrades_page_text() {
+        let path = gen_random_path();
+
+        create_default_tables(path.to_str().unwrap()).unwrap();
+        Connection::open(path.to_str().unwrap())
+            .unwrap()
+            .execute_batch(
+                "DROP TABLE PageText;
+CREATE VIRTUAL TABLE PageText USING fts5(url, title, description, keywords, headings, text, tokenize = 'unicode61 remove_diacritics 2');
+INSERT INTO Ranking (url, visited, content) VALUES ('a.ch', true, '<title>Cats</title><p>Purring</p>');",
+            )
+            .unwrap();
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        let sql = conn
+            .connection
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE name = 'PageText';",
+                (),
+                |row| row.get::<usize, String>(0),
+            )
+            .unwrap();
+        let found = conn
+            .connection
+            .query_row(
+                "SELECT rowid, title FROM PageText WHERE PageText MATCH 'purring';",
+                (),
+                |row| Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?)),
+            )
+            .unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert!(sql.contains("content = 'Ranking'"));
+        assert_eq!(found, (1, "Cats".to_string()));
+    }
+
+    #[test]
+    fn redirects_stored() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        add_redirects(
+            &conn,
+            &["http://a.ch/".to_string(), "https://a.ch/".to_string()],
+            "https://www.a.ch/",
+        )
+        .unwrap();
+
+        let redirects = conn
+            .connection
+            .prepare("SELECT from_url, to_url FROM Redirects ORDER BY from_url;")
+            .unwrap()
+            .query_map((), |row| {
+                Ok((row.get::<usize, String>(0)?, row.get::<usize, String>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            redirects,
+            vec![
+                ("http://a.ch/".to_string(), "https://a.ch/".to_string()),
+                ("https://a.ch/".to_string(), "https://www.a.ch/".to_string())
+            ]
+        );
+    }
+
+    /// names are quoted instead of being pasted into the statement
+    #[test]
+    fn quotes_column_names() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+        gen_vals(&conn);
+
+        let name = "odd\" TEXT; DROP TABLE Ranking; --";
+
+        let added = add_column_if_missing(&conn.connection, "Ranking", name, "TEXT").unwrap();
+        let added_again = add_column_if_missing(&conn.connection, "Ranking", name, "TEXT").unwrap();
+        let rows = conn
+            .connection
+            .query_row("SELECT COUNT(*) FROM Ranking;", (), |row| {
+                row.get::<usize, i64>(0)
+            })
+            .unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert!(added);
+        assert!(!added_again);
+        assert_eq!(rows, 6);
+    }
+
+    /// the target of a redirect is only returned if nobody else visits it
+    #[test]
+    fn canonical_page_claimed() {
+        let path = gen_random_path();
+        {
+            let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+            gen_vals(&conn);
+
+            let conn = Arc::new(Mutex::new(conn));
+            let lease = Lease::new(Duration::from_secs(60));
+            let other = Lease::new(Duration::from_secs(60));
+
+            // new page
+            let page = canonical_page(Arc::clone(&conn), "https://new.ch/", &lease, false)
+                .unwrap()
+                .unwrap();
+            assert_eq!(page.url, "https://new.ch/");
+
+            // is already leased, also by another crawler
+            assert!(
+                canonical_page(Arc::clone(&conn), "https://new.ch/", &lease, false)
+                    .unwrap()
+                    .is_none()
+            );
+            assert!(
+                canonical_page(Arc::clone(&conn), "https://new.ch/", &other, false)
+                    .unwrap()
+                    .is_none()
+            );
+
+            // has already been visited
+            assert!(canonical_page(Arc::clone(&conn), "test.ch", &lease, false)
+                .unwrap()
+                .is_none());
+
+            // released once dropped
+            drop(page);
+            assert!(
+                canonical_page(Arc::clone(&conn), "https://new.ch/", &other, false)
+                    .unwrap()
+                    .is_some()
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    /// the links of databases of older versions are moved to the link graph
+    #[test]
+    fn migrates_links() {
+        let path = gen_random_path();
+
+        let old = Connection::open(path.to_str().unwrap()).unwrap();
+        old.execute_batch(
+            "CREATE TABLE Ranking (id INTEGER NOT NULL PRIMARY KEY, visited BOOLEAN NOT NULL DEFAULT false, url TEXT NOT NULL UNIQUE, content TEXT, links_to TEXT, in_use BOOLEAN NOT NULL DEFAULT false);
+INSERT INTO Ranking (url, visited, links_to) VALUES ('a.ch', true, 'b.ch:::c.ch:::gone.ch'), ('b.ch', true, 'a.ch'), ('c.ch', false, NULL);",
+        )
+        .unwrap();
+        drop(old);
+
+        create_default_tables(path.to_str().unwrap()).unwrap();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        let links = conn
+            .connection
+            .prepare("SELECT from_id, to_id FROM Links ORDER BY from_id, to_id;")
+            .unwrap()
+            .query_map((), |row| {
+                Ok((row.get::<usize, i64>(0)?, row.get::<usize, i64>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(links, vec![(1, 2), (1, 3), (2, 1)]);
+    }
+
+    #[test]
+    fn links_stored() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+        gen_vals(&conn);
+
+        let links = [
+            Link {
+                href: "help.ch".to_string(),
+                text: "Help".to_string(),
+                rel: None,
+            },
+            Link {
+                href: "https://new.ch/".to_string(),
+                text: String::new(),
+                rel: Some("nofollow".to_string()),
+            },
+        ];
+
+        let discovered = add_links(&conn, 1, &links, false).unwrap();
+        // adding the same links again changes nothing
+        let rediscovered = add_links(&conn, 1, &links, false).unwrap();
+
+        let outlinks = get_outlinks(&conn, 1).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(discovered, vec!["https://new.ch/".to_string()]);
+        assert!(rediscovered.is_empty());
+        assert_eq!(
+            outlinks,
+            vec![
+                GraphLink {
+                    url: "help.ch".to_string(),
+                    anchor_text: Some("Help".to_string()),
+                    rel: None,
+                },
+                GraphLink {
+                    url: "https://new.ch/".to_string(),
+                    anchor_text: None,
+                    rel: Some("nofollow".to_string()),
+                },
+            ]
+        );
+    }
 }
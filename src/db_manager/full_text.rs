@@ -0,0 +1,228 @@
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{
+    page_rank::tokenizer::tokenize,
+    page_scraper::html_parser::{Document, PageFields},
+};
+
+/// extracts the fields of the stored content of the page (see `Document::fields`) into their columns and the full text index
+///
+/// pages without content are removed from the index
+pub fn update_page_text(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    let content = conn
+        .query_row("SELECT content FROM Ranking WHERE id = ?1;", [id], |row| {
+            row.get::<usize, Option<String>>(0)
+        })
+        .optional()?
+        .flatten();
+
+    match content {
+        Some(content) => set_page_text(conn, id, &Document::parse_str(&content).fields()),
+        None => remove_page_text(conn, id),
+    }
+}
+
+/// stores the fields of the page in their columns and the full text index (the `PageText` fts5 table)
+pub fn set_page_text(
+    conn: &Connection,
+    id: i64,
+    fields: &PageFields,
+) -> Result<(), rusqlite::Error> {
+    remove_page_text(conn, id)?;
+
+    conn.execute(
+        "UPDATE Ranking SET lang = ?1, title = ?2, description = ?3, keywords = ?4, headings = ?5, text = ?6 WHERE id = ?7;",
+        (
+            &fields.lang,
+            &fields.title,
+            &fields.description,
+            fields.keywords.join(", "),
+            fields.headings.join("\n"),
+            &fields.text,
+            id,
+        ),
+    )?;
+    // `PageText` only stores the index, the columns are read from `Ranking`
+    conn.execute(
+        "INSERT INTO PageText (rowid, url, title, description, keywords, headings, text) SELECT id, url, title, description, keywords, headings, text FROM Ranking WHERE id = ?1;",
+        [id],
+    )?;
+
+    Ok(())
+}
+
+/// removes the page from the full text index and clears its fields
+///
+/// the index of an external content table can only be updated with the values that were indexed,
+/// a page is indexed if its `text` is set
+fn remove_page_text(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO PageText (PageText, rowid, url, title, description, keywords, headings, text) SELECT 'delete', id, url, title, description, keywords, headings, text FROM Ranking WHERE id = ?1 AND text IS NOT NULL;",
+        [id],
+    )?;
+    conn.execute(
+        "UPDATE Ranking SET title = NULL, description = NULL, keywords = NULL, headings = NULL, text = NULL WHERE id = ?1;",
+        [id],
+    )?;
+
+    Ok(())
+}
+
+/// rebuilds the full text index of all stored pages, databases of older versions don't have one
+pub fn fill_page_text(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let ids = conn
+        .prepare("SELECT id FROM Ranking WHERE content IS NOT NULL;")?
+        .query_map((), |row| row.get::<usize, i64>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let transaction = conn.unchecked_transaction()?;
+
+    // the index may not match the fields, so it can't be updated page by page
+    transaction.execute("INSERT INTO PageText (PageText) VALUES ('delete-all');", ())?;
+    transaction.execute(
+        "UPDATE Ranking SET title = NULL, description = NULL, keywords = NULL, headings = NULL, text = NULL;",
+        (),
+    )?;
+
+    for id in &ids {
+        update_page_text(&transaction, *id)?;
+    }
+
+    transaction.commit()?;
+
+    Ok(ids.len())
+}
+
+/// turns text into a fts5 query that matches pages containing its words next to each other
+///
+/// only the words of the text are kept and quoted, so the fts5 query syntax (`AND`, `*`, `:`, ...) can't be used
+/// by accident, `column` restricts the match to the url, title or text,
+/// returns `None` if the text doesn't contain any words
+pub fn phrase_query(column: Option<&str>, text: &str) -> Option<String> {
+    let words = tokenize(text);
+
+    if words.is_empty() {
+        return None;
+    }
+
+    let phrase = format!("\"{}\"", words.join(" "));
+
+    Some(match column {
+        Some(column) => format!("{column} : {phrase}"),
+        None => phrase,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        db_manager::{
+            creation::create_default_tables,
+            helper::{gen_random_path, gen_vals, lease_page},
+            selecting::get_page_fields,
+            updating::update_to_visited,
+        },
+        page_scraper::html_parser::Document,
+    };
+
+    use super::{fill_page_text, phrase_query, update_page_text};
+
+    #[test]
+    fn builds_queries() {
+        assert_eq!(
+            phrase_query(None, "Team OR crystal*").unwrap(),
+            "\"team or crystal\""
+        );
+        assert_eq!(
+            phrase_query(Some("title"), "NEAR(\"x\")").unwrap(),
+            "title : \"near x\""
+        );
+        assert_eq!(phrase_query(None, " \"-* "), None);
+    }
+
+    /// the index follows the content of the page
+    #[test]
+    fn synced_on_visit() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let content =
+            "<html><head><title>Guugle</title></head><body><p>Search engine</p></body></html>";
+        let fields = Document::parse_str(content).fields();
+        lease_page(&conn, 3, "crawler");
+        update_to_visited(&conn, 3, "crawler", content, vec![], &fields, false).unwrap();
+
+        let found = conn
+            .connection
+            .prepare("SELECT rowid, title FROM PageText WHERE PageText MATCH ?1;")
+            .unwrap()
+            .query_map([phrase_query(None, "engine").unwrap()], |row| {
+                Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let old_content = conn
+            .connection
+            .query_row(
+                "SELECT COUNT(*) FROM PageText WHERE PageText MATCH '\"help\"' AND rowid = 3;",
+                (),
+                |row| row.get::<usize, i64>(0),
+            )
+            .unwrap();
+
+        let fields = get_page_fields(&conn, 3).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(found, vec![(3, "Guugle".to_string())]);
+        assert_eq!(fields.title, Some("Guugle".to_string()));
+        assert_eq!(fields.text, "Search engine");
+        assert_eq!(old_content, 0);
+    }
+
+    /// the index only contains what is stored in `Ranking`, revisited and emptied pages don't leave old terms behind
+    #[test]
+    fn consistent_with_pages() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+        lease_page(&conn, 3, "crawler");
+
+        for content in ["<p>First visit</p>", "<p>Second visit</p>"] {
+            let fields = Document::parse_str(content).fields();
+            update_to_visited(&conn, 3, "crawler", content, vec![], &fields, false).unwrap();
+        }
+        conn.connection
+            .execute("UPDATE Ranking SET content = NULL WHERE id = 4;", ())
+            .unwrap();
+        update_page_text(&conn.connection, 4).unwrap();
+        fill_page_text(&conn.connection).unwrap();
+
+        // unvisited pages aren't indexed, so the index can't be compared with all of `Ranking` (`rank = 1`)
+        let integrity = conn.connection.execute(
+            "INSERT INTO PageText (PageText) VALUES ('integrity-check');",
+            (),
+        );
+        let matches = |search: &str| {
+            conn.connection
+                .query_row(
+                    "SELECT COUNT(*) FROM PageText WHERE PageText MATCH ?1;",
+                    [phrase_query(None, search).unwrap()],
+                    |row| row.get::<usize, i64>(0),
+                )
+                .unwrap()
+        };
+        let (first, second, ep) = (matches("first"), matches("second"), matches("ep ch"));
+
+        fs::remove_file(path).unwrap();
+
+        integrity.unwrap();
+        assert_eq!((first, second, ep), (0, 1, 0));
+    }
+}
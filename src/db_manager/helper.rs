@@ -1,32 +1,79 @@
+#[cfg(test)]
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+#[cfg(test)]
 use rusqlite::{Result, Rows};
 
+#[cfg(test)]
 use super::creation::DatabaseConnection;
+#[cfg(test)]
+use super::{creation::migrate_links, full_text::fill_page_text, postings::index_stored_pages};
+
+/// escapes `%`, `_` and `\` so the value only matches itself in a `LIKE ... ESCAPE '\'` pattern
+pub fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
 
 // counts how many rows the sql select statement outputed
+#[cfg(test)]
 pub fn count_rows(rows: Result<Rows<'_>>) -> Result<usize, rusqlite::Error> {
     let values: Vec<_> = rows?.mapped(|_| Ok(())).collect();
 
     Ok(values.len())
 }
 
+/// reverses the labels of the host and ends it with a dot, `www.example.com` becomes `com.example.www.`
+///
+/// the hosts of a domain and its subdomains then share a prefix, see `count_domain_pages`
+pub fn reverse_host(host: &str) -> String {
+    host.rsplit('.').map(|label| format!("{label}.")).collect()
+}
+
+/// converts a point in time to milliseconds since the unix epoch, the way times are stored in the database
+pub fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
 pub fn gen_random_path() -> PathBuf {
-    let path = format!("./{}.db3", uuid::Uuid::new_v4().to_string());
+    let path = format!("./{}.db3", uuid::Uuid::new_v4());
 
     Path::new(&path).to_owned()
 }
 
+/// leases the page to `owner` like a crawler that claimed it, also if it was visited already
+#[cfg(test)]
+pub fn lease_page(conn: &DatabaseConnection, id: i64, owner: &str) {
+    conn.connection
+        .execute(
+            "UPDATE Ranking SET lease_owner = ?1, lease_until = ?2 WHERE id = ?3;",
+            (owner, i64::MAX, id),
+        )
+        .unwrap();
+}
+
+#[cfg(test)]
 pub fn gen_vals(conn: &DatabaseConnection) {
     let mut prep = conn
-            .connection
-            .prepare("INSERT INTO Ranking (url, links_to, in_use, visited, content) VALUES (?1, ?2, ?3, ?4, ?5);") 
-            .unwrap();
+        .connection
+        .prepare("INSERT INTO Ranking (url, links_to, visited, content) VALUES (?1, ?2, ?3, ?4);")
+        .unwrap();
 
     prep.execute((
         "test.ch",
         "team-crystal.ch:::google.ch:::example.com",
-        false,
         true,
         "team-crystal.ch:::google.ch:::example.com",
     ))
@@ -34,34 +81,24 @@ pub fn gen_vals(conn: &DatabaseConnection) {
     prep.execute((
         "help.ch",
         "team-crystal.ch:::google.ch:::test.ch",
-        false,
         true,
         "team-crystal.ch:::google.ch:::test.ch",
     ))
     .unwrap();
-    prep.execute(("p.ch", "help.ch", false, true, "help.ch"))
-        .unwrap();
+    prep.execute(("p.ch", "help.ch", true, "help.ch")).unwrap();
     prep.execute((
         "ep.ch",
         "team-crystal.ch:::help.ch",
-        false,
         true,
         "team-crystal.ch::help.ch",
     ))
     .unwrap();
-    prep.execute((
-        "lp.ch",
-        "help.ch:::google.ch",
-        false,
-        true,
-        "help.ch:::google.ch",
-    ))
-    .unwrap();
+    prep.execute(("lp.ch", "help.ch:::google.ch", true, "help.ch:::google.ch"))
+        .unwrap();
 
     prep.execute((
             "hre.he",
             "test.ch:::lp.ch",
-            false,
             true,
             "<html><body><h1>
             Laborum nulla quis deserunt labore quis cupidatat reprehenderit amet consequat reprehenderit tempor anim sint amet. Eiusmod fugiat eu aliqua qui do proident adipisicing. Dolore esse laborum voluptate in qui in ex. Sunt exercitation sit dolore cillum. Nostrud non aliqua sit anim aliqua labore Lorem quis nostrud. Exercitation ex nulla in laborum eu non voluptate consectetur.
@@ -73,6 +110,11 @@ pub fn gen_vals(conn: &DatabaseConnection) {
             </h1></body></html>",
         ))
         .unwrap();
+
+    // like a database of an older version, where the links were only stored in `links_to`
+    migrate_links(&conn.connection).unwrap();
+    index_stored_pages(&conn.connection).unwrap();
+    fill_page_text(&conn.connection).unwrap();
 }
 
 #[cfg(test)]
@@ -139,11 +181,10 @@ mod tests {
 
         let test_vals = vals.iter();
 
-        let correct_vals = vec![
+        let correct_vals = [
             Ranking {
                 id: 1,
                 content: Some("team-crystal.ch:::google.ch:::example.com".to_string()),
-                in_use: false,
                 visited: true,
                 links_to: Some("team-crystal.ch:::google.ch:::example.com".to_string()),
                 url: "test.ch".to_string(),
@@ -153,7 +194,6 @@ mod tests {
                 url: "help.ch".to_string(),
                 content: Some("team-crystal.ch:::google.ch:::test.ch".to_string()),
                 links_to: Some("team-crystal.ch:::google.ch:::test.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -161,7 +201,6 @@ mod tests {
                 url: "p.ch".to_string(),
                 content: Some("help.ch".to_string()),
                 links_to: Some("help.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -169,7 +208,6 @@ mod tests {
                 url: "ep.ch".to_string(),
                 content: Some("team-crystal.ch::help.ch".to_string()),
                 links_to: Some("team-crystal.ch:::help.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -177,7 +215,6 @@ mod tests {
                 url: "lp.ch".to_string(),
                 content: Some("help.ch:::google.ch".to_string()),
                 links_to: Some("help.ch:::google.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -192,7 +229,6 @@ mod tests {
             Eu nisi eu quis anim tempor fugiat deserunt est deserunt nulla ad do. Ipsum pariatur enim eiusmod minim cupidatat esse excepteur nostrud proident officia Lorem laboris esse. Excepteur reprehenderit anim duis exercitation labore nisi aliquip duis do. Id eiusmod dolore ex nulla nulla.
             </h1></body></html>".to_string()),
                 links_to: Some("test.ch:::lp.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
         ];
@@ -0,0 +1,145 @@
+use std::time::{Duration, SystemTime};
+
+use rusqlite::OptionalExtension;
+
+use super::{creation::DatabaseConnection, helper::unix_millis};
+
+/// the pages a crawler may claim now: not visited, not leased by a crawler (or the lease has expired)
+/// and not waiting for a retry, `?1` is the current time
+pub const CLAIMABLE: &str = "visited = false AND (lease_until IS NULL OR lease_until <= ?1) AND (retry_at IS NULL OR retry_at <= ?1)";
+
+/// the shortest lease, it is renewed every second and mustn't expire while the writer holds the database
+pub const MIN_LEASE_DURATION: Duration = Duration::from_secs(5);
+
+/// A crawler's claim on the pages it is visiting
+///
+/// other crawlers (in this or another process) skip leased pages, leases that aren't renewed expire
+/// so the pages of a crawler that was killed are visited by the others
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    /// unique id of the crawler
+    pub owner: String,
+    /// how long a page stays claimed after it was claimed or the lease was renewed
+    pub duration: Duration,
+}
+
+impl Lease {
+    /// a lease for a new crawler
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            owner: uuid::Uuid::new_v4().to_string(),
+            duration,
+        }
+    }
+
+    /// the time (unix ms) the lease expires if it is taken or renewed now
+    pub fn until(&self) -> i64 {
+        unix_millis(SystemTime::now() + self.duration)
+    }
+}
+
+/// claims the page with the url if it may be visited, returns its id
+pub fn claim_url(
+    conn: &DatabaseConnection,
+    url: &str,
+    lease: &Lease,
+) -> Result<Option<i64>, rusqlite::Error> {
+    conn.connection
+        .query_row(
+            &format!("UPDATE Ranking SET lease_owner = ?2, lease_until = ?3 WHERE url = ?4 AND {CLAIMABLE} RETURNING id;"),
+            (unix_millis(SystemTime::now()), &lease.owner, lease.until(), url),
+            |row| row.get(0),
+        )
+        .optional()
+}
+
+/// gives the page back if it is still leased by `owner`
+pub fn release_page(
+    conn: &DatabaseConnection,
+    id: i64,
+    owner: &str,
+) -> Result<usize, rusqlite::Error> {
+    conn.connection.execute(
+        "UPDATE Ranking SET lease_owner = NULL, lease_until = NULL WHERE id = ?1 AND lease_owner = ?2;",
+        (id, owner),
+    )
+}
+
+/// extends all leases of the crawler, returns the amount of renewed leases
+pub fn renew_leases(conn: &DatabaseConnection, lease: &Lease) -> Result<usize, rusqlite::Error> {
+    conn.connection.execute(
+        "UPDATE Ranking SET lease_until = ?1 WHERE lease_owner = ?2;",
+        (lease.until(), &lease.owner),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use crate::db_manager::{
+        creation::{create_default_tables, DatabaseConnection},
+        helper::gen_random_path,
+    };
+
+    use super::{claim_url, release_page, renew_leases, Lease};
+
+    fn lease_until(conn: &DatabaseConnection) -> Option<i64> {
+        conn.connection
+            .query_row("SELECT lease_until FROM Ranking WHERE id = 1;", (), |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn claims_once() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        conn.connection
+            .execute("INSERT INTO Ranking (url) VALUES ('a.ch');", ())
+            .unwrap();
+
+        let first = Lease::new(Duration::from_secs(60));
+        let second = Lease::new(Duration::from_secs(60));
+
+        assert_eq!(claim_url(&conn, "a.ch", &first).unwrap(), Some(1));
+        assert_eq!(claim_url(&conn, "a.ch", &second).unwrap(), None);
+        assert_eq!(claim_url(&conn, "b.ch", &second).unwrap(), None);
+
+        // only the owner can give the page back
+        assert_eq!(release_page(&conn, 1, &second.owner).unwrap(), 0);
+        assert_eq!(release_page(&conn, 1, &first.owner).unwrap(), 1);
+        assert_eq!(claim_url(&conn, "a.ch", &second).unwrap(), Some(1));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reclaims_expired_leases() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        conn.connection
+            .execute("INSERT INTO Ranking (url) VALUES ('a.ch');", ())
+            .unwrap();
+
+        // a crawler that was killed never renews its lease
+        let killed = Lease::new(Duration::ZERO);
+        let alive = Lease::new(Duration::from_secs(60));
+
+        assert_eq!(claim_url(&conn, "a.ch", &killed).unwrap(), Some(1));
+        assert_eq!(claim_url(&conn, "a.ch", &alive).unwrap(), Some(1));
+
+        // the killed crawler lost the page
+        assert_eq!(renew_leases(&conn, &killed).unwrap(), 0);
+
+        let before = lease_until(&conn).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(renew_leases(&conn, &alive).unwrap(), 1);
+        assert!(lease_until(&conn).unwrap() > before);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use crate::page_scraper::html::HtmlGetterError;
+
+/// What happened when a page was visited, stored in the `outcome` column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// the page was fetched and its content stored
+    Ok,
+    /// the page redirects to another page (stored in `final_url`)
+    Redirect,
+    /// the robots.txt of the host doesn't allow crawling the page
+    Disallowed,
+    NotHtml,
+    InvalidUrl,
+    /// the connection failed or broke (including tls and io errors)
+    NetworkError,
+    Timeout,
+    /// the server answered with an unexpected status code
+    HttpStatus,
+    ServerError,
+    TooManyRequests,
+    RedirectLoop,
+    TooManyRedirects,
+    /// failed in a version that didn't store the reason
+    Error,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Redirect => "redirect",
+            Outcome::Disallowed => "disallowed",
+            Outcome::NotHtml => "not_html",
+            Outcome::InvalidUrl => "invalid_url",
+            Outcome::NetworkError => "network_error",
+            Outcome::Timeout => "timeout",
+            Outcome::HttpStatus => "http_status",
+            Outcome::ServerError => "server_error",
+            Outcome::TooManyRequests => "too_many_requests",
+            Outcome::RedirectLoop => "redirect_loop",
+            Outcome::TooManyRedirects => "too_many_redirects",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// The outcome of a visit together with the details the server sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchOutcome {
+    pub outcome: Outcome,
+    /// http status code of the (last) response
+    pub status: Option<u16>,
+    /// Content-Type of the (last) response
+    pub content_type: Option<String>,
+    /// description of the error if the page couldn't be fetched
+    pub error: Option<String>,
+}
+
+impl FetchOutcome {
+    pub fn new(outcome: Outcome) -> Self {
+        Self {
+            outcome,
+            status: None,
+            content_type: None,
+            error: None,
+        }
+    }
+}
+
+impl From<&HtmlGetterError> for FetchOutcome {
+    fn from(err: &HtmlGetterError) -> Self {
+        let outcome = match err {
+            HtmlGetterError::NotHTML(_) => Outcome::NotHtml,
+            HtmlGetterError::GetError(_) => Outcome::NetworkError,
+            HtmlGetterError::StatusCode(_) => Outcome::HttpStatus,
+            HtmlGetterError::UrlError(_) => Outcome::InvalidUrl,
+            HtmlGetterError::TooManyRequests(..) => Outcome::TooManyRequests,
+            HtmlGetterError::RedirectLoop(_) => Outcome::RedirectLoop,
+            HtmlGetterError::TooManyRedirects(_) => Outcome::TooManyRedirects,
+            HtmlGetterError::ServerError(_) => Outcome::ServerError,
+            HtmlGetterError::Timeout(_) => Outcome::Timeout,
+        };
+
+        let content_type = match err {
+            HtmlGetterError::NotHTML(content_type) => content_type.clone(),
+            _ => None,
+        };
+
+        Self {
+            outcome,
+            status: err.status().map(|status| status.as_u16()),
+            content_type,
+            error: Some(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use crate::page_scraper::html::HtmlGetterError;
+
+    use super::{FetchOutcome, Outcome};
+
+    #[test]
+    fn outcome_of_error() {
+        assert_eq!(
+            FetchOutcome::from(&HtmlGetterError::NotHTML(Some("image/png".to_string()))),
+            FetchOutcome {
+                outcome: Outcome::NotHtml,
+                status: None,
+                content_type: Some("image/png".to_string()),
+                error: Some("not html (Content-Type: image/png)".to_string()),
+            }
+        );
+
+        let outcome = FetchOutcome::from(&HtmlGetterError::StatusCode(StatusCode::GONE));
+        assert_eq!(outcome.outcome.as_str(), "http_status");
+        assert_eq!(outcome.status, Some(410));
+
+        let outcome = FetchOutcome::from(&HtmlGetterError::TooManyRequests(
+            StatusCode::SERVICE_UNAVAILABLE,
+            None,
+        ));
+        assert_eq!(outcome.outcome, Outcome::TooManyRequests);
+        assert_eq!(outcome.status, Some(503));
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use rusqlite::Connection;
+
+use super::creation::DatabaseConnection;
+use crate::page_rank::tokenizer::page_terms;
+
+/// The occurrences of a term in a page
+#[derive(Debug, PartialEq, Eq)]
+pub struct Posting {
+    pub page_id: i64,
+    /// how often the term appears in the page
+    pub frequency: u32,
+    /// positions of the term in the terms of the page
+    pub positions: Vec<u32>,
+}
+
+/// Numbers about all indexed pages needed for scoring
+#[derive(Debug, PartialEq)]
+pub struct CorpusStats {
+    /// amount of indexed pages
+    pub documents: u64,
+    /// average amount of terms per indexed page
+    pub average_length: f64,
+}
+
+/// replaces the index entries of the page with the given terms
+///
+/// the entries are written one by one, callers that index more than one page should use a transaction
+pub fn index_page(conn: &Connection, id: i64, terms: &[String]) -> Result<usize, rusqlite::Error> {
+    let mut positions: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+
+    for (position, term) in terms.iter().enumerate() {
+        positions
+            .entry(term.as_str())
+            .or_default()
+            .push(position as u32);
+    }
+
+    conn.execute("DELETE FROM Postings WHERE page_id = ?1;", [id])?;
+
+    {
+        let mut statement = conn.prepare(
+            "INSERT INTO Postings (term, page_id, frequency, positions) VALUES (?1, ?2, ?3, ?4);",
+        )?;
+
+        for (term, positions) in &positions {
+            statement.execute((term, id, positions.len(), positions.iter().join(",")))?;
+        }
+    }
+
+    conn.execute(
+        "UPDATE Ranking SET term_count = ?1 WHERE id = ?2;",
+        (terms.len(), id),
+    )?;
+
+    Ok(positions.len())
+}
+
+/// indexes all stored pages that haven't been indexed, databases of older versions weren't indexed while crawling
+pub fn index_stored_pages(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let pages = conn
+        .prepare("SELECT id, url, content FROM Ranking WHERE content IS NOT NULL AND term_count IS NULL;")?
+        .query_map((), |row| {
+            Ok((
+                row.get::<usize, i64>(0)?,
+                row.get::<usize, String>(1)?,
+                row.get::<usize, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let transaction = conn.unchecked_transaction()?;
+
+    for (id, url, content) in &pages {
+        index_page(&transaction, *id, &page_terms(url, content))?;
+    }
+
+    transaction.commit()?;
+
+    Ok(pages.len())
+}
+
+/// returns all pages that contain the term
+pub fn get_postings(
+    conn: &DatabaseConnection,
+    term: &str,
+) -> Result<Vec<Posting>, rusqlite::Error> {
+    conn.connection
+        .prepare(
+            "SELECT page_id, frequency, positions FROM Postings WHERE term = ?1 ORDER BY page_id;",
+        )?
+        .query_map([term], |row| {
+            let positions: String = row.get(2)?;
+
+            Ok(Posting {
+                page_id: row.get(0)?,
+                frequency: row.get(1)?,
+                positions: positions
+                    .split(',')
+                    .filter_map(|position| position.parse().ok())
+                    .collect(),
+            })
+        })?
+        .collect()
+}
+
+/// returns how many terms the page was indexed with, 0 if it hasn't been indexed
+pub fn get_term_count(conn: &DatabaseConnection, id: i64) -> Result<u64, rusqlite::Error> {
+    conn.connection
+        .query_row(
+            "SELECT term_count FROM Ranking WHERE id = ?1;",
+            [id],
+            |row| row.get::<usize, Option<u64>>(0),
+        )
+        .map(|count| count.unwrap_or(0))
+}
+
+pub fn get_corpus_stats(conn: &DatabaseConnection) -> Result<CorpusStats, rusqlite::Error> {
+    conn.connection.query_row(
+        "SELECT COUNT(*), AVG(term_count) FROM Ranking WHERE term_count IS NOT NULL;",
+        (),
+        |row| {
+            Ok(CorpusStats {
+                documents: row.get(0)?,
+                average_length: row.get::<usize, Option<f64>>(1)?.unwrap_or(0.0),
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::db_manager::{
+        creation::create_default_tables,
+        helper::{gen_random_path, gen_vals},
+    };
+
+    use super::{get_corpus_stats, get_postings, get_term_count, index_page, Posting};
+
+    #[test]
+    fn stores_postings() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let terms = ["cat", "dog", "cat"].map(|term| term.to_string());
+        index_page(&conn.connection, 3, &terms).unwrap();
+        // indexing again replaces the old entries
+        index_page(&conn.connection, 3, &terms).unwrap();
+
+        let cat = get_postings(&conn, "cat").unwrap();
+        let count = get_term_count(&conn, 3).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            cat,
+            vec![Posting {
+                page_id: 3,
+                frequency: 2,
+                positions: vec![0, 2],
+            }]
+        );
+        assert_eq!(count, 3);
+    }
+
+    /// the pages stored by `gen_vals` are indexed like pages of an older database
+    #[test]
+    fn indexes_stored_pages() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let team = get_postings(&conn, "team").unwrap();
+        let stats = get_corpus_stats(&conn).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            team.iter()
+                .map(|posting| posting.page_id)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 4]
+        );
+        assert_eq!(stats.documents, 6);
+        assert!(stats.average_length > 5.0);
+    }
+}
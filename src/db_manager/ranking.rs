@@ -5,7 +5,6 @@ pub struct Ranking {
     pub url: String,
     pub content: Option<String>,
     pub links_to: Option<String>,
-    pub in_use: bool,
 }
 
 impl PartialEq for Ranking {
@@ -15,6 +14,14 @@ impl PartialEq for Ranking {
             && self.url == other.url
             && self.content == other.content
             && self.links_to == other.links_to
-            && self.in_use == other.in_use
     }
 }
+
+/// A link between two pages of the link graph
+#[derive(Debug, PartialEq, Eq)]
+pub struct GraphLink {
+    /// url of the page on the other end of the link
+    pub url: String,
+    pub anchor_text: Option<String>,
+    pub rel: Option<String>,
+}
@@ -1,8 +1,23 @@
-use std::sync::{Arc, Mutex};
-
-use crate::indexer::visit_types::ToVisit;
-
-use super::{creation::DatabaseConnection, helper::count_rows, ranking::Ranking};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use itertools::Itertools;
+use rusqlite::OptionalExtension;
+
+use crate::{
+    indexer::{frontier::Frontier, visit_types::ToVisit},
+    page_scraper::html_parser::PageFields,
+};
+
+use super::{
+    creation::DatabaseConnection,
+    helper::{escape_like, reverse_host, unix_millis},
+    lease::{Lease, CLAIMABLE},
+    ranking::{GraphLink, Ranking},
+};
 
 // returns true if all links have been visited
 pub fn is_finished(conn: &DatabaseConnection) -> Result<bool, rusqlite::Error> {
@@ -17,42 +32,246 @@ pub fn is_finished(conn: &DatabaseConnection) -> Result<bool, rusqlite::Error> {
 
 /// calculates how many pages point to this page
 pub fn calculate_links_from(conn: &DatabaseConnection, id: i64) -> Result<usize, rusqlite::Error> {
-    let url: String = conn
-        .connection
-        .prepare("SELECT url FROM Ranking WHERE id = ?1;")?
-        .query_row([id], |row| row.get(0))?;
+    conn.connection.query_row(
+        "SELECT COUNT(*) FROM Links WHERE to_id = ?1 AND from_id != to_id;",
+        [id],
+        |row| row.get(0),
+    )
+}
 
-    let mut statement = conn
-        .connection
-        .prepare(format!("SELECT * FROM Ranking WHERE links_to LIKE '%{}%';", url).as_str())?;
+/// calculates how many pages this page points to
+pub fn count_outlinks(conn: &DatabaseConnection, id: i64) -> Result<usize, rusqlite::Error> {
+    conn.connection.query_row(
+        "SELECT COUNT(*) FROM Links WHERE from_id = ?1 AND from_id != to_id;",
+        [id],
+        |row| row.get(0),
+    )
+}
 
-    count_rows(statement.query(()))
+/// returns the links that point to this page
+pub fn get_inlinks(conn: &DatabaseConnection, id: i64) -> Result<Vec<GraphLink>, rusqlite::Error> {
+    get_graph_links(
+        conn,
+        "SELECT Ranking.url, Links.anchor_text, Links.rel FROM Links JOIN Ranking ON Ranking.id = Links.from_id WHERE Links.to_id = ?1 ORDER BY Ranking.id;",
+        id,
+    )
 }
 
-// returns a new link that can be searched if new links exist
-pub fn get_new_link(conn: Arc<Mutex<DatabaseConnection>>) -> Option<ToVisit> {
-    let result: (i64, String);
+/// returns the links found on this page
+pub fn get_outlinks(conn: &DatabaseConnection, id: i64) -> Result<Vec<GraphLink>, rusqlite::Error> {
+    get_graph_links(
+        conn,
+        "SELECT Ranking.url, Links.anchor_text, Links.rel FROM Links JOIN Ranking ON Ranking.id = Links.to_id WHERE Links.from_id = ?1 ORDER BY Ranking.id;",
+        id,
+    )
+}
 
-    {
-        let lock = conn.lock().unwrap();
-        let mut statement = lock
-            .connection
-            .prepare(
-                "SELECT id, url FROM Ranking WHERE in_use = false AND visited = false LIMIT 1;",
-            )
-            .ok()?;
+fn get_graph_links(
+    conn: &DatabaseConnection,
+    query: &str,
+    id: i64,
+) -> Result<Vec<GraphLink>, rusqlite::Error> {
+    conn.connection
+        .prepare(query)?
+        .query_map([id], |row| {
+            Ok(GraphLink {
+                url: row.get(0)?,
+                anchor_text: row.get(1)?,
+                rel: row.get(2)?,
+            })
+        })?
+        .collect()
+}
+
+/// returns the ids of all pages
+pub fn get_page_ids(conn: &DatabaseConnection) -> Result<Vec<i64>, rusqlite::Error> {
+    conn.connection
+        .prepare("SELECT id FROM Ranking ORDER BY id;")?
+        .query_map((), |row| row.get(0))?
+        .collect()
+}
+
+/// returns every link of the link graph as (from_id, to_id), links of a page to itself are left out
+pub fn get_graph_edges(conn: &DatabaseConnection) -> Result<Vec<(i64, i64)>, rusqlite::Error> {
+    conn.connection
+        .prepare("SELECT from_id, to_id FROM Links WHERE from_id != to_id;")?
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect()
+}
+
+/// returns the ids of the pages this page links to
+pub fn get_outlink_ids(conn: &DatabaseConnection, id: i64) -> Result<Vec<i64>, rusqlite::Error> {
+    conn.connection
+        .prepare("SELECT to_id FROM Links WHERE from_id = ?1 AND to_id != ?1 ORDER BY to_id;")?
+        .query_map([id], |row| row.get(0))?
+        .collect()
+}
+
+/// returns the ids of at most `limit` pages that link to this page
+pub fn get_inlink_ids(
+    conn: &DatabaseConnection,
+    id: i64,
+    limit: usize,
+) -> Result<Vec<i64>, rusqlite::Error> {
+    conn.connection
+        .prepare(
+            "SELECT from_id FROM Links WHERE to_id = ?1 AND from_id != ?1 ORDER BY from_id LIMIT ?2;",
+        )?
+        .query_map((id, limit), |row| row.get(0))?
+        .collect()
+}
+
+/// returns the pageranks of the pages relative to the average page (1.0 is average)
+///
+/// pages without a computed pagerank are left out
+pub fn get_relative_pageranks(
+    conn: &DatabaseConnection,
+    ids: impl IntoIterator<Item = i64>,
+) -> Result<HashMap<i64, f64>, rusqlite::Error> {
+    // the ids are passed as one json array, a search can match more pages than sqlite allows parameters
+    conn.connection
+        .prepare(
+            "SELECT id, pagerank * (SELECT COUNT(*) FROM Ranking) FROM Ranking WHERE id IN (SELECT value FROM json_each(?1)) AND pagerank IS NOT NULL;",
+        )?
+        .query_map([format!("[{}]", ids.into_iter().join(","))], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect()
+}
+
+// returns a new link that can be searched if new links exist, the frontier decides which one
+// pages that wait for a retry are skipped until their time has come
+pub fn get_new_link(
+    conn: Arc<Mutex<DatabaseConnection>>,
+    frontier: &mut dyn Frontier,
+    lease: &Lease,
+) -> Option<ToVisit> {
+    let (id, url) = frontier.next(&conn.lock().unwrap(), lease).ok()??;
+
+    Some(ToVisit::new(&url, id, &lease.owner, conn))
+}
 
-        result = statement
-            .query_row((), |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
-            .ok()?;
+/// Which unvisited page is picked first, see `Frontier`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontierOrder {
+    /// the page that was found first
+    Oldest,
+    /// the page that was found last
+    Newest,
+    /// the page with the highest priority, the oldest one if several have the same
+    Priority,
+}
+
+/// claims the next page that may be visited now for the lease, returns its id and url
+///
+/// picking and claiming the page is a single statement, so no other crawler can claim it in between
+pub fn claim_next_unvisited(
+    conn: &DatabaseConnection,
+    order: FrontierOrder,
+    lease: &Lease,
+) -> Result<Option<(i64, String)>, rusqlite::Error> {
+    let order = match order {
+        FrontierOrder::Oldest => "id",
+        FrontierOrder::Newest => "id DESC",
+        FrontierOrder::Priority => "priority DESC, id",
+    };
+
+    conn.connection
+        .query_row(
+            &format!("UPDATE Ranking SET lease_owner = ?2, lease_until = ?3 WHERE id = (SELECT id FROM Ranking WHERE {CLAIMABLE} ORDER BY {order} LIMIT 1) RETURNING id, url;"),
+            (unix_millis(SystemTime::now()), &lease.owner, lease.until()),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+}
+
+/// claims the oldest page (returns id, url and host) that may be visited now of the first host after `host`
+/// (in alphabetical order) for the lease
+///
+/// starts over at the first host if there is none after it
+pub fn claim_next_unvisited_after_host(
+    conn: &DatabaseConnection,
+    host: &str,
+    lease: &Lease,
+) -> Result<Option<(i64, String, String)>, rusqlite::Error> {
+    let mut statement = conn.connection.prepare(&format!(
+        "UPDATE Ranking SET lease_owner = ?2, lease_until = ?3 WHERE id = (SELECT id FROM Ranking WHERE {CLAIMABLE} AND (?4 IS NULL OR COALESCE(host, '') > ?4) ORDER BY host, id LIMIT 1) RETURNING id, url, COALESCE(host, '');",
+    ))?;
+    let now = unix_millis(SystemTime::now());
+    let until = lease.until();
+
+    let mut next = |host: Option<&str>| {
+        statement
+            .query_row((now, &lease.owner, until, host), |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .optional()
+    };
+
+    match next(Some(host))? {
+        Some(page) => Ok(Some(page)),
+        None => next(None),
     }
+}
 
-    ToVisit::new(&result.1, result.0, conn).ok()
+/// returns how often fetching the page has failed
+pub fn get_retries(conn: &DatabaseConnection, id: i64) -> Result<u32, rusqlite::Error> {
+    conn.connection
+        .query_row("SELECT retries FROM Ranking WHERE id = ?1;", [id], |row| {
+            row.get(0)
+        })
+}
+
+/// returns how many links the page is away from a start url
+pub fn get_depth(conn: &DatabaseConnection, id: i64) -> Result<u32, rusqlite::Error> {
+    conn.connection
+        .query_row("SELECT depth FROM Ranking WHERE id = ?1;", [id], |row| {
+            row.get(0)
+        })
+}
+
+/// returns the id of the page with the url if it is stored
+pub fn get_page_id(conn: &DatabaseConnection, url: &str) -> Result<Option<i64>, rusqlite::Error> {
+    conn.connection
+        .query_row("SELECT id FROM Ranking WHERE url = ?1;", [url], |row| {
+            row.get(0)
+        })
+        .optional()
+}
+
+/// returns true if the url is already stored as a page
+pub fn page_exists(conn: &DatabaseConnection, url: &str) -> Result<bool, rusqlite::Error> {
+    get_page_id(conn, url).map(|id| id.is_some())
+}
+
+/// returns the amount of stored pages, visited or not
+pub fn count_pages(conn: &DatabaseConnection) -> Result<usize, rusqlite::Error> {
+    conn.connection
+        .query_row("SELECT COUNT(*) FROM Ranking;", (), |row| row.get(0))
+}
+
+/// returns the amount of stored pages of the domain and its subdomains, visited or not
+pub fn count_domain_pages(
+    conn: &DatabaseConnection,
+    domain: &str,
+) -> Result<usize, rusqlite::Error> {
+    // `com.example.` and `com.example.www.` are in the range up to `com.example/` (`/` follows `.`), `com.examples.` isn't
+    let start = reverse_host(domain);
+    let end = format!("{}/", start.trim_end_matches('.'));
+
+    conn.connection.query_row(
+        "SELECT COUNT(*) FROM Ranking WHERE reversed_host >= ?1 AND reversed_host < ?2;",
+        [start, end],
+        |row| row.get(0),
+    )
 }
 
 // returns the values stored in the database
+#[cfg(test)]
 pub fn get_values(conn: &DatabaseConnection) -> Result<Vec<Ranking>, rusqlite::Error> {
-    let mut statement = conn.connection.prepare("SELECT * FROM Ranking;")?;
+    let mut statement = conn
+        .connection
+        .prepare("SELECT id, visited, url, content, links_to FROM Ranking;")?;
 
     let results = statement.query_map((), |row| {
         Ok(Ranking {
@@ -61,7 +280,6 @@ pub fn get_values(conn: &DatabaseConnection) -> Result<Vec<Ranking>, rusqlite::E
             url: row.get(2)?,
             content: row.get(3)?,
             links_to: row.get(4)?,
-            in_use: row.get::<usize, i64>(5)? == 1,
         })
     })?;
 
@@ -74,46 +292,96 @@ pub fn get_values(conn: &DatabaseConnection) -> Result<Vec<Ranking>, rusqlite::E
     Ok(output)
 }
 
-/// finds all database entries witch include the search value in the url or the content
-pub fn find(
-    conn: &DatabaseConnection,
-    search_value: &str,
-    amount: u32,
-) -> Result<Vec<Ranking>, rusqlite::Error> {
-    let keywords: Vec<&str> = search_value.split(" ").collect();
-    let mut url_search_statement = String::new();
-    let mut content_search_statement = String::new();
-
-    for keyword in keywords {
-        url_search_statement.push_str(&format!(" url LIKE '%{keyword}%' OR "));
-        content_search_statement.push_str(&format!(" content LIKE '%{keyword}%' OR "));
-    }
+/// finds the ids of all pages whose url, title or visible text match the fts5 query (see `phrase_query`)
+///
+/// there is no limit, the results are ranked and cut by `rank_pages`
+pub fn find(conn: &DatabaseConnection, fts_query: &str) -> Result<Vec<i64>, rusqlite::Error> {
+    conn.connection
+        .prepare("SELECT rowid FROM PageText WHERE PageText MATCH ?1 ORDER BY rowid;")?
+        .query_map([fts_query], |row| row.get(0))?
+        .collect()
+}
 
-    url_search_statement.push_str(" FALSE ");
-    content_search_statement.push_str(" FALSE ");
+/// returns the ids of all pages with content
+pub fn get_indexed_ids(conn: &DatabaseConnection) -> Result<Vec<i64>, rusqlite::Error> {
+    conn.connection
+        .prepare("SELECT id FROM Ranking WHERE content IS NOT NULL ORDER BY id;")?
+        .query_map((), |row| row.get(0))?
+        .collect()
+}
 
-    let mut statement = conn.connection.prepare(&format!(
-        "SELECT * FROM Ranking WHERE {url_search_statement} OR {content_search_statement} LIMIT {amount};"
-    ))?;
+/// finds the pages with content whose url contains the fragment (ignoring ascii case), returns their ids and urls
+pub fn find_by_url(
+    conn: &DatabaseConnection,
+    fragment: &str,
+) -> Result<Vec<(i64, String)>, rusqlite::Error> {
+    conn.connection
+        .prepare(
+            "SELECT id, url FROM Ranking WHERE content IS NOT NULL AND url LIKE ?1 ESCAPE '\\' ORDER BY id;",
+        )?
+        .query_map([format!("%{}%", escape_like(fragment))], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect()
+}
 
-    let results = statement.query_map((), |row| {
-        Ok(Ranking {
-            id: row.get(0)?,
-            visited: row.get::<usize, i64>(1)? == 1,
-            url: row.get(2)?,
-            content: row.get(3)?,
-            links_to: row.get(4)?,
-            in_use: row.get::<usize, i64>(5)? == 1,
-        })
-    })?;
+/// finds the pages written in the language, `de` also finds `de-ch`
+pub fn find_by_lang(conn: &DatabaseConnection, lang: &str) -> Result<Vec<i64>, rusqlite::Error> {
+    let lang = lang.to_lowercase();
 
-    let mut output = vec![];
+    conn.connection
+        .prepare(
+            "SELECT id FROM Ranking WHERE content IS NOT NULL AND (lang = ?1 OR lang LIKE ?2 ESCAPE '\\') ORDER BY id;",
+        )?
+        .query_map((&lang, format!("{}-%", escape_like(&lang))), |row| row.get(0))?
+        .collect()
+}
 
-    for result in results {
-        output.push(result?);
-    }
+/// returns the extracted fields of the page, see `set_page_text`
+pub fn get_page_fields(conn: &DatabaseConnection, id: i64) -> Result<PageFields, rusqlite::Error> {
+    conn.connection.query_row(
+        "SELECT title, description, keywords, headings, text, lang FROM Ranking WHERE id = ?1;",
+        [id],
+        |row| {
+            let list = |index: usize, separator: &str| -> Result<Vec<String>, rusqlite::Error> {
+                Ok(row
+                    .get::<usize, Option<String>>(index)?
+                    .map(|list| {
+                        list.split(separator)
+                            .filter(|item| !item.is_empty())
+                            .map(|item| item.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default())
+            };
+
+            Ok(PageFields {
+                title: row.get(0)?,
+                description: row.get(1)?,
+                keywords: list(2, ", ")?,
+                headings: list(3, "\n")?,
+                text: row.get::<usize, Option<String>>(4)?.unwrap_or_default(),
+                lang: row.get(5)?,
+            })
+        },
+    )
+}
 
-    Ok(output)
+/// returns the stored values of the page
+pub fn get_page(conn: &DatabaseConnection, id: i64) -> Result<Ranking, rusqlite::Error> {
+    conn.connection.query_row(
+        "SELECT id, visited, url, content, links_to FROM Ranking WHERE id = ?1;",
+        [id],
+        |row| {
+            Ok(Ranking {
+                id: row.get(0)?,
+                visited: row.get::<usize, i64>(1)? == 1,
+                url: row.get(2)?,
+                content: row.get(3)?,
+                links_to: row.get(4)?,
+            })
+        },
+    )
 }
 
 #[cfg(test)]
@@ -121,14 +389,22 @@ pub mod tests {
     use std::{
         fs,
         sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
     };
 
     use crate::db_manager::{
-        creation::create_default_tables,
-        helper::{gen_random_path, gen_vals},
-        ranking::Ranking,
-        selecting::{calculate_links_from, find, get_new_link, get_values},
+        creation::{create_default_tables, migrate_links, unvisited_page},
+        full_text::phrase_query,
+        helper::{gen_random_path, gen_vals, unix_millis},
+        lease::Lease,
+        ranking::{GraphLink, Ranking},
+        selecting::{
+            calculate_links_from, count_domain_pages, count_outlinks, find, find_by_lang,
+            find_by_url, get_indexed_ids, get_inlinks, get_new_link, get_outlinks, get_page,
+            get_values,
+        },
     };
+    use crate::indexer::frontier::BreadthFirst;
 
     use super::is_finished;
 
@@ -153,6 +429,8 @@ pub mod tests {
             .unwrap();
         prep.execute(("l.ch", "help.ch:::google.ch")).unwrap();
 
+        migrate_links(&conn.connection).unwrap();
+
         let test_result1 = calculate_links_from(&conn, 2).unwrap();
 
         let test_result2 = calculate_links_from(&conn, 1).unwrap();
@@ -164,18 +442,89 @@ pub mod tests {
         assert_eq!(test_result2, 1);
     }
 
+    /// a url that is a prefix of another url isn't counted as a link to the other page
+    #[test]
+    fn counts_only_exact_links() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        let mut prep = conn
+            .connection
+            .prepare("INSERT INTO Ranking (url, links_to) VALUES (?1, ?2)")
+            .unwrap();
+
+        prep.execute(("http://a.com", "http://a.com.evil/"))
+            .unwrap();
+        prep.execute(("http://a.com.evil/", "")).unwrap();
+        prep.execute(("http://b.com", "http://a.com.evil/"))
+            .unwrap();
+
+        migrate_links(&conn.connection).unwrap();
+
+        let to_a = calculate_links_from(&conn, 1).unwrap();
+        let to_evil = calculate_links_from(&conn, 2).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(to_a, 0);
+        assert_eq!(to_evil, 2);
+    }
+
+    #[test]
+    fn graph_queries() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let inlinks = get_inlinks(&conn, 2).unwrap();
+        let outlinks = get_outlinks(&conn, 6).unwrap();
+        let outlink_count = count_outlinks(&conn, 2).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        // help.ch is linked by p.ch, ep.ch and lp.ch
+        assert_eq!(
+            inlinks
+                .iter()
+                .map(|link| link.url.as_str())
+                .collect::<Vec<_>>(),
+            vec!["p.ch", "ep.ch", "lp.ch"]
+        );
+        assert_eq!(
+            outlinks,
+            vec![
+                GraphLink {
+                    url: "test.ch".to_string(),
+                    anchor_text: None,
+                    rel: None,
+                },
+                GraphLink {
+                    url: "lp.ch".to_string(),
+                    anchor_text: None,
+                    rel: None,
+                },
+            ]
+        );
+        // team-crystal.ch and google.ch were never stored as pages
+        assert_eq!(outlink_count, 1);
+    }
+
     #[test]
     fn get_new_link_test() {
         let path = gen_random_path();
         let conn = create_default_tables(path.to_str().unwrap()).unwrap();
         let conn = Arc::new(Mutex::new(conn));
+        let lease = Lease::new(Duration::from_secs(60));
+        // leased by another crawler
+        let leased = Some(unix_millis(SystemTime::now() + Duration::from_secs(60)));
         {
             // fill db with values
             let prep = conn.lock().unwrap();
             let mut statement = prep
                 .connection
                 .prepare(
-                    "INSERT INTO Ranking (url, links_to, in_use, visited) VALUES (?1, ?2, ?3, ?4)",
+                    "INSERT INTO Ranking (url, links_to, lease_owner, lease_until, visited) VALUES (?1, ?2, 'other', ?3, ?4)",
                 )
                 .unwrap();
 
@@ -183,7 +532,7 @@ pub mod tests {
                 .execute((
                     "test.ch",
                     "team-crystal.ch:::google.ch:::example.com",
-                    true,
+                    leased,
                     false,
                 ))
                 .unwrap();
@@ -191,24 +540,76 @@ pub mod tests {
                 .execute((
                     "help.ch",
                     "team-crystal.ch:::google.ch:::test.ch",
-                    false,
+                    None::<i64>,
                     false,
                 ))
                 .unwrap();
             statement
-                .execute(("lp.ch", "help.ch", false, true))
+                .execute(("lp.ch", "help.ch", None::<i64>, true))
                 .unwrap();
             statement
-                .execute(("ep.ch", "team-crystal.ch:::help.ch", false, false))
+                .execute(("ep.ch", "team-crystal.ch:::help.ch", None::<i64>, false))
                 .unwrap();
             statement
-                .execute(("p.ch", "help.ch:::google.ch", true, true))
+                .execute(("p.ch", "help.ch:::google.ch", leased, true))
                 .unwrap();
         }
 
-        let link = get_new_link(conn).unwrap();
+        let link = get_new_link(Arc::clone(&conn), &mut BreadthFirst, &lease).unwrap();
         assert_eq!(link.url, "help.ch");
+        assert_eq!(link.owner, lease.owner);
+
+        // the lease of a crawler that was killed expires
+        conn.lock()
+            .unwrap()
+            .connection
+            .execute(
+                "UPDATE Ranking SET lease_until = 0 WHERE url = 'test.ch';",
+                (),
+            )
+            .unwrap();
+        let expired = get_new_link(Arc::clone(&conn), &mut BreadthFirst, &lease).unwrap();
+        assert_eq!(expired.url, "test.ch");
+
         // had to drop here so that the database entry can be chanched before the file is deleted
+        drop(link);
+        drop(expired);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// pages that wait for a retry aren't returned before their time
+    #[test]
+    fn get_new_link_waits_for_retry() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        {
+            let mut statement = conn
+                .connection
+                .prepare("INSERT INTO Ranking (url, retry_at) VALUES (?1, ?2)")
+                .unwrap();
+
+            statement
+                .execute((
+                    "later.ch",
+                    unix_millis(SystemTime::now() + Duration::from_secs(60)),
+                ))
+                .unwrap();
+            statement
+                .execute(("now.ch", unix_millis(SystemTime::now())))
+                .unwrap();
+        }
+
+        let conn = Arc::new(Mutex::new(conn));
+
+        let lease = Lease::new(Duration::from_secs(60));
+
+        let link = get_new_link(Arc::clone(&conn), &mut BreadthFirst, &lease).unwrap();
+        assert_eq!(link.url, "now.ch");
+
+        // the page that is leased and the page that waits aren't returned
+        assert!(get_new_link(Arc::clone(&conn), &mut BreadthFirst, &lease).is_none());
+
         drop(link);
         fs::remove_file(path).unwrap();
     }
@@ -222,27 +623,21 @@ pub mod tests {
         // fill db with values
         let mut prep = conn
             .connection
-            .prepare("INSERT INTO Ranking (url, links_to, in_use, visited) VALUES (?1, ?2, ?3, ?4)")
+            .prepare("INSERT INTO Ranking (url, links_to, visited) VALUES (?1, ?2, ?3)")
             .unwrap();
 
         prep.execute((
             "test.ch",
             "team-crystal.ch:::google.ch:::example.com",
-            true,
-            false,
-        ))
-        .unwrap();
-        prep.execute((
-            "help.ch",
-            "team-crystal.ch:::google.ch:::test.ch",
-            false,
             false,
         ))
         .unwrap();
-        prep.execute(("p.ch", "help.ch", false, true)).unwrap();
-        prep.execute(("ep.ch", "team-crystal.ch:::help.ch", false, false))
+        prep.execute(("help.ch", "team-crystal.ch:::google.ch:::test.ch", false))
             .unwrap();
-        prep.execute(("lp.ch", "help.ch:::google.ch", true, true))
+        prep.execute(("p.ch", "help.ch", true)).unwrap();
+        prep.execute(("ep.ch", "team-crystal.ch:::help.ch", false))
+            .unwrap();
+        prep.execute(("lp.ch", "help.ch:::google.ch", true))
             .unwrap();
         fs::remove_file(path).unwrap();
 
@@ -260,27 +655,17 @@ pub mod tests {
         // fill db with values
         let mut prep = conn
             .connection
-            .prepare("INSERT INTO Ranking (url, links_to, in_use, visited) VALUES (?1, ?2, ?3, ?4)")
+            .prepare("INSERT INTO Ranking (url, links_to, visited) VALUES (?1, ?2, ?3)")
             .unwrap();
 
-        prep.execute((
-            "test.ch",
-            "team-crystal.ch:::google.ch:::example.com",
-            false,
-            true,
-        ))
-        .unwrap();
-        prep.execute((
-            "help.ch",
-            "team-crystal.ch:::google.ch:::test.ch",
-            false,
-            true,
-        ))
-        .unwrap();
-        prep.execute(("p.ch", "help.ch", false, true)).unwrap();
-        prep.execute(("ep.ch", "team-crystal.ch:::help.ch", false, true))
+        prep.execute(("test.ch", "team-crystal.ch:::google.ch:::example.com", true))
+            .unwrap();
+        prep.execute(("help.ch", "team-crystal.ch:::google.ch:::test.ch", true))
+            .unwrap();
+        prep.execute(("p.ch", "help.ch", true)).unwrap();
+        prep.execute(("ep.ch", "team-crystal.ch:::help.ch", true))
             .unwrap();
-        prep.execute(("lp.ch", "help.ch:::google.ch", false, true))
+        prep.execute(("lp.ch", "help.ch:::google.ch", true))
             .unwrap();
 
         fs::remove_file(path).unwrap();
@@ -296,17 +681,89 @@ pub mod tests {
 
         gen_vals(&conn);
 
-        let test_results = find(&conn, "crystal", 10).unwrap();
+        let test_results = find(&conn, "\"crystal\"").unwrap();
 
         assert_eq!(test_results.len(), 3);
 
-        assert_eq!(test_results[0].id, 1);
-        assert_eq!(test_results[1].id, 2);
-        assert_eq!(test_results[2].id, 4);
+        assert_eq!(test_results[0], 1);
+        assert_eq!(test_results[1], 2);
+        assert_eq!(test_results[2], 4);
+
+        let page = get_page(&conn, test_results[2]).unwrap();
+        assert_eq!(page.url, "ep.ch");
 
         fs::remove_file(path).unwrap();
     }
 
+    /// only whole words match, case and accents are ignored
+    #[test]
+    fn search_matches_words() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let est = find(&conn, &phrase_query(None, "est").unwrap()).unwrap();
+        let laborum = find(&conn, &phrase_query(None, "LABÖRUM").unwrap()).unwrap();
+        let phrase = find(&conn, &phrase_query(None, "help ch").unwrap()).unwrap();
+        let in_url = find(&conn, &phrase_query(Some("url"), "ch").unwrap()).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(est, vec![6]);
+        assert_eq!(laborum.len(), 1);
+        // p.ch, ep.ch and lp.ch contain help.ch, help.ch only in its url
+        assert_eq!(phrase, vec![2, 3, 4, 5]);
+        assert_eq!(in_url.len(), 5);
+    }
+
+    /// `%` and `_` in the url fragment only match themselves
+    #[test]
+    fn search_by_url() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        conn.connection
+            .execute(
+                "INSERT INTO Ranking (url, content) VALUES ('https://a.ch/100%_real', ''), ('https://a.ch/1000real', '');",
+                (),
+            )
+            .unwrap();
+
+        let percent = find_by_url(&conn, "100%_").unwrap();
+        let upper = find_by_url(&conn, "HELP").unwrap();
+        let unvisited = find_by_url(&conn, "new.ch").unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(percent, vec![(7, "https://a.ch/100%_real".to_string())]);
+        assert_eq!(upper, vec![(2, "help.ch".to_string())]);
+        assert!(unvisited.is_empty());
+    }
+
+    #[test]
+    fn search_by_lang() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        conn.connection
+            .execute(
+                "INSERT INTO Ranking (url, content, lang) VALUES ('a.ch', '', 'de'), ('b.ch', '', 'de-ch'), ('c.ch', '', 'dex'), ('d.ch', '', 'en');",
+                (),
+            )
+            .unwrap();
+
+        let german = find_by_lang(&conn, "DE").unwrap();
+        let all = get_indexed_ids(&conn).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(german, vec![1, 2]);
+        assert_eq!(all, vec![1, 2, 3, 4]);
+    }
+
     /// tests if get_values gets all values
     #[test]
     fn get_values_test() {
@@ -322,11 +779,10 @@ pub mod tests {
 
         let test_vals = vals.iter();
 
-        let correct_vals = vec![
+        let correct_vals = [
             Ranking {
                 id: 1,
                 content: Some("team-crystal.ch:::google.ch:::example.com".to_string()),
-                in_use: false,
                 visited: true,
                 links_to: Some("team-crystal.ch:::google.ch:::example.com".to_string()),
                 url: "test.ch".to_string(),
@@ -336,7 +792,6 @@ pub mod tests {
                 url: "help.ch".to_string(),
                 content: Some("team-crystal.ch:::google.ch:::test.ch".to_string()),
                 links_to: Some("team-crystal.ch:::google.ch:::test.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -344,7 +799,6 @@ pub mod tests {
                 url: "p.ch".to_string(),
                 content: Some("help.ch".to_string()),
                 links_to: Some("help.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -352,7 +806,6 @@ pub mod tests {
                 url: "ep.ch".to_string(),
                 content: Some("team-crystal.ch::help.ch".to_string()),
                 links_to: Some("team-crystal.ch:::help.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -360,7 +813,6 @@ pub mod tests {
                 url: "lp.ch".to_string(),
                 content: Some("help.ch:::google.ch".to_string()),
                 links_to: Some("help.ch:::google.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
             Ranking {
@@ -375,7 +827,6 @@ pub mod tests {
             Eu nisi eu quis anim tempor fugiat deserunt est deserunt nulla ad do. Ipsum pariatur enim eiusmod minim cupidatat esse excepteur nostrud proident officia Lorem laboris esse. Excepteur reprehenderit anim duis exercitation labore nisi aliquip duis do. Id eiusmod dolore ex nulla nulla.
             </h1></body></html>".to_string()),
                 links_to: Some("test.ch:::lp.ch".to_string()),
-                in_use: false,
                 visited: true,
             },
         ];
@@ -389,4 +840,47 @@ pub mod tests {
         fs::remove_file(path).unwrap();
         assert!(test_vals.eq(iter_correct_vals));
     }
+
+    /// the pages of a domain are counted with the index, also in databases of older versions
+    #[test]
+    fn counts_domain_pages() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        // like a database of a version without reversed hosts
+        conn.connection
+            .execute_batch(
+                "DROP INDEX ranking_reversed_host;
+ALTER TABLE Ranking DROP COLUMN reversed_host;
+INSERT INTO Ranking (url) VALUES ('http://ethz.ch/');",
+            )
+            .unwrap();
+        drop(conn);
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        for url in [
+            "http://www.ethz.ch/",
+            "https://people.ethz.ch:8080/a",
+            "http://notethz.ch/",
+            "http://ethz.chat/",
+        ] {
+            unvisited_page(&conn, url, false).unwrap();
+        }
+
+        let counts = ["ethz.ch", "www.ethz.ch", "ch", "hz.ch", "uzh.ch"]
+            .map(|domain| count_domain_pages(&conn, domain).unwrap());
+        let plan = conn
+            .connection
+            .query_row(
+                "EXPLAIN QUERY PLAN SELECT COUNT(*) FROM Ranking WHERE reversed_host >= 'ch.ethz.' AND reversed_host < 'ch.ethz/';",
+                (),
+                |row| row.get::<usize, String>(3),
+            )
+            .unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(counts, [3, 1, 4, 0, 0]);
+        assert!(plan.contains("ranking_reversed_host"), "{plan}");
+    }
 }
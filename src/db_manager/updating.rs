@@ -1,51 +1,200 @@
-use rusqlite::Connection;
+use std::time::SystemTime;
 
-use super::creation::DatabaseConnection;
+use rusqlite::OptionalExtension;
 
-// # sets all in use to false
-// used when opening a new database that maybe hasn't been closed correctely
-pub fn reset_in_use(conn: &Connection) -> Result<usize, rusqlite::Error> {
-    conn.execute("UPDATE Ranking SET in_use = false;", ())
-}
+use crate::page_scraper::html_parser::PageFields;
+
+use super::{
+    creation::DatabaseConnection,
+    full_text::set_page_text,
+    helper::unix_millis,
+    outcome::{FetchOutcome, Outcome},
+};
 
-/// updates the database entry for the page to visited and fills in the required data
+/// updates the database entry for the page to visited and fills in the required data,
+/// the `fields` are the ones of the content (see `Document::fields`)
+///
+/// returns false without changing anything if the page isn't leased by `owner` (anymore)
 pub fn update_to_visited(
     conn: &DatabaseConnection,
     id: i64,
+    owner: &str,
     content: &str,
     links_to: Vec<&str>,
+    fields: &PageFields,
     verbose: bool,
-) -> Result<i64, rusqlite::Error> {
-    conn.connection.execute(
-        "UPDATE Ranking SET visited = true, content = ?1, links_to = ?2 WHERE id = ?3;",
-        [content, &links_to.join(":::"), &id.to_string()],
+) -> Result<bool, rusqlite::Error> {
+    let updated = conn.connection.execute(
+        "UPDATE Ranking SET visited = true, content = ?1, links_to = ?2 WHERE id = ?3 AND lease_owner = ?4;",
+        (content, links_to.join(":::"), id, owner),
     )?;
 
+    // the lease expired and another crawler claimed the page
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    set_page_text(&conn.connection, id, fields)?;
+
     if verbose {
         println!("Crawled webpage with id: {}", id);
     }
 
+    Ok(true)
+}
+
+/// marks the page as visited without content, the reason is stored in the outcome columns
+///
+/// returns false without changing anything if the page isn't leased by `owner` (anymore)
+pub fn update_to_failed(
+    conn: &DatabaseConnection,
+    id: i64,
+    owner: &str,
+    outcome: &FetchOutcome,
+    verbose: bool,
+) -> Result<bool, rusqlite::Error> {
+    let updated = conn.connection.execute(
+        "UPDATE Ranking SET visited = true, content = NULL, links_to = NULL WHERE id = ?1 AND lease_owner = ?2;",
+        (id, owner),
+    )?;
+
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    set_outcome(conn, id, outcome)?;
+
+    if verbose {
+        println!(
+            "Couldn't crawl webpage with id {}: {}",
+            id,
+            outcome.error.as_deref().unwrap_or(outcome.outcome.as_str())
+        );
+    }
+
+    Ok(true)
+}
+
+/// stores what happened when the page was visited (last)
+pub fn set_outcome(
+    conn: &DatabaseConnection,
+    id: i64,
+    outcome: &FetchOutcome,
+) -> Result<i64, rusqlite::Error> {
+    conn.connection.execute(
+        "UPDATE Ranking SET outcome = ?1, status = ?2, content_type = ?3, error = ?4 WHERE id = ?5;",
+        (
+            outcome.outcome.as_str(),
+            outcome.status,
+            &outcome.content_type,
+            &outcome.error,
+            id,
+        ),
+    )?;
+
     Ok(id)
 }
 
-pub fn set_in_use(conn: &DatabaseConnection, id: i64, state: bool) -> Result<i64, rusqlite::Error> {
-    let mut statement = conn
+/// stores the pageranks of all pages in one transaction
+pub fn update_pageranks(
+    conn: &DatabaseConnection,
+    pageranks: &[(i64, f64)],
+) -> Result<usize, rusqlite::Error> {
+    let transaction = conn.connection.unchecked_transaction()?;
+
+    let mut updated = 0;
+
+    {
+        let mut statement =
+            transaction.prepare("UPDATE Ranking SET pagerank = ?1 WHERE id = ?2;")?;
+
+        for (id, pagerank) in pageranks {
+            updated += statement.execute((pagerank, id))?;
+        }
+    }
+
+    transaction.commit()?;
+
+    Ok(updated)
+}
+
+/// marks the page as visited and points it at the page it redirects to
+///
+/// returns false without changing anything if the page isn't leased by `owner` (anymore)
+pub fn update_to_redirected(
+    conn: &DatabaseConnection,
+    id: i64,
+    owner: &str,
+    final_url: &str,
+    verbose: bool,
+) -> Result<bool, rusqlite::Error> {
+    let updated = conn.connection.execute(
+        "UPDATE Ranking SET visited = true, content = NULL, links_to = ?1, final_url = ?1, outcome = ?2 WHERE id = ?3 AND lease_owner = ?4;",
+        (final_url, Outcome::Redirect.as_str(), id, owner),
+    )?;
+
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    if verbose {
+        println!("Page with id {} redirects to {}", id, final_url);
+    }
+
+    Ok(true)
+}
+
+/// counts a failed attempt to fetch the page, it won't be picked again before `retry_at`
+///
+/// returns how often fetching the page has failed,
+/// `None` without changing anything if the page isn't leased by `owner` (anymore)
+pub fn schedule_retry(
+    conn: &DatabaseConnection,
+    id: i64,
+    owner: &str,
+    retry_at: SystemTime,
+    verbose: bool,
+) -> Result<Option<u32>, rusqlite::Error> {
+    let retries = match conn
         .connection
-        .prepare("UPDATE Ranking SET in_use = ?1 WHERE id = ?2;")?;
+        .query_row(
+            "UPDATE Ranking SET retries = retries + 1, retry_at = ?1 WHERE id = ?2 AND lease_owner = ?3 RETURNING retries;",
+            (unix_millis(retry_at), id, owner),
+            |row| row.get(0),
+        )
+        .optional()?
+    {
+        Some(retries) => retries,
+        None => return Ok(None),
+    };
 
-    statement.execute((state, id))?;
+    if verbose {
+        println!(
+            "Retrying page with id {} later ({} failed attempts)",
+            id, retries
+        );
+    }
 
-    Ok(id)
+    Ok(Some(retries))
 }
 
 #[cfg(test)]
 pub mod tests {
-    use std::fs;
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
 
-    use crate::db_manager::{
-        creation::create_default_tables,
-        helper::{count_rows, gen_random_path, gen_vals},
-        updating::{reset_in_use, set_in_use, update_to_visited},
+    use crate::{
+        db_manager::{
+            creation::create_default_tables,
+            helper::{gen_random_path, gen_vals, lease_page},
+            lease::{claim_url, Lease},
+            outcome::{FetchOutcome, Outcome},
+            selecting::get_retries,
+            updating::{schedule_retry, update_to_failed, update_to_redirected, update_to_visited},
+        },
+        page_scraper::html_parser::Document,
     };
 
     #[test]
@@ -91,10 +240,21 @@ pub mod tests {
                 </html>
         "#;
 
-        let links_to = vec!["ep.ch", "lp.ch"].join(":::");
+        let links_to = ["ep.ch", "lp.ch"].join(":::");
 
         // update value
-        update_to_visited(&conn, 1, content, vec!["ep.ch", "lp.ch"], false).unwrap();
+        let fields = Document::parse_str(content).fields();
+        lease_page(&conn, 1, "crawler");
+        update_to_visited(
+            &conn,
+            1,
+            "crawler",
+            content,
+            vec!["ep.ch", "lp.ch"],
+            &fields,
+            false,
+        )
+        .unwrap();
 
         // test if values have been updatet
         let row: (i64, String, String) = conn
@@ -114,66 +274,170 @@ pub mod tests {
     }
 
     #[test]
-    fn clear_in_use() {
+    fn redirected_points_to_target() {
         let path = gen_random_path();
 
         let conn = create_default_tables(path.to_str().unwrap()).unwrap();
 
-        // fill db with values
-        let mut prep = conn
+        gen_vals(&conn);
+
+        lease_page(&conn, 3, "crawler");
+        update_to_redirected(&conn, 3, "crawler", "https://www.p.ch/", false).unwrap();
+
+        let row: (bool, String, String) = conn
             .connection
-            .prepare("INSERT INTO Ranking (url, links_to, in_use) VALUES (?1, ?2, true)")
+            .prepare("SELECT visited, links_to, final_url FROM Ranking WHERE id = 3;")
+            .unwrap()
+            .query_row((), |row| {
+                Ok((row.get_unwrap(0), row.get_unwrap(1), row.get_unwrap(2)))
+            })
             .unwrap();
 
-        prep.execute(("test.ch", "team-crystal.ch:::google.ch:::example.com"))
-            .unwrap();
-        prep.execute(("help.ch", "team-crystal.ch:::google.ch:::test.ch"))
-            .unwrap();
-        prep.execute(("p.ch", "help.ch")).unwrap();
-        prep.execute(("ep.ch", "team-crystal.ch:::help.ch"))
-            .unwrap();
-        prep.execute(("lp.ch", "help.ch:::google.ch")).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(row.0);
+        assert_eq!(row.1, "https://www.p.ch/");
+        assert_eq!(row.2, "https://www.p.ch/");
+    }
+
+    /// failed pages don't get content, the reason is stored separately
+    #[test]
+    fn failed_stores_outcome() {
+        let path = gen_random_path();
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
 
-        reset_in_use(&conn.connection).unwrap();
+        gen_vals(&conn);
 
-        // check if db are correct
-        let mut statement = conn
+        lease_page(&conn, 4, "crawler");
+        update_to_failed(
+            &conn,
+            4,
+            "crawler",
+            &FetchOutcome {
+                status: Some(404),
+                error: Some("unexpected status code 404 Not Found".to_string()),
+                ..FetchOutcome::new(Outcome::HttpStatus)
+            },
+            false,
+        )
+        .unwrap();
+
+        type Row = (
+            bool,
+            Option<String>,
+            Option<String>,
+            String,
+            Option<u16>,
+            Option<String>,
+        );
+
+        let row: Row = conn
             .connection
-            .prepare("SELECT * FROM Ranking WHERE in_use = true;")
+            .prepare(
+                "SELECT visited, content, links_to, outcome, status, error FROM Ranking WHERE id = 4;",
+            )
+            .unwrap()
+            .query_row((), |row| {
+                Ok((
+                    row.get_unwrap(0),
+                    row.get_unwrap(1),
+                    row.get_unwrap(2),
+                    row.get_unwrap(3),
+                    row.get_unwrap(4),
+                    row.get_unwrap(5),
+                ))
+            })
             .unwrap();
 
-        let rows = statement.query(());
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            row,
+            (
+                true,
+                None,
+                None,
+                "http_status".to_string(),
+                Some(404),
+                Some("unexpected status code 404 Not Found".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn counts_retries() {
+        let path = gen_random_path();
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        lease_page(&conn, 2, "crawler");
 
-        let count = count_rows(rows).unwrap();
+        let before = get_retries(&conn, 2).unwrap();
+        let first = schedule_retry(&conn, 2, "crawler", SystemTime::now(), false).unwrap();
+        let second = schedule_retry(&conn, 2, "crawler", SystemTime::now(), false).unwrap();
+        let after = get_retries(&conn, 2).unwrap();
 
         fs::remove_file(path).unwrap();
 
-        assert_eq!(count, 0);
+        assert_eq!(before, 0);
+        assert_eq!(first, Some(1));
+        assert_eq!(second, Some(2));
+        assert_eq!(after, 2);
     }
 
+    /// a crawler whose lease expired doesn't overwrite the page another crawler claimed in the meantime
     #[test]
-    fn set_in_use_test() {
+    fn only_owner_updates() {
         let path = gen_random_path();
 
         let conn = create_default_tables(path.to_str().unwrap()).unwrap();
 
-        gen_vals(&conn);
+        conn.connection
+            .execute("INSERT INTO Ranking (url) VALUES ('a.ch');", ())
+            .unwrap();
+
+        let expired = Lease::new(Duration::ZERO);
+        let owner = Lease::new(Duration::from_secs(60));
+
+        claim_url(&conn, "a.ch", &expired).unwrap();
+        claim_url(&conn, "a.ch", &owner).unwrap();
 
-        assert!(!conn
+        let fields = Document::parse_str("<p>old</p>").fields();
+        let outcome = FetchOutcome::new(Outcome::Timeout);
+
+        let stale = (
+            update_to_visited(&conn, 1, &expired.owner, "old", vec![], &fields, false).unwrap(),
+            update_to_failed(&conn, 1, &expired.owner, &outcome, false).unwrap(),
+            update_to_redirected(&conn, 1, &expired.owner, "b.ch", false).unwrap(),
+            schedule_retry(&conn, 1, &expired.owner, SystemTime::now(), false).unwrap(),
+        );
+        let unchanged: (bool, Option<String>, u32) = conn
             .connection
-            .prepare("SELECT in_use FROM Ranking WHERE id = 3")
-            .unwrap()
-            .query_row((), |row| Ok(row.get::<usize, bool>(0).unwrap()))
-            .unwrap());
+            .query_row(
+                "SELECT visited, content, retries FROM Ranking WHERE id = 1;",
+                (),
+                |row| Ok((row.get_unwrap(0), row.get_unwrap(1), row.get_unwrap(2))),
+            )
+            .unwrap();
 
-        set_in_use(&conn, 3, true).unwrap();
+        let fields = Document::parse_str("<p>new</p>").fields();
+        let stored =
+            update_to_visited(&conn, 1, &owner.owner, "new", vec![], &fields, false).unwrap();
+        let content: String = conn
+            .connection
+            .query_row("SELECT content FROM Ranking WHERE id = 1;", (), |row| {
+                row.get(0)
+            })
+            .unwrap();
 
         fs::remove_file(path).unwrap();
-        assert!(conn
-            .connection
-            .prepare("SELECT in_use FROM Ranking WHERE id = 3")
-            .unwrap()
-            .query_row((), |row| Ok(row.get::<usize, bool>(0).unwrap()))
-            .unwrap());
+
+        assert_eq!(stale, (false, false, false, None));
+        assert_eq!(unchanged, (false, None, 0));
+        assert!(stored);
+        assert_eq!(content, "new");
     }
 }
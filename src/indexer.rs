@@ -1,2 +1,9 @@
+pub mod config;
+pub mod control;
+pub mod frontier;
 pub mod loops;
+pub mod retry;
+pub mod scheduler;
+pub mod scope;
 pub mod visit_types;
+pub mod writer;
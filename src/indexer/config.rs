@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use crate::page_scraper::fetcher::FetchLimits;
+
+use super::{frontier::FrontierStrategy, scope::CrawlScope};
+
+/// Settings of a crawl
+#[derive(Debug, Clone)]
+pub struct CrawlConfig {
+    /// amount of pages that are fetched at the same time
+    pub concurrency: usize,
+    /// minimum time between two requests to the same host
+    pub min_delay: Duration,
+    /// maximum amount of requests that are sent to the same host at the same time
+    pub max_per_host: usize,
+    /// removes tracking parameters (e.g. `utm_source`) from found links
+    pub strip_tracking: bool,
+    /// timeouts and size limits of a single page
+    pub fetch: FetchLimits,
+    /// how often a page that failed with a transient error (e.g. a timeout or 5xx) is tried again
+    pub max_retries: u32,
+    /// the order in which pages are crawled
+    pub frontier: FrontierStrategy,
+    /// which of the found pages are crawled
+    pub scope: CrawlScope,
+    /// how long the pages of a crawler stay claimed after it was killed, the lease is renewed while it runs,
+    /// at least `MIN_LEASE_DURATION`
+    pub lease_duration: Duration,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 64,
+            min_delay: Duration::from_millis(1000),
+            max_per_host: 2,
+            strip_tracking: false,
+            fetch: FetchLimits::default(),
+            max_retries: 3,
+            frontier: FrontierStrategy::default(),
+            scope: CrawlScope::default(),
+            lease_duration: Duration::from_secs(120),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use crate::db_manager::control::CrawlState;
+
+/// Pauses, resumes and stops a running crawler, see `run_with_control`
+///
+/// clones control the same crawler, the other crawlers of the database keep running,
+/// all of them are controlled through the database (see `CrawlState`)
+#[derive(Debug, Clone)]
+pub struct CrawlControl {
+    state: Arc<watch::Sender<CrawlState>>,
+}
+
+impl Default for CrawlControl {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(watch::channel(CrawlState::Running).0),
+        }
+    }
+}
+
+impl CrawlControl {
+    /// no new pages are fetched until the crawl is resumed, the pages that are being fetched are finished
+    pub fn pause(&self) {
+        self.set(CrawlState::Paused);
+    }
+
+    /// continues a paused crawl
+    pub fn resume(&self) {
+        self.set(CrawlState::Running);
+    }
+
+    /// ends the crawl once the pages that are being fetched are stored
+    pub fn stop(&self) {
+        self.set(CrawlState::Stopping);
+    }
+
+    pub fn state(&self) -> CrawlState {
+        *self.state.borrow()
+    }
+
+    /// a stopped crawl can't be paused or resumed again
+    pub(crate) fn set(&self, state: CrawlState) {
+        self.state.send_if_modified(|current| {
+            let changed = *current != state && *current != CrawlState::Stopping;
+
+            if changed {
+                *current = state;
+            }
+
+            changed
+        });
+    }
+
+    /// waits until the state changes (or at most `timeout`)
+    pub(crate) async fn changed(&self, timeout: std::time::Duration) {
+        let mut receiver = self.state.subscribe();
+
+        tokio::time::timeout(timeout, receiver.changed()).await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::db_manager::control::CrawlState;
+
+    use super::CrawlControl;
+
+    #[test]
+    fn changes_state() {
+        let control = CrawlControl::default();
+        let clone = control.clone();
+
+        assert_eq!(control.state(), CrawlState::Running);
+
+        clone.pause();
+        assert_eq!(control.state(), CrawlState::Paused);
+
+        control.resume();
+        control.stop();
+        assert_eq!(clone.state(), CrawlState::Stopping);
+
+        // stopping can't be undone
+        control.resume();
+        assert_eq!(clone.state(), CrawlState::Stopping);
+    }
+
+    #[tokio::test]
+    async fn wakes_on_change() {
+        let control = CrawlControl::default();
+        let clone = control.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            clone.pause();
+        });
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            control.changed(Duration::from_secs(60)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(control.state(), CrawlState::Paused);
+    }
+}
@@ -0,0 +1,262 @@
+use std::{fmt, str::FromStr};
+
+use crate::db_manager::{
+    creation::DatabaseConnection,
+    lease::Lease,
+    selecting::{claim_next_unvisited, claim_next_unvisited_after_host, FrontierOrder},
+};
+
+/// Decides which unvisited page is crawled next
+///
+/// the pages themselves stay in the database, so a crawl can be continued with another frontier
+pub trait Frontier: Send {
+    /// claims the next page that should be visited for the lease and returns its id and url,
+    /// `None` if no page may be visited right now
+    fn next(
+        &mut self,
+        conn: &DatabaseConnection,
+        lease: &Lease,
+    ) -> Result<Option<(i64, String)>, rusqlite::Error>;
+}
+
+/// Visits pages in the order they were found, the pages closest to the start urls come first
+pub struct BreadthFirst;
+
+impl Frontier for BreadthFirst {
+    fn next(
+        &mut self,
+        conn: &DatabaseConnection,
+        lease: &Lease,
+    ) -> Result<Option<(i64, String)>, rusqlite::Error> {
+        claim_next_unvisited(conn, FrontierOrder::Oldest, lease)
+    }
+}
+
+/// Visits the page that was found last first, follows links deeper before going back
+pub struct DepthFirst;
+
+impl Frontier for DepthFirst {
+    fn next(
+        &mut self,
+        conn: &DatabaseConnection,
+        lease: &Lease,
+    ) -> Result<Option<(i64, String)>, rusqlite::Error> {
+        claim_next_unvisited(conn, FrontierOrder::Newest, lease)
+    }
+}
+
+/// Visits the page most other pages link to first (an estimate of its pagerank)
+pub struct BestFirst;
+
+impl Frontier for BestFirst {
+    fn next(
+        &mut self,
+        conn: &DatabaseConnection,
+        lease: &Lease,
+    ) -> Result<Option<(i64, String)>, rusqlite::Error> {
+        claim_next_unvisited(conn, FrontierOrder::Priority, lease)
+    }
+}
+
+/// Takes turns between the hosts, so a single large site doesn't hold up all others
+#[derive(Default)]
+pub struct RoundRobin {
+    /// host of the page that was returned last
+    last_host: String,
+}
+
+impl Frontier for RoundRobin {
+    fn next(
+        &mut self,
+        conn: &DatabaseConnection,
+        lease: &Lease,
+    ) -> Result<Option<(i64, String)>, rusqlite::Error> {
+        let next = claim_next_unvisited_after_host(conn, &self.last_host, lease)?;
+
+        Ok(next.map(|(id, url, host)| {
+            self.last_host = host;
+            (id, url)
+        }))
+    }
+}
+
+/// The frontiers that can be chosen for a crawl
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrontierStrategy {
+    #[default]
+    BreadthFirst,
+    DepthFirst,
+    BestFirst,
+    RoundRobin,
+}
+
+impl FrontierStrategy {
+    pub fn frontier(self) -> Box<dyn Frontier> {
+        match self {
+            FrontierStrategy::BreadthFirst => Box::new(BreadthFirst),
+            FrontierStrategy::DepthFirst => Box::new(DepthFirst),
+            FrontierStrategy::BestFirst => Box::new(BestFirst),
+            FrontierStrategy::RoundRobin => Box::<RoundRobin>::default(),
+        }
+    }
+}
+
+impl FromStr for FrontierStrategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        match strategy.to_lowercase().as_str() {
+            "bfs" | "breadth-first" => Ok(FrontierStrategy::BreadthFirst),
+            "dfs" | "depth-first" => Ok(FrontierStrategy::DepthFirst),
+            "best-first" => Ok(FrontierStrategy::BestFirst),
+            "round-robin" => Ok(FrontierStrategy::RoundRobin),
+            _ => Err(format!(
+                "unknown frontier {strategy} (expected bfs, dfs, best-first or round-robin)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FrontierStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrontierStrategy::BreadthFirst => write!(f, "bfs"),
+            FrontierStrategy::DepthFirst => write!(f, "dfs"),
+            FrontierStrategy::BestFirst => write!(f, "best-first"),
+            FrontierStrategy::RoundRobin => write!(f, "round-robin"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use crate::{
+        db_manager::{
+            creation::{add_links, create_default_tables, DatabaseConnection},
+            helper::gen_random_path,
+            lease::Lease,
+        },
+        page_scraper::html_parser::Link,
+    };
+
+    use super::FrontierStrategy;
+
+    fn link(href: &str) -> Link {
+        Link {
+            href: href.to_string(),
+            text: String::new(),
+            rel: None,
+        }
+    }
+
+    /// `a.ch/` links to `a.ch/1`, `a.ch/2` and `b.ch/`, which links to `a.ch/2` and `b.ch/1`
+    fn gen_site(conn: &DatabaseConnection) {
+        conn.connection
+            .execute(
+                "INSERT INTO Ranking (url, host, visited) VALUES ('http://a.ch/', 'a.ch', true);",
+                (),
+            )
+            .unwrap();
+
+        add_links(
+            conn,
+            1,
+            &[
+                link("http://a.ch/1"),
+                link("http://a.ch/2"),
+                link("http://b.ch/"),
+            ],
+            false,
+        )
+        .unwrap();
+        conn.connection
+            .execute("UPDATE Ranking SET visited = true WHERE id = 4;", ())
+            .unwrap();
+        add_links(
+            conn,
+            4,
+            &[link("http://a.ch/2"), link("http://b.ch/1")],
+            false,
+        )
+        .unwrap();
+    }
+
+    /// returns the urls in the order the frontier visits them
+    fn crawl_order(strategy: FrontierStrategy) -> Vec<String> {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_site(&conn);
+
+        let mut frontier = strategy.frontier();
+        let lease = Lease::new(Duration::from_secs(60));
+        let mut order = vec![];
+
+        while let Some((id, url)) = frontier.next(&conn, &lease).unwrap() {
+            conn.connection
+                .execute("UPDATE Ranking SET visited = true WHERE id = ?1;", [id])
+                .unwrap();
+            order.push(url);
+        }
+
+        fs::remove_file(path).unwrap();
+
+        order
+    }
+
+    #[test]
+    fn breadth_first() {
+        assert_eq!(
+            crawl_order(FrontierStrategy::BreadthFirst),
+            vec!["http://a.ch/1", "http://a.ch/2", "http://b.ch/1"]
+        );
+    }
+
+    #[test]
+    fn depth_first() {
+        assert_eq!(
+            crawl_order(FrontierStrategy::DepthFirst),
+            vec!["http://b.ch/1", "http://a.ch/2", "http://a.ch/1"]
+        );
+    }
+
+    /// a.ch/2 is linked twice
+    #[test]
+    fn best_first() {
+        assert_eq!(
+            crawl_order(FrontierStrategy::BestFirst),
+            vec!["http://a.ch/2", "http://a.ch/1", "http://b.ch/1"]
+        );
+    }
+
+    #[test]
+    fn round_robin() {
+        assert_eq!(
+            crawl_order(FrontierStrategy::RoundRobin),
+            vec!["http://a.ch/1", "http://b.ch/1", "http://a.ch/2"]
+        );
+    }
+
+    #[test]
+    fn parses_strategy() {
+        for strategy in [
+            FrontierStrategy::BreadthFirst,
+            FrontierStrategy::DepthFirst,
+            FrontierStrategy::BestFirst,
+            FrontierStrategy::RoundRobin,
+        ] {
+            assert_eq!(
+                strategy.to_string().parse::<FrontierStrategy>().unwrap(),
+                strategy
+            );
+        }
+
+        assert_eq!(
+            "Depth-First".parse::<FrontierStrategy>().unwrap(),
+            FrontierStrategy::DepthFirst
+        );
+        assert!("random".parse::<FrontierStrategy>().is_err());
+    }
+}
@@ -1,24 +1,79 @@
 use std::{
     sync::{Arc, Mutex},
-    thread,
     time::Duration,
 };
 
+use tokio::sync::{mpsc, oneshot, Semaphore};
+
 use crate::{
     db_manager::{
+        control::{get_crawl_state, heartbeat, leave, set_crawl_state, CrawlState},
         creation::{create_default_tables, unvisited_page, DatabaseConnection},
+        lease::{renew_leases, Lease, MIN_LEASE_DURATION},
+        outcome::{FetchOutcome, Outcome},
         selecting::{get_new_link, is_finished},
-        updating::update_to_visited,
     },
-    indexer::visit_types::ToVisit,
+    indexer::{
+        config::CrawlConfig,
+        control::CrawlControl,
+        scheduler::{host_of, HostScheduler},
+        visit_types::ToVisit,
+        writer::{spawn_writer, Write},
+    },
+    page_rank::tokenizer::field_terms,
     page_scraper::{
+        fetcher::{Fetched, Fetcher},
         html::{Html, HtmlGetterError},
-        html_getter::html_getter,
-        html_parser::get_links,
+        html_parser::Document,
+        url_normalizer::{normalize_url, resolve_outlinks},
     },
+    robots::cache::RobotsCache,
 };
 
-pub fn run(start_urls: Vec<&str>, db_path: Option<String>, verbose: bool, threads: u8) {
+/// how long a host is left alone if it answers with 429 or 503 without a `Retry-After` header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// how long to wait before looking for new pages again if all unvisited pages are claimed or wait for a retry
+const IDLE_DELAY: Duration = Duration::from_millis(100);
+
+/// how often a paused crawl checks if another process resumed or stopped it
+const PAUSED_DELAY: Duration = Duration::from_secs(1);
+
+/// how often the crawler renews its leases and heartbeat
+const KEEP_ALIVE_DELAY: Duration = Duration::from_secs(1);
+
+/// the crawler renews its leases at least four times per lease, but never more often than this
+const MIN_KEEP_ALIVE_DELAY: Duration = Duration::from_millis(100);
+
+/// crawls from the start urls until every page is visited, Ctrl-C and SIGTERM stop the crawler gracefully
+///
+/// the pages that are being fetched are finished and stored, the crawl can be continued later (see `resume`),
+/// other crawlers of the database keep crawling
+pub fn run(start_urls: Vec<&str>, db_path: Option<String>, verbose: bool, config: CrawlConfig) {
+    let control = CrawlControl::default();
+
+    crawl_database(start_urls, db_path, verbose, config, control, true)
+}
+
+/// like `run`, but the crawl is controlled through `control` instead of signals
+pub fn run_with_control(
+    start_urls: Vec<&str>,
+    db_path: Option<String>,
+    verbose: bool,
+    config: CrawlConfig,
+    control: CrawlControl,
+) {
+    crawl_database(start_urls, db_path, verbose, config, control, false)
+}
+
+fn crawl_database(
+    start_urls: Vec<&str>,
+    db_path: Option<String>,
+    verbose: bool,
+    config: CrawlConfig,
+    control: CrawlControl,
+    handle_signals: bool,
+) {
     let db_path = db_path.unwrap_or("./database.db3".to_owned());
 
     let conn = create_default_tables(&db_path).unwrap();
@@ -26,107 +81,413 @@ pub fn run(start_urls: Vec<&str>, db_path: Option<String>, verbose: bool, thread
 
     // fill in the start_urls
     for url in start_urls {
-        unvisited_page(Arc::clone(&conn), url, verbose).unwrap();
+        match normalize_url(url, config.strip_tracking) {
+            Some(url) if config.scope.allows(&url) => {
+                unvisited_page(&conn.lock().unwrap(), &url, verbose).unwrap();
+            }
+            Some(url) => println!("Ignoring start url outside of the crawl scope: {url}"),
+            None => println!("Ignoring invalid start url: {url}"),
+        }
     }
 
-    cmd_fn(conn, verbose, threads)
+    // one runtime for the whole crawl, all fetch tasks share it and its connection pool
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    if handle_signals {
+        runtime.spawn(stop_on_signal(control.clone()));
+    }
+
+    runtime.block_on(crawl(conn, verbose, config, control))
 }
 
-/// # Command function
+/// stops the crawl on the first Ctrl-C or SIGTERM, quits immediately on the second one
+async fn stop_on_signal(control: CrawlControl) {
+    shutdown_signal().await;
+    println!("Stopping the crawler after the pages that are being fetched (press Ctrl-C again to quit immediately)...");
+    control.stop();
+
+    shutdown_signal().await;
+    // the leased pages are crawled again once the leases have expired
+    std::process::exit(130);
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(_) => {
+            tokio::signal::ctrl_c().await.ok();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c().await.ok();
+}
+
+/// Everything a fetch task needs, shared by all of them
+struct FetchContext {
+    fetcher: Fetcher,
+    robots: RobotsCache,
+    scheduler: HostScheduler,
+    writer: mpsc::Sender<Write>,
+    /// stopped if the writer is gone
+    control: CrawlControl,
+    strip_tracking: bool,
+    verbose: bool,
+}
+
+impl FetchContext {
+    /// hands the write to the writer, waits if the writer is behind
+    async fn write(&self, write: Write) {
+        // the writer only stops early if it failed, nothing can be stored anymore
+        if let Err(err) = self.writer.send(write).await {
+            eprintln!(
+                "Couldn't store the result of {}, stopping the crawl: the writer stopped",
+                err.0.page().url
+            );
+            self.control.stop();
+        }
+    }
+}
+
+/// # Crawl
 ///
-/// 1. Stores all lists
-/// 2. creates threads to parse new websites
-fn cmd_fn(db_connection: Arc<Mutex<DatabaseConnection>>, verbose: bool, thread_count: u8) {
-    let mut threads = vec![];
-
-    for _ in 0..thread_count {
-        let new_db_connection = Arc::clone(&db_connection);
-
-        threads.push(thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_multi_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-
-            loop {
-                if is_finished(&new_db_connection.lock().unwrap()).unwrap_or(false) {
-                    if verbose {
-                        println!("No new pages to crawl found, shutting down thread...");
+/// 1. leases the pages the frontier picks and starts a fetch task for each of them, at most `concurrency` run at the same time,
+///    other crawlers (also in other processes) skip leased pages
+/// 2. skips pages that are disallowed by the robots.txt of their host, also if a redirect points to them
+/// 3. waits until the host of a page may be requested again, also before following a redirect
+/// 4. hands the results to a single writer task, fetch tasks wait when it falls behind
+/// 5. stores the content of redirected pages on the page the redirect points to
+/// 6. tries pages that failed with a transient error again later
+/// 7. stops claiming pages while paused, finishes the claimed pages when stopped
+async fn crawl(
+    db_connection: Arc<Mutex<DatabaseConnection>>,
+    verbose: bool,
+    config: CrawlConfig,
+    control: CrawlControl,
+) {
+    let concurrency = config.concurrency.max(1);
+    let lease = Lease::new(config.lease_duration.max(MIN_LEASE_DURATION));
+
+    let fetcher = Fetcher::new(config.fetch.clone());
+    let (writer, writer_handle) = spawn_writer(
+        Arc::clone(&db_connection),
+        concurrency,
+        config.max_retries,
+        config.scope.clone(),
+        lease.clone(),
+        verbose,
+    );
+
+    let context = Arc::new(FetchContext {
+        robots: RobotsCache::with_fetcher(fetcher.clone()),
+        fetcher,
+        scheduler: HostScheduler::new(config.min_delay, config.max_per_host),
+        writer,
+        control: control.clone(),
+        strip_tracking: config.strip_tracking,
+        verbose,
+    });
+
+    let slots = Arc::new(Semaphore::new(concurrency));
+    let frontier = Arc::new(Mutex::new(config.frontier.frontier()));
+
+    {
+        let lock = db_connection.lock().unwrap();
+
+        // the first crawler of the database starts the crawl, the others join it in the state it is in
+        if get_crawl_state(&lock).unwrap() == CrawlState::Stopped {
+            set_crawl_state(&lock, CrawlState::Running).unwrap();
+        }
+
+        heartbeat(&lock, &lease.owner).unwrap();
+    }
+    // the state of the whole crawl (set by `guugle pause`, `resume` and `stop`) and the state of this crawler
+    let mut requested_state = CrawlState::Running;
+    let mut local_state = control.state();
+
+    let keep_alive = tokio::spawn(keep_alive(Arc::clone(&db_connection), lease.clone()));
+
+    loop {
+        let slot = Arc::clone(&slots).acquire_owned().await.unwrap();
+
+        // pages are only marked as visited by the writer
+        let conn = Arc::clone(&db_connection);
+        let frontier = Arc::clone(&frontier);
+        let task_control = control.clone();
+        let task_lease = lease.clone();
+        let (step, requested, state) = tokio::task::spawn_blocking(move || {
+            let lock = conn.lock().unwrap();
+            let requested = sync_state(&lock, &task_control, &task_lease.owner, requested_state);
+            let state = task_control.state();
+
+            let step = match state {
+                CrawlState::Running if is_finished(&lock).unwrap_or(false) => Step::Finish,
+                CrawlState::Running => {
+                    drop(lock);
+
+                    match get_new_link(conn, frontier.lock().unwrap().as_mut(), &task_lease) {
+                        Some(to_visit) => Step::Visit(to_visit),
+                        None => Step::Wait,
                     }
-                    break;
                 }
+                CrawlState::Paused => Step::Pause,
+                CrawlState::Stopping | CrawlState::Stopped => Step::Finish,
+            };
 
-                let new_url = get_new_link(Arc::clone(&new_db_connection));
-                let to_visit: ToVisit;
+            (step, requested, state)
+        })
+        .await
+        .unwrap();
 
-                match new_url {
-                    Some(t_v) => to_visit = t_v,
-                    None => {
-                        thread::sleep(Duration::from_millis(100));
-                        continue;
-                    }
+        if verbose && state != local_state {
+            println!("Crawl is {}", state.as_str());
+        }
+        requested_state = requested;
+        local_state = state;
+
+        let to_visit = match step {
+            Step::Visit(to_visit) => to_visit,
+            Step::Wait => {
+                drop(slot);
+                tokio::time::sleep(IDLE_DELAY).await;
+                continue;
+            }
+            Step::Pause => {
+                drop(slot);
+                control.changed(PAUSED_DELAY).await;
+                continue;
+            }
+            Step::Finish => {
+                if verbose && state == CrawlState::Running {
+                    println!("No new pages to crawl, shutting down...");
+                } else if verbose {
+                    println!("Finishing the pages that are being fetched...");
                 }
+                break;
+            }
+        };
 
-                let mut html: Option<Html> = None;
-
-                match rt.block_on(async { html_getter(&to_visit.url).await }) {
-                    Ok(ok) => html = Some(ok),
-                    Err(err) => match err {
-                        HtmlGetterError::NotHTML => {
-                            update_to_visited(
-                                &new_db_connection.lock().unwrap(),
-                                to_visit.id,
-                                "NOT HTML",
-                                vec![],
-                                verbose,
-                            )
-                            .ok();
-                            continue;
-                        }
-                        HtmlGetterError::GetError
-                        | HtmlGetterError::StatusCode
-                        | HtmlGetterError::UrlError => {
-                            // links doesn't work so just ignore it
-                            update_to_visited(
-                                &new_db_connection.lock().unwrap(),
-                                to_visit.id,
-                                "ERROR",
-                                vec![],
-                                verbose,
-                            )
-                            .ok();
-
-                            continue;
-                        }
-                    },
-                }
+        let context = Arc::clone(&context);
 
-                let html = html.unwrap();
+        tokio::spawn(async move {
+            visit(&context, to_visit).await;
+            drop(slot);
+        });
+    }
 
-                let links = get_links(&html);
+    // the writer stops once the last fetch task is done with it
+    drop(context);
+    writer_handle.await.unwrap();
 
-                update_to_visited(
-                    &new_db_connection.lock().unwrap(),
-                    to_visit.id,
-                    &html.text,
-                    links.iter().map(|string| string.as_str()).collect(),
-                    verbose,
-                )
-                .unwrap();
+    keep_alive.abort();
+    leave(&db_connection.lock().unwrap(), &lease.owner).unwrap();
+}
+
+/// renews the leases of the crawler and tells the other processes that it is still alive,
+/// also while the crawl loop waits for a free slot
+async fn keep_alive(conn: Arc<Mutex<DatabaseConnection>>, lease: Lease) {
+    let delay = KEEP_ALIVE_DELAY
+        .min(lease.duration / 4)
+        .max(MIN_KEEP_ALIVE_DELAY);
+
+    loop {
+        tokio::time::sleep(delay).await;
+
+        let conn = Arc::clone(&conn);
+        let lease = lease.clone();
+        tokio::task::spawn_blocking(move || {
+            let lock = conn.lock().unwrap();
+
+            heartbeat(&lock, &lease.owner).ok();
+            renew_leases(&lock, &lease).ok();
+        })
+        .await
+        .ok();
+    }
+}
 
-                // add newly found links to database
-                for link in links {
-                    unvisited_page(Arc::clone(&new_db_connection), &link, verbose).ok();
+/// What the crawl loop does next
+enum Step {
+    Visit(ToVisit),
+    /// all unvisited pages are claimed or wait for a retry
+    Wait,
+    Pause,
+    /// every page is visited or the crawl was stopped
+    Finish,
+}
+
+/// applies a change of the state other processes stored in the database to the `CrawlControl`,
+/// `requested` is the state that was stored last, returns the state that is stored now
+///
+/// the `CrawlControl` (e.g. Ctrl-C) only controls this crawler, the state of the other crawlers isn't changed
+fn sync_state(
+    conn: &DatabaseConnection,
+    control: &CrawlControl,
+    owner: &str,
+    requested: CrawlState,
+) -> CrawlState {
+    let stored = heartbeat(conn, owner).unwrap_or(requested);
+
+    if stored != requested {
+        control.set(stored);
+    }
+
+    stored
+}
+
+/// What fetching a page and following its redirects ended with
+enum Fetch {
+    Page(Html),
+    /// the page or a page it redirects to is disallowed by robots.txt
+    Disallowed,
+    Failed(HtmlGetterError),
+}
+
+/// fetches the page and follows its redirects,
+/// every request (also to another host) is checked against robots.txt and waits until its host may be requested,
+/// this includes the request of the robots.txt itself
+async fn fetch(context: &FetchContext, url: &str) -> Fetch {
+    let mut chain = match context.fetcher.redirect_chain(url) {
+        Ok(chain) => chain,
+        Err(err) => return Fetch::Failed(err),
+    };
+
+    loop {
+        let url = chain.url().to_string();
+        let host = host_of(&url).unwrap_or_default();
+
+        let allowed = match context.robots.has_rules(&url) {
+            true => context.robots.is_allowed(&url).await,
+            false => {
+                let _permit = context.scheduler.acquire(&host, None).await;
+                context.robots.is_allowed(&url).await
+            }
+        };
+
+        if !allowed {
+            return Fetch::Disallowed;
+        }
+
+        let permit = context
+            .scheduler
+            .acquire(&host, context.robots.crawl_delay(&url))
+            .await;
+        let response = context.fetcher.fetch_once(&mut chain).await;
+        drop(permit);
+
+        let target = match response {
+            Ok(Fetched::Page(html)) => return Fetch::Page(chain.finish(html)),
+            Ok(Fetched::Redirect(target)) => target,
+            Err(err) => {
+                if let HtmlGetterError::TooManyRequests(_, delay) = err {
+                    context
+                        .scheduler
+                        .delay_host(&host, delay.unwrap_or(DEFAULT_RETRY_AFTER));
                 }
+
+                return Fetch::Failed(err);
             }
-        }));
+        };
+
+        if let Err(err) = chain.follow(target) {
+            return Fetch::Failed(err);
+        }
     }
+}
+
+/// fetches the page and hands the result to the writer
+async fn visit(context: &FetchContext, to_visit: ToVisit) {
+    let html = match fetch(context, &to_visit.url).await {
+        Fetch::Page(html) => html,
+        Fetch::Disallowed => {
+            if context.verbose {
+                println!("Skipping {} (disallowed by robots.txt)", to_visit.url);
+            }
 
-    for thread in threads {
-        match thread.join() {
-            Ok(_) => {}
-            Err(err) => println!("{:#?}", err),
+            context.write(disallowed(to_visit)).await;
+            return;
         }
+        Fetch::Failed(err) => {
+            let retry_after = match err {
+                HtmlGetterError::TooManyRequests(_, delay) => {
+                    Some(delay.unwrap_or(DEFAULT_RETRY_AFTER))
+                }
+                _ => None,
+            };
+
+            context
+                .write(Write::Failed {
+                    page: to_visit,
+                    outcome: FetchOutcome::from(&err),
+                    transient: err.is_transient(),
+                    retry_after,
+                })
+                .await;
+            return;
+        }
+    };
+
+    // the content belongs to the page the redirects point to
+    let page = match html.url.as_deref() {
+        Some(final_url) if !html.redirects.is_empty() => {
+            let final_url = normalize_url(final_url, context.strip_tracking)
+                .unwrap_or_else(|| final_url.to_string());
+
+            let (canonical, receiver) = oneshot::channel();
+
+            context
+                .write(Write::Redirected {
+                    page: to_visit,
+                    redirects: html.redirects.clone(),
+                    final_url,
+                    canonical,
+                })
+                .await;
+
+            match receiver.await {
+                Ok(Some(canonical)) => canonical,
+                // already crawled (or being crawled) under its own url
+                _ => return,
+            }
+        }
+        _ => to_visit,
+    };
+
+    let links = resolve_outlinks(&page.url, &html, context.strip_tracking);
+    let fields = Document::parse(&html).fields();
+    let terms = field_terms(&page.url, &fields);
+
+    context
+        .write(Write::Visited {
+            page,
+            content: html.text,
+            content_type: html.content_type,
+            fields,
+            links,
+            terms,
+        })
+        .await;
+}
+
+fn disallowed(page: ToVisit) -> Write {
+    Write::Failed {
+        page,
+        outcome: FetchOutcome::new(Outcome::Disallowed),
+        transient: false,
+        retry_after: None,
     }
 }
 
@@ -134,10 +495,467 @@ fn cmd_fn(db_connection: Arc<Mutex<DatabaseConnection>>, verbose: bool, thread_c
 mod tests {
     use std::fs;
 
-    use sha2::{Digest, Sha256};
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use hyper::{
+        header,
+        service::{make_service_fn, service_fn},
+        Body, Response, Server, StatusCode,
+    };
+
+    use super::{run, run_with_control};
+    use crate::db_manager::{
+        control::{get_crawl_state, CrawlState},
+        creation::{create_default_tables, DatabaseConnection},
+        helper::*,
+        outcome::Outcome,
+        selecting::get_values,
+    };
+    use crate::indexer::{config::CrawlConfig, control::CrawlControl, scope::CrawlScope};
+
+    /// the amount of pages that are still claimed by a crawler
+    fn leased_pages(conn: &DatabaseConnection) -> i64 {
+        conn.connection
+            .query_row(
+                "SELECT COUNT(*) FROM Ranking WHERE lease_owner IS NOT NULL;",
+                (),
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    /// starts a local site in its own thread: `/` links to `/a` and `/b`, `/a` links to `/c`, which redirects to `/b`
+    fn serve_site() -> SocketAddr {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+
+            runtime.block_on(async move {
+                let make_service = make_service_fn(|_| async {
+                    Ok::<_, Infallible>(service_fn(|request: hyper::Request<Body>| async move {
+                        let response = match request.uri().path() {
+                            "/" => Response::builder().body(Body::from(
+                                "<html><a href=\"/a\">a</a><a href=\"/b\">b</a></html>",
+                            )),
+                            "/a" => Response::builder()
+                                .body(Body::from("<html><a href=\"/c\">c</a></html>")),
+                            "/b" => Response::builder().body(Body::from("<html>b</html>")),
+                            "/c" => Response::builder()
+                                .status(StatusCode::MOVED_PERMANENTLY)
+                                .header(header::LOCATION, "/b")
+                                .body(Body::empty()),
+                            _ => Response::builder()
+                                .status(StatusCode::NOT_FOUND)
+                                .body(Body::empty()),
+                        };
+
+                        Ok::<_, Infallible>(response.unwrap())
+                    }))
+                });
+
+                let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+                sender.send(server.local_addr()).unwrap();
+
+                server.await.unwrap();
+            });
+        });
+
+        receiver.recv().unwrap()
+    }
 
-    use super::run;
-    use crate::db_manager::{creation::create_default_tables, helper::*, selecting::get_values};
+    /// starts a local site whose robots.txt disallows `/private`, `/` redirects there
+    ///
+    /// the returned counter contains the amount of requests to `/private`
+    fn serve_disallowed_redirect() -> (SocketAddr, Arc<AtomicUsize>) {
+        let (sender, receiver) = mpsc::channel();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let counter = Arc::clone(&counter);
+
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                            let counter = Arc::clone(&counter);
+
+                            async move {
+                                let response = match request.uri().path() {
+                                    "/robots.txt" => Response::builder()
+                                        .body(Body::from("User-agent: *\nDisallow: /private\n")),
+                                    "/" => Response::builder()
+                                        .status(StatusCode::FOUND)
+                                        .header(header::LOCATION, "/private")
+                                        .body(Body::empty()),
+                                    _ => {
+                                        counter.fetch_add(1, Ordering::SeqCst);
+                                        Response::builder().body(Body::from("<html>secret</html>"))
+                                    }
+                                };
+
+                                Ok::<_, Infallible>(response.unwrap())
+                            }
+                        }))
+                    }
+                });
+
+                let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+                sender.send(server.local_addr()).unwrap();
+
+                server.await.unwrap();
+            });
+        });
+
+        (receiver.recv().unwrap(), requests)
+    }
+
+    /// the paths of the requests to a local site and when they arrived
+    type Requests = Arc<Mutex<Vec<(String, Instant)>>>;
+
+    /// starts a local site with a single page and records its requests
+    fn serve_recorded() -> (SocketAddr, Requests) {
+        let (sender, receiver) = mpsc::channel();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let recorded = Arc::clone(&recorded);
+
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                            recorded
+                                .lock()
+                                .unwrap()
+                                .push((request.uri().path().to_string(), Instant::now()));
+
+                            async move {
+                                Ok::<_, Infallible>(Response::new(Body::from("<html>page</html>")))
+                            }
+                        }))
+                    }
+                });
+
+                let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+                sender.send(server.local_addr()).unwrap();
+
+                server.await.unwrap();
+            });
+        });
+
+        (receiver.recv().unwrap(), requests)
+    }
+
+    /// the robots.txt request waits for the host like every other request
+    #[test]
+    fn schedules_robots_requests() {
+        let (address, requests) = serve_recorded();
+        let path = gen_random_path();
+
+        run(
+            vec![&format!("http://{address}/")],
+            Some(path.to_str().unwrap().to_string()),
+            false,
+            CrawlConfig {
+                min_delay: Duration::from_millis(300),
+                max_per_host: 1,
+                ..Default::default()
+            },
+        );
+
+        fs::remove_file(path).unwrap();
+
+        let requests = requests.lock().unwrap();
+        let paths = requests
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(paths, ["/robots.txt", "/"]);
+        assert!(requests[1].1 - requests[0].1 >= Duration::from_millis(300));
+    }
+
+    /// a redirect to a disallowed page isn't followed
+    #[test]
+    fn checks_robots_before_redirects() {
+        let (address, requests) = serve_disallowed_redirect();
+        let path = gen_random_path();
+
+        run(
+            vec![&format!("http://{address}/")],
+            Some(path.to_str().unwrap().to_string()),
+            false,
+            CrawlConfig {
+                min_delay: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+        let pages = get_values(&conn).unwrap();
+        let outcome: String = conn
+            .connection
+            .query_row("SELECT outcome FROM Ranking WHERE id = 1;", (), |row| {
+                row.get(0)
+            })
+            .unwrap();
+        drop(conn);
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(outcome, Outcome::Disallowed.as_str());
+    }
+
+    /// every page is visited exactly once and no claim is left behind
+    #[test]
+    fn crawls_local_site() {
+        let address = serve_site();
+        let path = gen_random_path();
+
+        run(
+            vec![&format!("http://{address}/")],
+            Some(path.to_str().unwrap().to_string()),
+            false,
+            CrawlConfig {
+                concurrency: 16,
+                min_delay: Duration::ZERO,
+                max_per_host: 4,
+                ..Default::default()
+            },
+        );
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        let mut pages = get_values(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|page| {
+                (
+                    page.url.replace(&address.to_string(), "site"),
+                    page.visited,
+                    page.content.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+        pages.sort();
+        let leases = leased_pages(&conn);
+        drop(conn);
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            pages,
+            vec![
+                ("http://site/".to_string(), true, true),
+                ("http://site/a".to_string(), true, true),
+                ("http://site/b".to_string(), true, true),
+                // redirected, the content is stored on /b
+                ("http://site/c".to_string(), true, false),
+            ]
+        );
+        assert_eq!(leases, 0);
+    }
+
+    /// a stopped crawl doesn't fetch anything, resuming it (without start urls) crawls the stored frontier
+    #[test]
+    fn resumes_stopped_crawl() {
+        let address = serve_site();
+        let path = gen_random_path();
+        let config = CrawlConfig {
+            min_delay: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let control = CrawlControl::default();
+        control.stop();
+
+        run_with_control(
+            vec![&format!("http://{address}/")],
+            Some(path.to_str().unwrap().to_string()),
+            false,
+            config.clone(),
+            control,
+        );
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+        let pages = get_values(&conn).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(!pages[0].visited);
+        assert_eq!(leased_pages(&conn), 0);
+        assert_eq!(get_crawl_state(&conn).unwrap(), CrawlState::Stopped);
+        drop(conn);
+
+        run(
+            vec![],
+            Some(path.to_str().unwrap().to_string()),
+            false,
+            config,
+        );
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+        let pages = get_values(&conn).unwrap();
+        let leases = leased_pages(&conn);
+        drop(conn);
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(pages.len(), 4);
+        assert!(pages.iter().all(|page| page.visited));
+        assert_eq!(leases, 0);
+    }
+
+    /// `/c` is two links away from the start url
+    #[test]
+    fn crawls_within_depth() {
+        let address = serve_site();
+        let path = gen_random_path();
+
+        run(
+            vec![&format!("http://{address}/")],
+            Some(path.to_str().unwrap().to_string()),
+            false,
+            CrawlConfig {
+                min_delay: Duration::ZERO,
+                scope: CrawlScope {
+                    max_depth: Some(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        let mut urls = get_values(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|page| page.url.replace(&address.to_string(), "site"))
+            .collect::<Vec<_>>();
+        urls.sort();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(urls, vec!["http://site/", "http://site/a", "http://site/b"]);
+    }
+
+    /// stopping one crawler (e.g. with Ctrl-C) doesn't stop the others
+    #[test]
+    fn stops_crawler_only() {
+        let address = serve_site();
+        let path = gen_random_path();
+        let config = CrawlConfig {
+            min_delay: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        // the first crawler waits until the second one stopped
+        let paused = CrawlControl::default();
+        paused.pause();
+
+        let first = {
+            let path = path.to_str().unwrap().to_string();
+            let start = format!("http://{address}/");
+            let (config, control) = (config.clone(), paused.clone());
+
+            thread::spawn(move || {
+                run_with_control(vec![&start], Some(path), false, config, control)
+            })
+        };
+
+        while get_crawl_state(&conn).unwrap() == CrawlState::Stopped {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let stopped = CrawlControl::default();
+        stopped.stop();
+        run_with_control(
+            vec![],
+            Some(path.to_str().unwrap().to_string()),
+            false,
+            config,
+            stopped,
+        );
+
+        let state = get_crawl_state(&conn).unwrap();
+        paused.resume();
+        first.join().unwrap();
+
+        let pages = get_values(&conn).unwrap();
+        drop(conn);
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(state, CrawlState::Running);
+        assert_eq!(pages.len(), 4);
+        assert!(pages.iter().all(|page| page.visited));
+    }
+
+    /// two crawlers (like two processes) share the database, every page is visited once
+    #[test]
+    fn crawlers_share_database() {
+        let address = serve_site();
+        let path = gen_random_path();
+        let start = format!("http://{address}/");
+
+        // the crawlers join a crawl, they don't create the database at the same time
+        drop(create_default_tables(path.to_str().unwrap()).unwrap());
+
+        let crawlers = (0..2)
+            .map(|_| {
+                let path = path.to_str().unwrap().to_string();
+                let start = start.clone();
+
+                thread::spawn(move || {
+                    run(
+                        vec![&start],
+                        Some(path),
+                        false,
+                        CrawlConfig {
+                            min_delay: Duration::ZERO,
+                            ..Default::default()
+                        },
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for crawler in crawlers {
+            crawler.join().unwrap();
+        }
+
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+        let pages = get_values(&conn).unwrap();
+        let leases = leased_pages(&conn);
+        let state = get_crawl_state(&conn).unwrap();
+        drop(conn);
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(pages.len(), 4);
+        assert!(pages.iter().all(|page| page.visited));
+        assert_eq!(leases, 0);
+        assert_eq!(state, CrawlState::Stopped);
+    }
 
     #[test]
     fn run_fn_basic_urls() {
@@ -149,7 +967,10 @@ mod tests {
             start_urls,
             Some(path.to_str().unwrap().to_string()),
             false,
-            5,
+            CrawlConfig {
+                concurrency: 5,
+                ..Default::default()
+            },
         );
 
         let conn = create_default_tables(path.to_str().unwrap()).unwrap();
@@ -176,7 +997,10 @@ mod tests {
             start_urls,
             Some(path.to_str().unwrap().to_string()),
             false,
-            5,
+            CrawlConfig {
+                concurrency: 5,
+                ..Default::default()
+            },
         );
 
         let conn = create_default_tables(path.to_str().unwrap()).unwrap();
@@ -185,8 +1009,7 @@ mod tests {
 
         println!("{:?}", result);
 
-        let content = [
-            r#"
+        let content = r#"
         <!doctype html>
 <html>
 <head>
@@ -233,14 +1056,20 @@ mod tests {
 </div>
 </body>
 </html>
-        "#.trim(),
-            "ERROR",
-        ];
+        "#.trim();
+
+        // the outcome of every visited page is stored
+        let outcomes = conn
+            .connection
+            .prepare("SELECT COUNT(*) FROM Ranking WHERE outcome IS NULL;")
+            .unwrap()
+            .query_row((), |row| row.get::<usize, i64>(0))
+            .unwrap();
 
         fs::remove_file(path).unwrap();
 
-        assert_eq!(result[0].content.as_ref().unwrap().trim(), content[0]);
-        assert_eq!(result[1].content.as_ref().unwrap().trim(), content[1]);
+        assert_eq!(result[0].content.as_ref().unwrap().trim(), content);
+        assert_eq!(outcomes, 0);
     }
 
     #[test]
@@ -255,7 +1084,10 @@ mod tests {
             start_urls,
             Some(path.to_str().unwrap().to_string()),
             false,
-            5,
+            CrawlConfig {
+                concurrency: 5,
+                ..Default::default()
+            },
         );
 
         let res = get_values(&conn).unwrap();
@@ -266,17 +1098,15 @@ mod tests {
 
         fs::remove_file(path).unwrap();
 
-        assert!(res.any(|res| res.url == "http://example.com/".to_string()));
-        assert!(res.any(|res| res.url == "https://maslinks.netlify.app/".to_string()));
-        assert!(res.any(|res| res.url == "https://www.iana.org/domains/example".to_string()));
-        assert!(res.any(|res| res.url == "https://regameapp.vercel.app/".to_string()));
+        assert!(res.any(|res| res.url == "http://example.com/"));
+        assert!(res.any(|res| res.url == "https://maslinks.netlify.app/"));
+        assert!(res.any(|res| res.url == "https://www.iana.org/domains/example"));
+        assert!(res.any(|res| res.url == "https://regameapp.vercel.app/"));
     }
 
     /// checks if content is correct
     #[test]
     fn run_fn_complex_content() {
-        let hasher = Sha256::new();
-
         let start_urls = vec!["http://example.com/", "https://maslinks.netlify.app/"];
 
         let path = gen_random_path();
@@ -287,7 +1117,10 @@ mod tests {
             start_urls,
             Some(path.to_str().unwrap().to_string()),
             false,
-            5,
+            CrawlConfig {
+                concurrency: 5,
+                ..Default::default()
+            },
         );
 
         let res = get_values(&conn).unwrap();
@@ -304,8 +1137,8 @@ mod tests {
             }
 
             match &res.content {
-                None => return false,
-                Some(c) => return c.contains(correct_content[0]),
+                None => false,
+                Some(c) => c.contains(correct_content[0]),
             }
         }));
 
@@ -315,8 +1148,8 @@ mod tests {
             }
 
             match &res.content {
-                None => return false,
-                Some(c) => return c.contains(correct_content[1]),
+                None => false,
+                Some(c) => c.contains(correct_content[1]),
             }
         }));
 
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+/// delay before the first retry
+const BASE_DELAY: Duration = Duration::from_secs(2);
+/// retries are never delayed longer than this
+const MAX_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// returns how long to wait before a page is requested again after it failed `retries` times
+///
+/// the delay doubles with every retry, a random part of up to half of it is taken off so that failed pages don't all come back at once
+pub fn backoff(retries: u32) -> Duration {
+    let delay = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(MAX_DELAY);
+
+    delay / 2 + delay.mul_f64(fastrand::f64() / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{backoff, MAX_DELAY};
+
+    #[test]
+    fn grows_exponentially() {
+        for retries in 0..5 {
+            let delay = Duration::from_secs(2 * 2u64.pow(retries));
+
+            for _ in 0..100 {
+                let backoff = backoff(retries);
+
+                assert!(backoff >= delay / 2 && backoff <= delay);
+            }
+        }
+    }
+
+    #[test]
+    fn is_capped() {
+        assert!(backoff(30) <= MAX_DELAY);
+        assert!(backoff(u32::MAX) <= MAX_DELAY);
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+use crate::page_scraper::html_getter::parse_uri;
+
+/// the idle hosts are removed once this many hosts are known
+const PRUNE_AT: usize = 1024;
+
+struct HostState {
+    /// requests that are currently sent to the host
+    active: usize,
+    /// the next request to the host may not be sent before this point in time
+    next_allowed: Instant,
+}
+
+/// Makes sure that hosts aren't flooded with requests
+///
+/// between two requests to the same host at least `min_delay` (or the crawl delay of the host if it is longer) has to pass
+/// and at most `max_per_host` requests are sent to a host at the same time
+pub struct HostScheduler {
+    hosts: Mutex<HashMap<String, HostState>>,
+    changed: Notify,
+    min_delay: Duration,
+    max_per_host: usize,
+}
+
+/// Permission to send a request to a host, the slot is freed when the permit is dropped
+pub struct HostPermit<'a> {
+    scheduler: &'a HostScheduler,
+    host: String,
+}
+
+impl HostScheduler {
+    pub fn new(min_delay: Duration, max_per_host: usize) -> Self {
+        Self {
+            hosts: Mutex::new(HashMap::new()),
+            changed: Notify::new(),
+            min_delay,
+            max_per_host: max_per_host.max(1),
+        }
+    }
+
+    /// waits until a request to the host may be sent
+    ///
+    /// the returned permit has to be kept until the request is finished
+    pub async fn acquire(&self, host: &str, crawl_delay: Option<Duration>) -> HostPermit<'_> {
+        let delay = crawl_delay.unwrap_or_default().max(self.min_delay);
+
+        loop {
+            // registered before the state is checked, so a release in between isn't missed
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let wait = {
+                let mut hosts = self.hosts.lock().unwrap();
+                let now = Instant::now();
+
+                if hosts.len() >= PRUNE_AT {
+                    prune(&mut hosts, now);
+                }
+
+                let state = hosts.entry(host.to_string()).or_insert(HostState {
+                    active: 0,
+                    next_allowed: now,
+                });
+
+                if state.active >= self.max_per_host {
+                    None
+                } else if state.next_allowed > now {
+                    Some(state.next_allowed - now)
+                } else {
+                    state.active += 1;
+                    state.next_allowed = now + delay;
+
+                    return HostPermit {
+                        scheduler: self,
+                        host: host.to_string(),
+                    };
+                }
+            };
+
+            match wait {
+                Some(wait) => {
+                    tokio::time::timeout(wait, changed).await.ok();
+                }
+                None => changed.await,
+            }
+        }
+    }
+
+    /// no new request is sent to the host before the delay has passed (used for `Retry-After`)
+    pub fn delay_host(&self, host: &str, delay: Duration) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        let until = Instant::now() + delay;
+
+        let state = hosts.entry(host.to_string()).or_insert(HostState {
+            active: 0,
+            next_allowed: until,
+        });
+
+        state.next_allowed = state.next_allowed.max(until);
+
+        self.changed.notify_waiters();
+    }
+
+    fn release(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if let Some(state) = hosts.get_mut(host) {
+            state.active = state.active.saturating_sub(1);
+        }
+
+        self.changed.notify_waiters();
+    }
+}
+
+/// removes the hosts without requests that may be requested right away, they behave like unknown hosts
+fn prune(hosts: &mut HashMap<String, HostState>, now: Instant) {
+    hosts.retain(|_, state| state.active > 0 || state.next_allowed > now);
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.host);
+    }
+}
+
+/// returns the lowercase host of the url, requests to all ports and schemes of a host are scheduled together
+pub fn host_of(url: &str) -> Option<String> {
+    parse_uri(url).ok()?.host().map(|host| host.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use super::{host_of, HostScheduler, PRUNE_AT};
+
+    #[test]
+    fn host_from_url() {
+        assert_eq!(host_of("https://Example.com:443/a").unwrap(), "example.com");
+        assert_eq!(host_of("example.com").unwrap(), "example.com");
+        assert_eq!(host_of("/relative"), None);
+    }
+
+    #[tokio::test]
+    async fn waits_min_delay() {
+        let scheduler = HostScheduler::new(Duration::from_millis(100), 4);
+
+        let start = Instant::now();
+
+        drop(scheduler.acquire("example.com", None).await);
+        drop(scheduler.acquire("example.com", None).await);
+        drop(scheduler.acquire("example.com", None).await);
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // other hosts aren't affected
+        let start = Instant::now();
+        drop(scheduler.acquire("example.org", None).await);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn crawl_delay_is_respected() {
+        let scheduler = HostScheduler::new(Duration::ZERO, 4);
+
+        let start = Instant::now();
+
+        drop(
+            scheduler
+                .acquire("example.com", Some(Duration::from_millis(150)))
+                .await,
+        );
+        drop(
+            scheduler
+                .acquire("example.com", Some(Duration::from_millis(150)))
+                .await,
+        );
+
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn limits_concurrent_requests() {
+        let scheduler = Arc::new(HostScheduler::new(Duration::ZERO, 1));
+
+        let permit = scheduler.acquire("example.com", None).await;
+
+        let other = Arc::clone(&scheduler);
+        let waiting = tokio::spawn(async move {
+            let start = Instant::now();
+            drop(other.acquire("example.com", None).await);
+            start.elapsed()
+        });
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        drop(permit);
+
+        assert!(waiting.await.unwrap() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn retry_after_delays_host() {
+        let scheduler = HostScheduler::new(Duration::ZERO, 1);
+
+        scheduler.delay_host("example.com", Duration::from_millis(150));
+
+        let start = Instant::now();
+        drop(scheduler.acquire("example.com", None).await);
+
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    /// hosts that were requested long enough ago are forgotten, delayed and busy hosts are kept
+    #[tokio::test]
+    async fn forgets_idle_hosts() {
+        let scheduler = HostScheduler::new(Duration::ZERO, 1);
+
+        scheduler.delay_host("delayed.com", Duration::from_secs(60));
+        let busy = scheduler.acquire("busy.com", None).await;
+
+        for i in 0..2 * PRUNE_AT {
+            drop(scheduler.acquire(&format!("{i}.example.com"), None).await);
+        }
+
+        let hosts = scheduler.hosts.lock().unwrap();
+
+        assert!(hosts.len() <= PRUNE_AT);
+        assert!(hosts.contains_key("delayed.com"));
+        assert_eq!(hosts["busy.com"].active, 1);
+        drop(hosts);
+        drop(busy);
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use regex::Regex;
+use url::Url;
+
+use crate::{
+    db_manager::{
+        creation::DatabaseConnection,
+        selecting::{count_domain_pages, count_pages, get_depth, page_exists},
+    },
+    page_scraper::html_parser::Link,
+};
+
+use super::scheduler::host_of;
+
+/// Limits which of the found links are stored as pages (and therefore crawled)
+///
+/// links to pages outside of the scope aren't stored in the link graph either
+#[derive(Debug, Clone)]
+pub struct CrawlScope {
+    /// pages further than this many links away from a start url aren't stored
+    pub max_depth: Option<u32>,
+    /// no new pages are stored once the database contains this many pages
+    pub max_pages: Option<usize>,
+    /// no new pages of a site are stored once the database contains this many pages of it (see `site_budget`)
+    pub max_pages_per_host: Option<usize>,
+    /// the budgets of domains (and their subdomains) that replace `max_pages_per_host`
+    pub domain_budgets: Vec<DomainBudget>,
+    /// only pages of these domains and their subdomains are stored, all domains if empty
+    pub allow_domains: Vec<String>,
+    /// pages of these domains and their subdomains are never stored
+    pub deny_domains: Vec<String>,
+    /// only urls matching at least one of these patterns are stored, all urls if empty
+    pub include: Vec<Regex>,
+    /// urls matching one of these patterns are never stored
+    pub exclude: Vec<Regex>,
+    /// the schemes of the urls that are stored (e.g. `mailto` links are skipped by default)
+    pub schemes: Vec<String>,
+}
+
+impl Default for CrawlScope {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            max_pages: None,
+            max_pages_per_host: None,
+            domain_budgets: vec![],
+            allow_domains: vec![],
+            deny_domains: vec![],
+            include: vec![],
+            exclude: vec![],
+            schemes: vec!["http".to_string(), "https".to_string()],
+        }
+    }
+}
+
+/// The most pages of a domain and its subdomains that are stored, `ethz.ch=500` on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainBudget {
+    pub domain: String,
+    pub max_pages: usize,
+}
+
+impl FromStr for DomainBudget {
+    type Err = String;
+
+    fn from_str(budget: &str) -> Result<Self, Self::Err> {
+        let (domain, max_pages) = budget
+            .split_once('=')
+            .ok_or_else(|| format!("invalid budget {budget} (expected DOMAIN=PAGES)"))?;
+        let max_pages = max_pages
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid amount of pages in {budget}"))?;
+
+        Ok(Self {
+            domain: domain_name(domain),
+            max_pages,
+        })
+    }
+}
+
+impl CrawlScope {
+    /// checks the scheme, domain and patterns of the url, the depth and budgets are checked by `filter_links`
+    pub fn allows(&self, url: &str) -> bool {
+        let parsed = match Url::parse(url) {
+            Ok(parsed) => parsed,
+            Err(_) => return false,
+        };
+
+        if !self
+            .schemes
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(parsed.scheme()))
+        {
+            return false;
+        }
+
+        let host = parsed.host_str().unwrap_or_default().to_lowercase();
+
+        if !self.allow_domains.is_empty()
+            && !self
+                .allow_domains
+                .iter()
+                .any(|domain| in_domain(&host, domain))
+        {
+            return false;
+        }
+
+        if self
+            .deny_domains
+            .iter()
+            .any(|domain| in_domain(&host, domain))
+        {
+            return false;
+        }
+
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(url)))
+            && !self.exclude.iter().any(|pattern| pattern.is_match(url))
+    }
+
+    /// returns the links of the page `from_id` that may be stored
+    ///
+    /// links to pages that are already stored are kept as long as the url is allowed,
+    /// new pages also have to be within the depth and the page budgets
+    pub fn filter_links(
+        &self,
+        conn: &DatabaseConnection,
+        from_id: i64,
+        links: Vec<Link>,
+    ) -> Result<Vec<Link>, rusqlite::Error> {
+        let depth = get_depth(conn, from_id)?;
+
+        // only counted if there is a budget
+        let mut pages = match self.max_pages {
+            Some(_) => count_pages(conn)?,
+            None => 0,
+        };
+        let mut site_pages: HashMap<String, usize> = HashMap::new();
+        let mut seen = HashSet::new();
+
+        let mut allowed = vec![];
+
+        for link in links {
+            // a page that is linked twice is only stored (and counted) once
+            if !seen.insert(link.href.clone()) || !self.allows(&link.href) {
+                continue;
+            }
+
+            if page_exists(conn, &link.href)? {
+                allowed.push(link);
+                continue;
+            }
+
+            // following a redirect doesn't lead deeper
+            let link_depth = depth + u32::from(!link.is_redirect());
+
+            if self
+                .max_depth
+                .is_some_and(|max_depth| link_depth > max_depth)
+                || self.max_pages.is_some_and(|max_pages| pages >= max_pages)
+            {
+                continue;
+            }
+
+            if let Some((site, max_site_pages)) = self.site_budget(&link.href) {
+                let count = match site_pages.get(&site) {
+                    Some(count) => *count,
+                    None => count_domain_pages(conn, &site)?,
+                };
+
+                if count >= max_site_pages {
+                    continue;
+                }
+
+                site_pages.insert(site, count + 1);
+            }
+
+            pages += 1;
+            allowed.push(link);
+        }
+
+        Ok(allowed)
+    }
+
+    /// returns the site the url's pages are counted for and how many pages it may have
+    ///
+    /// the site is the most specific domain with a budget, then the most specific allowed domain,
+    /// otherwise the host without `www.` (there is no list of public suffixes to find the registrable domain),
+    /// the pages of a site are the pages of its domain and all subdomains
+    fn site_budget(&self, url: &str) -> Option<(String, usize)> {
+        let host = host_of(url).unwrap_or_default();
+
+        let budget = self
+            .domain_budgets
+            .iter()
+            .filter(|budget| in_domain(&host, &budget.domain))
+            .max_by_key(|budget| budget.domain.len());
+
+        if let Some(budget) = budget {
+            return Some((budget.domain.clone(), budget.max_pages));
+        }
+
+        let max_pages = self.max_pages_per_host?;
+
+        let site = self
+            .allow_domains
+            .iter()
+            .map(|domain| domain_name(domain))
+            .filter(|domain| in_domain(&host, domain))
+            .max_by_key(|domain| domain.len())
+            .unwrap_or_else(|| host.strip_prefix("www.").unwrap_or(&host).to_string());
+
+        Some((site, max_pages))
+    }
+}
+
+/// true if the host is the domain or one of its subdomains, `ethz.ch` contains `www.ethz.ch` but not `eth.ch`
+fn in_domain(host: &str, domain: &str) -> bool {
+    let domain = domain_name(domain);
+
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
+/// `.ETHZ.ch` is written `ethz.ch`
+fn domain_name(domain: &str) -> String {
+    domain.trim().trim_start_matches('.').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use regex::Regex;
+
+    use crate::{
+        db_manager::{
+            creation::{add_links, create_default_tables},
+            helper::gen_random_path,
+            selecting::get_depth,
+        },
+        page_scraper::html_parser::Link,
+    };
+
+    use super::{in_domain, CrawlScope, DomainBudget};
+
+    fn link(href: &str) -> Link {
+        Link {
+            href: href.to_string(),
+            text: String::new(),
+            rel: None,
+        }
+    }
+
+    fn hrefs(links: &[Link]) -> Vec<&str> {
+        links.iter().map(|link| link.href.as_str()).collect()
+    }
+
+    #[test]
+    fn domains() {
+        assert!(in_domain("ethz.ch", "ethz.ch"));
+        assert!(in_domain("www.ethz.ch", ".ethz.ch"));
+        assert!(in_domain("www.ethz.ch", "ETHZ.ch"));
+        assert!(!in_domain("eth.ch", "ethz.ch"));
+        assert!(!in_domain("notethz.ch", "ethz.ch"));
+    }
+
+    #[test]
+    fn allows_urls() {
+        let scope = CrawlScope {
+            allow_domains: vec!["ethz.ch".to_string()],
+            deny_domains: vec!["intranet.ethz.ch".to_string()],
+            exclude: vec![Regex::new(r"\.pdf$").unwrap()],
+            ..Default::default()
+        };
+
+        assert!(scope.allows("https://www.ethz.ch/"));
+        assert!(!scope.allows("https://www.uzh.ch/"));
+        assert!(!scope.allows("https://a.intranet.ethz.ch/"));
+        assert!(!scope.allows("https://www.ethz.ch/paper.pdf"));
+        assert!(!scope.allows("mailto:info@ethz.ch"));
+        assert!(!scope.allows("ftp://ethz.ch/"));
+
+        let include = CrawlScope {
+            include: vec![Regex::new("/de/").unwrap()],
+            schemes: vec!["http".to_string()],
+            ..Default::default()
+        };
+
+        assert!(include.allows("http://ethz.ch/de/"));
+        assert!(!include.allows("http://ethz.ch/en/"));
+        assert!(!include.allows("https://ethz.ch/de/"));
+        assert!(CrawlScope::default().allows("https://wa.link/abc"));
+    }
+
+    #[test]
+    fn limits_depth_and_budgets() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        conn.connection
+            .execute(
+                "INSERT INTO Ranking (url, host, reversed_host) VALUES ('http://a.ch/', 'a.ch', 'ch.a.');",
+                (),
+            )
+            .unwrap();
+
+        let links = vec![
+            link("http://a.ch/1"),
+            link("http://a.ch/2"),
+            link("http://a.ch/3"),
+            link("http://b.ch/"),
+            link("mailto:a@a.ch"),
+        ];
+
+        let per_host = CrawlScope {
+            max_pages_per_host: Some(2),
+            ..Default::default()
+        };
+        let allowed = per_host.filter_links(&conn, 1, links.clone()).unwrap();
+        assert_eq!(hrefs(&allowed), vec!["http://a.ch/1", "http://b.ch/"]);
+
+        let budget = CrawlScope {
+            max_pages: Some(3),
+            ..Default::default()
+        };
+        let allowed = budget.filter_links(&conn, 1, links.clone()).unwrap();
+        assert_eq!(hrefs(&allowed), vec!["http://a.ch/1", "http://a.ch/2"]);
+
+        add_links(&conn, 1, &allowed, false).unwrap();
+        assert_eq!(get_depth(&conn, 2).unwrap(), 1);
+
+        // known pages are still linked once the budget is used up
+        let allowed = budget.filter_links(&conn, 1, links.clone()).unwrap();
+        assert_eq!(hrefs(&allowed), vec!["http://a.ch/1", "http://a.ch/2"]);
+
+        let depth = CrawlScope {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            depth.filter_links(&conn, 1, links.clone()).unwrap().len(),
+            4
+        );
+        assert_eq!(
+            hrefs(&depth.filter_links(&conn, 2, links).unwrap()),
+            vec!["http://a.ch/1", "http://a.ch/2"]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    /// a link that appears twice doesn't use up the budget twice
+    #[test]
+    fn counts_duplicates_once() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        conn.connection
+            .execute(
+                "INSERT INTO Ranking (url, host, reversed_host) VALUES ('http://a.ch/', 'a.ch', 'ch.a.');",
+                (),
+            )
+            .unwrap();
+
+        let links = vec![
+            link("http://a.ch/1"),
+            link("http://a.ch/1"),
+            link("http://a.ch/2"),
+        ];
+        let scope = CrawlScope {
+            max_pages: Some(3),
+            max_pages_per_host: Some(3),
+            ..Default::default()
+        };
+        let allowed = scope.filter_links(&conn, 1, links).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(hrefs(&allowed), vec!["http://a.ch/1", "http://a.ch/2"]);
+    }
+
+    /// `www.ethz.ch` and `ethz.ch` share a budget, domains can have their own budget
+    #[test]
+    fn budgets_per_site() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        conn.connection
+            .execute(
+                "INSERT INTO Ranking (url, host, reversed_host) VALUES ('http://ethz.ch/', 'ethz.ch', 'ch.ethz.'), ('http://people.ethz.ch/', 'people.ethz.ch', 'ch.ethz.people.');",
+                (),
+            )
+            .unwrap();
+
+        let links = vec![
+            link("http://www.ethz.ch/1"),
+            link("http://ethz.ch/2"),
+            link("http://uzh.ch/1"),
+            link("http://www.uzh.ch/2"),
+            link("http://www.uzh.ch/3"),
+        ];
+
+        let per_host = CrawlScope {
+            max_pages_per_host: Some(2),
+            ..Default::default()
+        };
+        // people.ethz.ch counts towards ethz.ch
+        assert_eq!(
+            hrefs(&per_host.filter_links(&conn, 1, links.clone()).unwrap()),
+            vec!["http://uzh.ch/1", "http://www.uzh.ch/2"]
+        );
+
+        let allowed_domain = CrawlScope {
+            max_pages_per_host: Some(3),
+            allow_domains: vec![".ETHZ.ch".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            hrefs(
+                &allowed_domain
+                    .filter_links(&conn, 1, links.clone())
+                    .unwrap()
+            ),
+            vec!["http://www.ethz.ch/1"]
+        );
+
+        let per_domain = CrawlScope {
+            max_pages_per_host: Some(1),
+            domain_budgets: vec![
+                "ethz.ch=4".parse().unwrap(),
+                "people.ethz.ch = 1".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            hrefs(&per_domain.filter_links(&conn, 1, links).unwrap()),
+            vec![
+                "http://www.ethz.ch/1",
+                "http://ethz.ch/2",
+                "http://uzh.ch/1"
+            ]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parses_domain_budgets() {
+        assert_eq!(
+            ".ETHZ.ch=500".parse::<DomainBudget>().unwrap(),
+            DomainBudget {
+                domain: "ethz.ch".to_string(),
+                max_pages: 500,
+            }
+        );
+        assert!("ethz.ch".parse::<DomainBudget>().is_err());
+        assert!("ethz.ch=many".parse::<DomainBudget>().is_err());
+    }
+}
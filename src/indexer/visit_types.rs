@@ -1,63 +1,40 @@
 use std::sync::{Arc, Mutex};
 
-use crate::db_manager::{creation::DatabaseConnection, updating::set_in_use};
+use crate::db_manager::{creation::DatabaseConnection, lease::release_page};
 
+/// A page that is leased by a crawler, the lease is released when it is dropped
 pub struct ToVisit {
     pub url: String,
     pub id: i64,
+    /// the crawler the page is leased by, see `Lease`
+    pub owner: String,
     pub connection: Arc<Mutex<DatabaseConnection>>,
 }
 
 impl ToVisit {
-    /// Panics if Mutex error
+    /// the page has to be claimed already (e.g. by `claim_url`)
     pub fn new(
         url: &str,
         id: i64,
+        owner: &str,
         connection: Arc<Mutex<DatabaseConnection>>,
-    ) -> Result<Self, rusqlite::Error> {
-        set_in_use(&connection.lock().unwrap(), id, true)?;
-
-        Ok(Self {
+    ) -> Self {
+        Self {
             url: url.to_string(),
             id,
+            owner: owner.to_string(),
             connection,
-        })
+        }
     }
 }
 
 impl Drop for ToVisit {
-    /// Panics if database execution didn't work
+    /// a page that can't be released (e.g. the database is busy) stays claimed until its lease expires
+    ///
+    /// Panics if another thread panicked while holding the connection
     fn drop(&mut self) {
-        set_in_use(&self.connection.lock().unwrap(), self.id, false).unwrap();
-    }
-}
-
-#[derive(Debug)]
-pub struct Visited {
-    url: String,
-    id: i64,
-}
-
-impl Visited {
-    pub fn new(visited: ToVisit) -> Self {
-        Self {
-            url: visited.url.clone(),
-            id: visited.id,
-        }
-    }
-}
-
-impl From<ToVisit> for Visited {
-    fn from(visited: ToVisit) -> Self {
-        Self::new(visited)
-    }
-}
-
-impl From<&Visited> for Visited {
-    fn from(visited: &Visited) -> Self {
-        Self {
-            url: visited.url.clone(),
-            id: visited.id,
+        if let Err(err) = release_page(&self.connection.lock().unwrap(), self.id, &self.owner) {
+            eprintln!("Couldn't release {}: {err}", self.url);
         }
     }
 }
@@ -0,0 +1,330 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    db_manager::{
+        creation::{add_links, add_redirects, canonical_page, DatabaseConnection},
+        lease::Lease,
+        outcome::{FetchOutcome, Outcome},
+        postings::index_page,
+        selecting::get_retries,
+        updating::{
+            schedule_retry, set_outcome, update_to_failed, update_to_redirected, update_to_visited,
+        },
+    },
+    indexer::{retry::backoff, scope::CrawlScope, visit_types::ToVisit},
+    page_scraper::html_parser::{Link, PageFields},
+};
+
+/// A change to the database that a fetch task hands to the writer
+///
+/// the lease on the page (`ToVisit`) is released after the change is stored,
+/// so the page can't be claimed again before it is marked as visited
+pub enum Write {
+    /// the page was fetched, its content, fields, terms and links are stored
+    Visited {
+        page: ToVisit,
+        content: String,
+        content_type: Option<String>,
+        /// extracted by the fetch task, so the writer doesn't parse the page while it holds the lock
+        fields: PageFields,
+        links: Vec<Link>,
+        terms: Vec<String>,
+    },
+    /// the page couldn't be fetched, transient errors are retried later (at most `max_retries` times)
+    Failed {
+        page: ToVisit,
+        outcome: FetchOutcome,
+        transient: bool,
+        /// the host asked to wait at least this long (`Retry-After`)
+        retry_after: Option<Duration>,
+    },
+    /// the page redirected to `final_url`, the page the content is stored on is claimed and sent back
+    Redirected {
+        page: ToVisit,
+        redirects: Vec<String>,
+        final_url: String,
+        canonical: oneshot::Sender<Option<ToVisit>>,
+    },
+}
+
+impl Write {
+    /// the page the write belongs to
+    pub fn page(&self) -> &ToVisit {
+        match self {
+            Write::Visited { page, .. }
+            | Write::Failed { page, .. }
+            | Write::Redirected { page, .. } => page,
+        }
+    }
+}
+
+/// starts the task that stores the results of the fetch tasks, it stops once all senders are dropped
+///
+/// at most `capacity` writes wait to be stored, fetch tasks wait when the queue is full,
+/// only links within the `scope` are stored, the targets of redirects are claimed for the `lease`
+pub fn spawn_writer(
+    conn: Arc<Mutex<DatabaseConnection>>,
+    capacity: usize,
+    max_retries: u32,
+    scope: CrawlScope,
+    lease: Lease,
+    verbose: bool,
+) -> (mpsc::Sender<Write>, JoinHandle<()>) {
+    let (sender, mut receiver) = mpsc::channel::<Write>(capacity.max(1));
+
+    // sqlite blocks, so the writer gets its own thread instead of a worker of the runtime
+    let handle = tokio::task::spawn_blocking(move || {
+        while let Some(write) = receiver.blocking_recv() {
+            let url = write.page().url.clone();
+
+            // the page is crawled again once it can be claimed, the other results are still stored
+            if let Err(err) = apply(&conn, write, max_retries, &scope, &lease, verbose) {
+                eprintln!("Couldn't store the result of {url}: {err}");
+            }
+        }
+    });
+
+    (sender, handle)
+}
+
+/// stores the write, the leased page is only released after the lock is given back (see `ToVisit`)
+///
+/// stops at the first error, a redirected page then gets no canonical page
+fn apply(
+    conn: &Arc<Mutex<DatabaseConnection>>,
+    write: Write,
+    max_retries: u32,
+    scope: &CrawlScope,
+    lease: &Lease,
+    verbose: bool,
+) -> Result<(), rusqlite::Error> {
+    match write {
+        Write::Visited {
+            page,
+            content,
+            content_type,
+            fields,
+            links,
+            terms,
+        } => {
+            let lock = conn.lock().unwrap();
+            // the page is either stored completely or not at all
+            let transaction = lock.connection.unchecked_transaction()?;
+
+            // another crawler visits the page since the lease expired, the transaction is rolled back
+            if !update_to_visited(
+                &lock,
+                page.id,
+                &page.owner,
+                &content,
+                links.iter().map(|link| link.href.as_str()).collect(),
+                &fields,
+                verbose,
+            )? {
+                return Ok(());
+            }
+
+            index_page(&lock.connection, page.id, &terms)?;
+
+            set_outcome(
+                &lock,
+                page.id,
+                &FetchOutcome {
+                    status: Some(200),
+                    content_type,
+                    ..FetchOutcome::new(Outcome::Ok)
+                },
+            )?;
+
+            // add newly found links within the scope to database
+            let links = scope.filter_links(&lock, page.id, links)?;
+            add_links(&lock, page.id, &links, verbose)?;
+
+            transaction.commit()?;
+            drop(lock);
+            drop(page);
+        }
+        Write::Failed {
+            page,
+            outcome,
+            transient,
+            retry_after,
+        } => {
+            let lock = conn.lock().unwrap();
+
+            // transient errors are retried later with a growing delay
+            let retries = match transient {
+                true => get_retries(&lock, page.id)?,
+                false => u32::MAX,
+            };
+
+            if retries < max_retries {
+                let delay = backoff(retries).max(retry_after.unwrap_or_default());
+
+                if schedule_retry(
+                    &lock,
+                    page.id,
+                    &page.owner,
+                    SystemTime::now() + delay,
+                    verbose,
+                )?
+                .is_some()
+                {
+                    set_outcome(&lock, page.id, &outcome)?;
+                }
+            } else {
+                // links doesn't work so just ignore it
+                update_to_failed(&lock, page.id, &page.owner, &outcome, verbose)?;
+            }
+
+            drop(lock);
+            drop(page);
+        }
+        Write::Redirected {
+            page,
+            redirects,
+            final_url,
+            canonical,
+        } => {
+            let in_scope = {
+                let lock = conn.lock().unwrap();
+
+                if !update_to_redirected(&lock, page.id, &page.owner, &final_url, verbose)? {
+                    drop(lock);
+                    drop(page);
+
+                    // another crawler visits the page since the lease expired
+                    canonical.send(None).ok();
+                    return Ok(());
+                }
+
+                add_redirects(&lock, &redirects, &final_url)?;
+
+                // keeps the graph connected, links to the redirecting page lead to the target
+                let redirect = Link {
+                    href: final_url.clone(),
+                    text: String::new(),
+                    rel: Some("redirect".to_string()),
+                };
+                let redirect = scope.filter_links(&lock, page.id, vec![redirect])?;
+                add_links(&lock, page.id, &redirect, verbose)?;
+
+                !redirect.is_empty()
+            };
+            drop(page);
+
+            if !in_scope {
+                if verbose {
+                    println!("Skipping {final_url} (outside of the crawl scope)");
+                }
+
+                canonical.send(None).ok();
+                return Ok(());
+            }
+
+            // `None` if it's already crawled (or being crawled) under its own url
+            let page = canonical_page(Arc::clone(conn), &final_url, lease, verbose)?;
+
+            // the fetch task only stops waiting for the page if it was cancelled
+            canonical.send(page).ok();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{
+        db_manager::{
+            creation::create_default_tables,
+            helper::{gen_random_path, gen_vals, lease_page},
+            lease::Lease,
+            outcome::{FetchOutcome, Outcome},
+        },
+        indexer::{scope::CrawlScope, visit_types::ToVisit},
+        page_scraper::html_parser::PageFields,
+    };
+
+    use super::{spawn_writer, Write};
+
+    /// a write that can't be stored is rolled back and doesn't stop the writer
+    #[tokio::test]
+    async fn keeps_storing_after_error() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+        // indexing the terms of a visited page fails
+        conn.connection.execute("DROP TABLE Postings;", ()).unwrap();
+
+        let lease = Lease::new(Duration::from_secs(60));
+        lease_page(&conn, 1, &lease.owner);
+        lease_page(&conn, 2, &lease.owner);
+
+        let conn = Arc::new(Mutex::new(conn));
+        let (writer, handle) = spawn_writer(
+            Arc::clone(&conn),
+            1,
+            0,
+            CrawlScope::default(),
+            lease.clone(),
+            false,
+        );
+        let page = |id: i64, url: &str| ToVisit::new(url, id, &lease.owner, Arc::clone(&conn));
+
+        let visited = writer
+            .send(Write::Visited {
+                page: page(1, "test.ch"),
+                content: "<p>Team</p>".to_string(),
+                content_type: None,
+                fields: PageFields::default(),
+                links: vec![],
+                terms: vec!["team".to_string()],
+            })
+            .await;
+        let failed = writer
+            .send(Write::Failed {
+                page: page(2, "help.ch"),
+                outcome: FetchOutcome::new(Outcome::Timeout),
+                transient: false,
+                retry_after: None,
+            })
+            .await;
+
+        assert!(visited.is_ok() && failed.is_ok());
+        drop(writer);
+        handle.await.unwrap();
+
+        let lock = conn.lock().unwrap();
+        let outcome = |id: i64| {
+            lock.connection
+                .query_row("SELECT outcome FROM Ranking WHERE id = ?1;", [id], |row| {
+                    row.get::<usize, Option<String>>(0)
+                })
+                .unwrap()
+        };
+        let (visited_outcome, failed_outcome) = (outcome(1), outcome(2));
+        drop(lock);
+
+        fs::remove_file(path).unwrap();
+
+        // the visited page wasn't stored halfway
+        assert_eq!(visited_outcome, None);
+        assert_eq!(failed_outcome.as_deref(), Some("timeout"));
+    }
+}
@@ -4,5 +4,12 @@ mod db_manager;
 mod indexer;
 mod page_rank;
 mod page_scraper;
+mod robots;
 
-pub use indexer::loops::run;
+pub use db_manager::control::CrawlState;
+pub use indexer::{
+    config::CrawlConfig,
+    control::CrawlControl,
+    loops::{run, run_with_control},
+    scope::CrawlScope,
+};
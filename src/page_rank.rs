@@ -1,2 +1,8 @@
+pub mod bm25;
 mod helper;
+pub mod hits;
+pub mod pagerank;
+pub mod query;
 pub mod ranker;
+pub mod snippet;
+pub mod tokenizer;
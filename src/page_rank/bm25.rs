@@ -0,0 +1,80 @@
+/// Parameters of the BM25 relevance function
+#[derive(Debug, Clone)]
+pub struct Bm25Config {
+    /// how fast repeated occurrences of a term stop adding to the score
+    pub k1: f64,
+    /// how much long pages are penalized (0 = not at all, 1 = fully normalized by length)
+    pub b: f64,
+}
+
+impl Default for Bm25Config {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+/// inverse document frequency of a term that appears in `document_frequency` of `documents` pages
+///
+/// uses the `ln(1 + ...)` variant, so terms in more than half of the pages still score positive
+pub fn idf(documents: u64, document_frequency: u64) -> f64 {
+    let documents = documents as f64;
+    let document_frequency = document_frequency as f64;
+
+    (1.0 + (documents - document_frequency + 0.5) / (document_frequency + 0.5)).ln()
+}
+
+/// score of a single term that appears `frequency` times in a page with `length` terms
+pub fn term_score(
+    frequency: u32,
+    length: u64,
+    average_length: f64,
+    idf: f64,
+    config: &Bm25Config,
+) -> f64 {
+    let frequency = frequency as f64;
+    let relative_length = if average_length > 0.0 {
+        length as f64 / average_length
+    } else {
+        1.0
+    };
+
+    idf * frequency * (config.k1 + 1.0)
+        / (frequency + config.k1 * (1.0 - config.b + config.b * relative_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{idf, term_score, Bm25Config};
+
+    #[test]
+    fn rare_terms_weigh_more() {
+        assert!(idf(100, 1) > idf(100, 50));
+        assert!(idf(100, 100) > 0.0);
+    }
+
+    #[test]
+    fn frequency_saturates() {
+        let config = Bm25Config::default();
+
+        let once = term_score(1, 100, 100.0, 1.0, &config);
+        let twice = term_score(2, 100, 100.0, 1.0, &config);
+        let often = term_score(100, 100, 100.0, 1.0, &config);
+
+        assert!(twice > once);
+        assert!(often < config.k1 + 1.0);
+    }
+
+    #[test]
+    fn long_pages_are_penalized() {
+        let config = Bm25Config::default();
+
+        assert!(term_score(1, 50, 100.0, 1.0, &config) > term_score(1, 1000, 100.0, 1.0, &config));
+
+        // without length normalization the length doesn't matter
+        let config = Bm25Config { b: 0.0, ..config };
+        assert_eq!(
+            term_score(1, 50, 100.0, 1.0, &config),
+            term_score(1, 1000, 100.0, 1.0, &config)
+        );
+    }
+}
@@ -1,32 +1,16 @@
-pub fn compute_rank(
-    link_to_count: usize,
-    link_from_count: usize,
-    search_word_appearance: usize,
-    has_whole_word: bool,
-) -> usize {
-    let mut res = link_from_count + link_to_count + search_word_appearance;
-
-    if has_whole_word {
-        res += 10
-    }
-
-    res
-}
-
-pub fn compute_search_word_appearance(search_word: &str, string: &str) -> usize {
-    let search_word = search_word.to_lowercase();
-    let string = string.to_lowercase();
-    let parts = search_word.split(" ").collect::<Vec<_>>();
-
-    let mut appearance = 0;
-
-    for part in parts {
-        let matches = string.matches(part).collect::<Vec<_>>();
-
-        appearance += matches.len();
-    }
-
-    appearance
+/// how much the link analysis can raise the relevance of a page
+const LINK_SCORE_WEIGHT: f64 = 0.5;
+
+/// combines the relevance of a page with its link analysis score
+///
+/// `score = relevance * (1 + LINK_SCORE_WEIGHT * ln(1 + link_score))`
+///
+/// `relevance` is the BM25 score of the page for the query and `link_score` is the pagerank or hits authority
+/// of the page relative to the average page (1.0 is average, 0.0 if it hasn't been computed).
+/// The link score only scales the relevance, so pages that don't match the query stay at 0 and
+/// the logarithm keeps pages with a huge pagerank from outranking better matches.
+pub fn combine_scores(relevance: f64, link_score: f64) -> f64 {
+    relevance * (1.0 + LINK_SCORE_WEIGHT * link_score.max(0.0).ln_1p())
 }
 
 #[cfg(test)]
@@ -34,20 +18,18 @@ mod tests {
     use crate::page_rank::helper::*;
 
     #[test]
-    fn test_rank_computation_normal() {
-        assert!(compute_rank(8, 10, 20, true) > compute_rank(1, 0, 2, false));
+    fn link_score_raises_relevance() {
+        assert!(combine_scores(2.0, 3.0) > combine_scores(2.0, 0.5));
+        assert_eq!(combine_scores(2.0, 0.0), 2.0);
     }
 
     #[test]
-    fn test_rank_computation_zero() {
-        assert!(compute_rank(0, 0, 0, false) < compute_rank(999, 999, 999, true));
+    fn irrelevant_pages_stay_irrelevant() {
+        assert_eq!(combine_scores(0.0, 999.0), 0.0);
     }
 
     #[test]
-    fn appearance_counter() {
-        let search_word = "where do we go?";
-        let content = "We go to the church. There we live. I don't know where we should go.";
-
-        assert_eq!(compute_search_word_appearance(search_word, content), 5);
+    fn relevance_outweighs_links() {
+        assert!(combine_scores(4.0, 0.0) > combine_scores(1.0, 50.0));
     }
 }
@@ -0,0 +1,192 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::db_manager::{
+    creation::DatabaseConnection,
+    selecting::{get_inlink_ids, get_outlink_ids},
+};
+
+/// how many pages linking to a result are added to the subgraph (Kleinberg's `d`)
+const MAX_INLINKS_PER_PAGE: usize = 50;
+
+/// Settings of the hits computation
+#[derive(Debug, Clone)]
+pub struct HitsConfig {
+    /// the computation stops once the scores change less than this in total (L1 norm)
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for HitsConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-6,
+            max_iterations: 100,
+        }
+    }
+}
+
+/// Hub and authority scores of a graph, both normalized to sum up to 1
+#[derive(Debug)]
+pub struct HitsScores {
+    /// how good a page is at pointing to good authorities
+    pub hubs: Vec<f64>,
+    /// how much good hubs point to a page
+    pub authorities: Vec<f64>,
+    #[allow(dead_code)]
+    pub iterations: usize,
+    #[allow(dead_code)]
+    pub converged: bool,
+}
+
+/// computes the hub and authority scores of `page_count` pages connected by `links` (from, to)
+pub fn hits(page_count: usize, links: &[(usize, usize)], config: &HitsConfig) -> HitsScores {
+    let mut hubs = vec![1.0 / page_count as f64; page_count];
+    let mut authorities = hubs.clone();
+    let mut iterations = 0;
+    let mut converged = page_count == 0;
+
+    while iterations < config.max_iterations && !converged {
+        let mut next_authorities = vec![0.0; page_count];
+        for &(from, to) in links {
+            next_authorities[to] += hubs[from];
+        }
+        normalize(&mut next_authorities);
+
+        let mut next_hubs = vec![0.0; page_count];
+        for &(from, to) in links {
+            next_hubs[from] += next_authorities[to];
+        }
+        normalize(&mut next_hubs);
+
+        let change = difference(&authorities, &next_authorities) + difference(&hubs, &next_hubs);
+
+        authorities = next_authorities;
+        hubs = next_hubs;
+        iterations += 1;
+        converged = change < config.tolerance;
+    }
+
+    HitsScores {
+        hubs,
+        authorities,
+        iterations,
+        converged,
+    }
+}
+
+/// computes hits on the subgraph around the result pages
+///
+/// the subgraph contains the results, the pages they link to and (some of) the pages linking to them,
+/// returns the scores by page id
+pub fn focused_hits(
+    conn: &DatabaseConnection,
+    result_ids: &[i64],
+    config: &HitsConfig,
+) -> Result<HashMap<i64, (f64, f64)>, rusqlite::Error> {
+    let mut base = result_ids.iter().copied().collect::<BTreeSet<_>>();
+
+    for &id in result_ids {
+        base.extend(get_outlink_ids(conn, id)?);
+        base.extend(get_inlink_ids(conn, id, MAX_INLINKS_PER_PAGE)?);
+    }
+
+    let ids = base.into_iter().collect::<Vec<_>>();
+    let index: HashMap<i64, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+
+    let mut links = vec![];
+    for (from, id) in ids.iter().enumerate() {
+        for to in get_outlink_ids(conn, *id)? {
+            if let Some(&to) = index.get(&to) {
+                links.push((from, to));
+            }
+        }
+    }
+
+    let scores = hits(ids.len(), &links, config);
+
+    Ok(ids
+        .into_iter()
+        .zip(scores.hubs.into_iter().zip(scores.authorities))
+        .collect())
+}
+
+/// scales the scores so they sum up to 1, all zero scores are left as they are
+fn normalize(scores: &mut [f64]) {
+    let sum: f64 = scores.iter().sum();
+
+    if sum > 0.0 {
+        scores.iter_mut().for_each(|score| *score /= sum);
+    }
+}
+
+fn difference(left: &[f64], right: &[f64]) -> f64 {
+    left.iter().zip(right).map(|(l, r)| (l - r).abs()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::db_manager::{
+        creation::create_default_tables,
+        helper::{gen_random_path, gen_vals},
+    };
+
+    use super::{focused_hits, hits, HitsConfig};
+
+    #[test]
+    fn hubs_and_authorities() {
+        // 0 and 1 link to 2 and 3, 3 links to 2
+        let result = hits(
+            4,
+            &[(0, 2), (0, 3), (1, 2), (1, 3), (3, 2)],
+            &HitsConfig::default(),
+        );
+
+        assert!(result.converged);
+        assert!(result.authorities[2] > result.authorities[3]);
+        assert!(result.authorities[3] > result.authorities[0]);
+        assert!(result.hubs[0] > result.hubs[3]);
+        assert_eq!(result.hubs[0], result.hubs[1]);
+        assert_eq!(result.hubs[2], 0.0);
+    }
+
+    #[test]
+    fn empty_graph() {
+        let result = hits(0, &[], &HitsConfig::default());
+
+        assert!(result.hubs.is_empty());
+        assert!(result.authorities.is_empty());
+    }
+
+    #[test]
+    fn graph_without_links() {
+        let result = hits(2, &[], &HitsConfig::default());
+
+        assert_eq!(result.authorities, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn focused_subgraph() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        // help.ch, linked by p.ch, ep.ch and lp.ch and linking to test.ch
+        let scores = focused_hits(&conn, &[2], &HitsConfig::default()).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        let mut ids = scores.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+
+        // help.ch is the best authority, the pages linking to it are the best hubs
+        let (_, help_authority) = scores[&2];
+        assert!(scores
+            .values()
+            .all(|(_, authority)| *authority <= help_authority));
+        assert!(scores[&3].0 > scores[&2].0);
+    }
+}
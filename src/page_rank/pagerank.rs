@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use crate::db_manager::{
+    creation::DatabaseConnection,
+    selecting::{get_graph_edges, get_page_ids},
+    updating::update_pageranks,
+};
+
+/// Settings of the pagerank computation
+#[derive(Debug, Clone)]
+pub struct PageRankConfig {
+    /// probability that a surfer follows a link instead of jumping to a random page
+    pub damping: f64,
+    /// the computation stops once the ranks change less than this in total (L1 norm)
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for PageRankConfig {
+    fn default() -> Self {
+        Self {
+            damping: 0.85,
+            tolerance: 1e-6,
+            max_iterations: 100,
+        }
+    }
+}
+
+/// Result of a pagerank computation
+#[derive(Debug)]
+pub struct PageRanks {
+    /// rank of every page, the ranks sum up to 1
+    pub ranks: Vec<f64>,
+    pub iterations: usize,
+    /// the ranks changed less than the tolerance before the iteration limit was reached
+    pub converged: bool,
+}
+
+/// computes the pagerank of `page_count` pages connected by `links` (from, to) with the power iteration
+///
+/// the rank of pages without links (dangling pages) is spread evenly over all pages
+pub fn pagerank(page_count: usize, links: &[(usize, usize)], config: &PageRankConfig) -> PageRanks {
+    if page_count == 0 {
+        return PageRanks {
+            ranks: vec![],
+            iterations: 0,
+            converged: true,
+        };
+    }
+
+    let n = page_count as f64;
+
+    let mut out_degree = vec![0usize; page_count];
+    for &(from, _) in links {
+        out_degree[from] += 1;
+    }
+
+    let mut ranks = vec![1.0 / n; page_count];
+    let mut iterations = 0;
+    let mut converged = false;
+
+    while iterations < config.max_iterations && !converged {
+        let dangling: f64 = ranks
+            .iter()
+            .zip(&out_degree)
+            .filter(|(_, &degree)| degree == 0)
+            .map(|(rank, _)| rank)
+            .sum();
+
+        let base = (1.0 - config.damping) / n + config.damping * dangling / n;
+        let mut next = vec![base; page_count];
+
+        for &(from, to) in links {
+            next[to] += config.damping * ranks[from] / out_degree[from] as f64;
+        }
+
+        let change: f64 = next
+            .iter()
+            .zip(&ranks)
+            .map(|(new, old)| (new - old).abs())
+            .sum();
+
+        ranks = next;
+        iterations += 1;
+        converged = change < config.tolerance;
+    }
+
+    PageRanks {
+        ranks,
+        iterations,
+        converged,
+    }
+}
+
+/// computes the pagerank over the stored link graph and stores it for every page
+pub fn compute_pagerank(
+    conn: &DatabaseConnection,
+    config: &PageRankConfig,
+    verbose: bool,
+) -> Result<PageRanks, rusqlite::Error> {
+    let ids = get_page_ids(conn)?;
+    let index: HashMap<i64, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+
+    let links = get_graph_edges(conn)?
+        .into_iter()
+        .filter_map(|(from, to)| Some((*index.get(&from)?, *index.get(&to)?)))
+        .collect::<Vec<_>>();
+
+    if verbose {
+        println!(
+            "Computing pagerank of {} pages with {} links",
+            ids.len(),
+            links.len()
+        );
+    }
+
+    let result = pagerank(ids.len(), &links, config);
+
+    let pageranks = ids
+        .iter()
+        .copied()
+        .zip(result.ranks.iter().copied())
+        .collect::<Vec<_>>();
+    update_pageranks(conn, &pageranks)?;
+
+    if verbose {
+        println!(
+            "Pagerank {} after {} iterations",
+            if result.converged {
+                "converged"
+            } else {
+                "stopped"
+            },
+            result.iterations
+        );
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::db_manager::{
+        creation::create_default_tables,
+        helper::{gen_random_path, gen_vals},
+        selecting::get_relative_pageranks,
+    };
+
+    use super::{compute_pagerank, pagerank, PageRankConfig};
+
+    fn assert_close(left: f64, right: f64) {
+        assert!((left - right).abs() < 1e-4, "{left} != {right}");
+    }
+
+    #[test]
+    fn ranks_sum_to_one() {
+        let result = pagerank(
+            4,
+            &[(0, 1), (1, 2), (2, 0), (3, 2)],
+            &PageRankConfig::default(),
+        );
+
+        assert!(result.converged);
+        assert_close(result.ranks.iter().sum(), 1.0);
+        // nobody links to page 3
+        assert_close(result.ranks[3], 0.15 / 4.0);
+        // page 2 is linked by two pages
+        assert!(result.ranks[2] > result.ranks[1]);
+    }
+
+    #[test]
+    fn symmetric_graph_is_uniform() {
+        let result = pagerank(3, &[(0, 1), (1, 2), (2, 0)], &PageRankConfig::default());
+
+        for rank in result.ranks {
+            assert_close(rank, 1.0 / 3.0);
+        }
+    }
+
+    /// the rank of pages without links isn't lost
+    #[test]
+    fn handles_dangling_pages() {
+        let result = pagerank(3, &[(0, 2), (1, 2)], &PageRankConfig::default());
+
+        assert_close(result.ranks.iter().sum(), 1.0);
+        assert!(result.ranks[2] > result.ranks[0]);
+        assert_close(result.ranks[0], result.ranks[1]);
+    }
+
+    #[test]
+    fn stops_after_max_iterations() {
+        let config = PageRankConfig {
+            tolerance: 0.0,
+            max_iterations: 5,
+            ..PageRankConfig::default()
+        };
+
+        let result = pagerank(2, &[(0, 1)], &config);
+
+        assert_eq!(result.iterations, 5);
+        assert!(!result.converged);
+    }
+
+    #[test]
+    fn empty_graph() {
+        let result = pagerank(0, &[], &PageRankConfig::default());
+
+        assert!(result.ranks.is_empty());
+    }
+
+    #[test]
+    fn stores_pageranks() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let before = get_relative_pageranks(&conn, [2, 3]).unwrap();
+
+        compute_pagerank(&conn, &PageRankConfig::default(), false).unwrap();
+
+        // help.ch (linked by three pages) and p.ch (not linked at all)
+        let after = get_relative_pageranks(&conn, [2, 3]).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert!(before.is_empty());
+        assert_eq!(after.len(), 2);
+        assert!(after[&2] > 1.0);
+        assert!(after[&3] < 1.0);
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::BTreeSet;
+
+use url::Url;
+
+use crate::db_manager::{
+    creation::DatabaseConnection,
+    full_text::phrase_query,
+    selecting::{find, find_by_lang, find_by_url, get_indexed_ids},
+};
+
+use super::tokenizer::tokenize;
+
+/// queries with more words than this are cut off, every word is looked up on its own
+const MAX_WORDS: usize = 32;
+
+/// A filter on a part of the page other than its text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// `site:example.com`, the host is the domain or a subdomain of it
+    Site,
+    /// `inurl:docs`, the url contains the text
+    InUrl,
+    /// `intitle:guide`, the title contains the words
+    InTitle,
+    /// `lang:de`, the page is written in the language (from `<html lang>`)
+    Lang,
+    /// `filetype:pdf`, the path of the url ends with the extension
+    FileType,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "site" => Some(Field::Site),
+            "inurl" => Some(Field::InUrl),
+            "intitle" => Some(Field::InTitle),
+            "lang" => Some(Field::Lang),
+            "filetype" => Some(Field::FileType),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed search query
+///
+/// `rust "error handling" -unsafe (site:docs.rs OR intitle:guide)`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// a single word (or several words joined by punctuation like `team-crystal`, which are matched as a phrase)
+    Word(String),
+    /// `"..."`, the words appear next to each other
+    Phrase(String),
+    Filter(Field, String),
+    /// all parts match, written with spaces or `AND`
+    And(Vec<Query>),
+    /// any part matches
+    Or(Vec<Query>),
+    /// `-part` or `NOT part`, the part doesn't match
+    Not(Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Or,
+    And,
+    Not,
+    Phrase(String),
+    Word(String),
+    Filter(Field, String),
+}
+
+/// parses a search query, returns `None` if it doesn't contain anything to search for
+///
+/// the parser never fails: unknown operators are searched as words, unbalanced parentheses are ignored
+pub fn parse(search: &str) -> Option<Query> {
+    let tokens = lex(search);
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        words: 0,
+    };
+
+    let mut parts = vec![];
+
+    while parser.position < parser.tokens.len() {
+        if let Some(query) = parser.or() {
+            parts.push(query);
+        }
+
+        // a `)` without a `(`
+        if parser.peek() == Some(&Token::Close) {
+            parser.position += 1;
+        }
+    }
+
+    combine(parts, Query::And)
+}
+
+fn lex(search: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = search.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Phrase(read_phrase(&mut chars)));
+            }
+            '-' => {
+                chars.next();
+
+                // only a `-` directly in front of something excludes it
+                if chars
+                    .peek()
+                    .map(|next| !next.is_whitespace() && *next != ')')
+                    .unwrap_or(false)
+                {
+                    tokens.push(Token::Not);
+                }
+            }
+            _ => {
+                let mut word = String::new();
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+
+                    word.push(c);
+                    chars.next();
+                }
+
+                let filter = word
+                    .split_once(':')
+                    .and_then(|(name, value)| Some((Field::from_name(name)?, value)));
+
+                let token = match (word.as_str(), filter) {
+                    ("OR" | "|", _) => Token::Or,
+                    ("AND" | "&", _) => Token::And,
+                    ("NOT", _) => Token::Not,
+                    (_, Some((field, ""))) if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        Token::Filter(field, read_phrase(&mut chars))
+                    }
+                    (_, Some((field, value))) => Token::Filter(field, value.to_string()),
+                    _ => Token::Word(word),
+                };
+
+                tokens.push(token);
+            }
+        }
+    }
+
+    tokens
+}
+
+/// reads until the closing `"` (or the end if there is none)
+fn read_phrase(chars: &mut impl Iterator<Item = char>) -> String {
+    chars.take_while(|c| *c != '"').collect()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// how many words have been read, see `MAX_WORDS`
+    words: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// `and (OR and)*`
+    fn or(&mut self) -> Option<Query> {
+        let mut parts = vec![];
+        parts.extend(self.and());
+
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            parts.extend(self.and());
+        }
+
+        combine(parts, Query::Or)
+    }
+
+    /// `unary (AND? unary)*`
+    fn and(&mut self) -> Option<Query> {
+        let mut parts = vec![];
+
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) | Some(Token::Or) => break,
+                Some(Token::And) => self.position += 1,
+                _ => parts.extend(self.unary()),
+            }
+        }
+
+        combine(parts, Query::And)
+    }
+
+    /// `(-|NOT)? primary`
+    fn unary(&mut self) -> Option<Query> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+
+            return match self.peek() {
+                None | Some(Token::Close) | Some(Token::Or) | Some(Token::And) => None,
+                _ => self.unary().map(|query| Query::Not(Box::new(query))),
+            };
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<Query> {
+        match self.next()? {
+            Token::Open => {
+                let query = self.or();
+
+                if self.peek() == Some(&Token::Close) {
+                    self.position += 1;
+                }
+
+                query
+            }
+            Token::Word(word) => self.count_word(Query::Word(word)),
+            Token::Phrase(phrase) => self.count_word(Query::Phrase(phrase)),
+            Token::Filter(field, value) => {
+                let value = value.trim().to_string();
+
+                (!value.is_empty()).then_some(Query::Filter(field, value))
+            }
+            _ => None,
+        }
+    }
+
+    /// drops words without letters or digits and words after `MAX_WORDS`
+    fn count_word(&mut self, query: Query) -> Option<Query> {
+        let text = match &query {
+            Query::Word(text) | Query::Phrase(text) => text,
+            _ => return Some(query),
+        };
+
+        if tokenize(text).is_empty() || self.words >= MAX_WORDS {
+            return None;
+        }
+
+        self.words += 1;
+        Some(query)
+    }
+}
+
+/// joins the parts with the operator, a single part is returned as it is
+fn combine(mut parts: Vec<Query>, operator: fn(Vec<Query>) -> Query) -> Option<Query> {
+    match parts.len() {
+        0 => None,
+        1 => parts.pop(),
+        _ => Some(operator(parts)),
+    }
+}
+
+impl Query {
+    /// returns the words that make a page relevant, excluded words aren't part of it
+    pub fn terms(&self) -> Vec<String> {
+        match self {
+            Query::Word(text) | Query::Phrase(text) | Query::Filter(Field::InTitle, text) => {
+                tokenize(text)
+            }
+            Query::Filter(..) | Query::Not(_) => vec![],
+            Query::And(parts) | Query::Or(parts) => {
+                parts.iter().flat_map(|part| part.terms()).collect()
+            }
+        }
+    }
+
+    /// returns the ids of all pages that match the query
+    pub fn evaluate(&self, conn: &DatabaseConnection) -> Result<BTreeSet<i64>, rusqlite::Error> {
+        let ids = match self {
+            Query::Word(text) | Query::Phrase(text) => find_phrase(conn, None, text)?,
+            Query::Filter(Field::InTitle, text) => find_phrase(conn, Some("title"), text)?,
+            Query::Filter(Field::InUrl, text) => find_by_url(conn, text)?
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect(),
+            Query::Filter(Field::Site, domain) => {
+                let domain = domain.trim_matches('.').to_lowercase();
+
+                find_by_url(conn, &domain)?
+                    .into_iter()
+                    .filter(|(_, url)| is_on_site(url, &domain))
+                    .map(|(id, _)| id)
+                    .collect()
+            }
+            Query::Filter(Field::FileType, extension) => {
+                let extension = extension.trim_start_matches('.').to_lowercase();
+
+                find_by_url(conn, &format!(".{extension}"))?
+                    .into_iter()
+                    .filter(|(_, url)| has_extension(url, &extension))
+                    .map(|(id, _)| id)
+                    .collect()
+            }
+            Query::Filter(Field::Lang, lang) => find_by_lang(conn, lang)?.into_iter().collect(),
+            Query::Not(query) => {
+                let excluded = query.evaluate(conn)?;
+
+                get_indexed_ids(conn)?
+                    .into_iter()
+                    .filter(|id| !excluded.contains(id))
+                    .collect()
+            }
+            Query::Or(parts) => {
+                let mut ids = BTreeSet::new();
+
+                for part in parts {
+                    ids.extend(part.evaluate(conn)?);
+                }
+
+                ids
+            }
+            Query::And(parts) => {
+                let (excluded, included): (Vec<_>, Vec<_>) =
+                    parts.iter().partition(|part| matches!(part, Query::Not(_)));
+
+                let mut ids = match included.split_first() {
+                    Some((first, rest)) => {
+                        let mut ids = first.evaluate(conn)?;
+
+                        for part in rest {
+                            if ids.is_empty() {
+                                break;
+                            }
+
+                            let other = part.evaluate(conn)?;
+                            ids.retain(|id| other.contains(id));
+                        }
+
+                        ids
+                    }
+                    None => get_indexed_ids(conn)?.into_iter().collect(),
+                };
+
+                // `a -b` removes the pages of b instead of building everything except b
+                for part in excluded {
+                    if let Query::Not(query) = part {
+                        for id in query.evaluate(conn)? {
+                            ids.remove(&id);
+                        }
+                    }
+                }
+
+                ids
+            }
+        };
+
+        Ok(ids)
+    }
+}
+
+fn find_phrase(
+    conn: &DatabaseConnection,
+    column: Option<&str>,
+    text: &str,
+) -> Result<BTreeSet<i64>, rusqlite::Error> {
+    match phrase_query(column, text) {
+        Some(query) => Ok(find(conn, &query)?.into_iter().collect()),
+        None => Ok(BTreeSet::new()),
+    }
+}
+
+/// parses stored urls, older versions stored some of them without a scheme
+fn parse_url(url: &str) -> Option<Url> {
+    Url::parse(url)
+        .ok()
+        .filter(|url| url.has_host())
+        .or_else(|| Url::parse(&format!("http://{url}")).ok())
+}
+
+fn is_on_site(url: &str, domain: &str) -> bool {
+    parse_url(url)
+        .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+        .map(|host| host == domain || host.ends_with(&format!(".{domain}")))
+        .unwrap_or(false)
+}
+
+fn has_extension(url: &str, extension: &str) -> bool {
+    parse_url(url)
+        .map(|url| {
+            url.path()
+                .to_lowercase()
+                .ends_with(&format!(".{extension}"))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, fs};
+
+    use crate::{
+        db_manager::{
+            creation::create_default_tables,
+            full_text::fill_page_text,
+            helper::{gen_random_path, gen_vals, lease_page},
+            updating::update_to_visited,
+        },
+        page_scraper::html_parser::Document,
+    };
+
+    use super::{parse, Field, Query};
+
+    fn word(text: &str) -> Query {
+        Query::Word(text.to_string())
+    }
+
+    #[test]
+    fn parses_words_and_phrases() {
+        assert_eq!(
+            parse("rust \"error handling\""),
+            Some(Query::And(vec![
+                word("rust"),
+                Query::Phrase("error handling".to_string())
+            ]))
+        );
+        assert_eq!(parse("single"), Some(word("single")));
+        assert_eq!(parse("  "), None);
+        assert_eq!(parse("-- ()"), None);
+    }
+
+    #[test]
+    fn parses_operators() {
+        assert_eq!(
+            parse("a b OR c -d"),
+            Some(Query::Or(vec![
+                Query::And(vec![word("a"), word("b")]),
+                Query::And(vec![word("c"), Query::Not(Box::new(word("d")))]),
+            ]))
+        );
+        assert_eq!(
+            parse("a AND (b | c) NOT d"),
+            Some(Query::And(vec![
+                word("a"),
+                Query::Or(vec![word("b"), word("c")]),
+                Query::Not(Box::new(word("d"))),
+            ]))
+        );
+        // a `-` inside of a word is part of it
+        assert_eq!(parse("team-crystal"), Some(word("team-crystal")));
+    }
+
+    #[test]
+    fn parses_filters() {
+        assert_eq!(
+            parse("site:docs.rs intitle:\"getting started\" lang:de filetype:pdf inurl:api"),
+            Some(Query::And(vec![
+                Query::Filter(Field::Site, "docs.rs".to_string()),
+                Query::Filter(Field::InTitle, "getting started".to_string()),
+                Query::Filter(Field::Lang, "de".to_string()),
+                Query::Filter(Field::FileType, "pdf".to_string()),
+                Query::Filter(Field::InUrl, "api".to_string()),
+            ]))
+        );
+        // unknown fields are searched as words
+        assert_eq!(parse("url:test"), Some(word("url:test")));
+        assert_eq!(parse("site:"), None);
+    }
+
+    /// broken queries are still searched
+    #[test]
+    fn parses_broken_queries() {
+        assert_eq!(
+            parse("(a OR b"),
+            Some(Query::Or(vec![word("a"), word("b")]))
+        );
+        assert_eq!(parse("a) b"), Some(Query::And(vec![word("a"), word("b")])));
+        assert_eq!(
+            parse("\"open phrase"),
+            Some(Query::Phrase("open phrase".to_string()))
+        );
+        assert_eq!(parse("OR a OR"), Some(word("a")));
+        assert_eq!(parse("a -"), Some(word("a")));
+        assert_eq!(parse("NOT"), None);
+    }
+
+    #[test]
+    fn limits_words() {
+        let query = parse(&"word ".repeat(100)).unwrap();
+
+        match query {
+            Query::And(parts) => assert_eq!(parts.len(), 32),
+            _ => panic!("{query:?}"),
+        }
+    }
+
+    #[test]
+    fn collects_terms() {
+        let query = parse("a \"b c\" -d intitle:e site:f.ch (g OR h)").unwrap();
+
+        assert_eq!(query.terms(), vec!["a", "b", "c", "e", "g", "h"]);
+    }
+
+    #[test]
+    fn evaluates_queries() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let content =
+            "<html lang=\"de-CH\"><head><title>Hilfe Seite</title></head><body>help</body></html>";
+        let fields = Document::parse_str(content).fields();
+        lease_page(&conn, 3, "crawler");
+        update_to_visited(&conn, 3, "crawler", content, vec![], &fields, false).unwrap();
+        conn.connection
+            .execute(
+                "INSERT INTO Ranking (url, content) VALUES ('https://docs.help.ch/guide.PDF', 'guide'), ('https://nothelp.ch/guide.pdf', 'guide');",
+                (),
+            )
+            .unwrap();
+        fill_page_text(&conn.connection).unwrap();
+
+        let evaluate = |search: &str| {
+            parse(search)
+                .unwrap()
+                .evaluate(&conn)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        let team_crystal = evaluate("team crystal");
+        let team_not_google = evaluate("team -google");
+        let phrase = evaluate("\"crystal ch help\"");
+        let either = evaluate("google OR lorem");
+        let only_not = evaluate("-ch");
+        let title = evaluate("intitle:hilfe");
+        let lang = evaluate("lang:de");
+        let site = evaluate("site:help.ch");
+        let in_url = evaluate("inurl:LP.CH");
+        let file_type = evaluate("filetype:pdf");
+        let grouped = evaluate("(team OR guide) -(google OR site:nothelp.ch)");
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(team_crystal, vec![1, 2, 4]);
+        assert_eq!(team_not_google, vec![4]);
+        assert_eq!(phrase, vec![4]);
+        assert_eq!(either, vec![1, 2, 5, 6]);
+        assert_eq!(only_not, vec![6]);
+        assert_eq!(title, vec![3]);
+        assert_eq!(lang, vec![3]);
+        assert_eq!(site, vec![2, 7]);
+        // every help.ch url contains lp.ch too
+        assert_eq!(in_url, vec![2, 5, 7, 8]);
+        assert_eq!(file_type, vec![7, 8]);
+        assert_eq!(
+            grouped.into_iter().collect::<BTreeSet<_>>(),
+            BTreeSet::from([4, 7])
+        );
+    }
+}
@@ -1,63 +1,207 @@
-use regex::Regex;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use itertools::Itertools;
 
 use crate::db_manager::{
     creation::DatabaseConnection,
+    postings::{get_corpus_stats, get_postings, get_term_count},
     ranking::Ranking,
-    selecting::{calculate_links_from, find},
+    selecting::{get_page, get_relative_pageranks},
 };
 
-use super::helper::{compute_rank, compute_search_word_appearance};
+use super::{
+    bm25::{idf, term_score, Bm25Config},
+    helper::combine_scores,
+    hits::{focused_hits, HitsConfig},
+    query::parse,
+};
+
+/// how many of the most relevant pages the hits subgraph is built around (Kleinberg's `t`)
+const HITS_ROOT_SIZE: usize = 200;
+
+/// Which link analysis is used as the link signal of a page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RankingMode {
+    /// the stored, query independent pagerank (see `compute_pagerank`)
+    #[default]
+    PageRank,
+    /// the hits authority computed on the subgraph around the results
+    Hits,
+}
+
+impl FromStr for RankingMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.to_lowercase().as_str() {
+            "pagerank" => Ok(RankingMode::PageRank),
+            "hits" => Ok(RankingMode::Hits),
+            _ => Err(format!(
+                "unknown ranking mode {mode} (expected pagerank or hits)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RankingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RankingMode::PageRank => write!(f, "pagerank"),
+            RankingMode::Hits => write!(f, "hits"),
+        }
+    }
+}
+
+/// Settings of the search ranking
+#[derive(Debug, Clone, Default)]
+pub struct RankingConfig {
+    pub mode: RankingMode,
+    pub bm25: Bm25Config,
+}
 
 #[derive(Debug)]
 pub struct RankedPage {
-    rank: usize,
-    page: Ranking,
+    /// relevance combined with the link analysis score, see `combine_scores`
+    pub rank: f64,
+    pub page: Ranking,
 }
 
-/// takes a search word and returns a list with all search results and their relevancy
+/// takes a search query (see `query::parse`) and returns the results `offset..offset + amount` ordered by their relevancy
+///
+/// all pages that match the query are scored before the best ones are picked,
+/// only the words that aren't excluded count towards the relevance,
+/// queries without words (only filters) are ranked by the link analysis score alone
 pub fn rank_pages(
     conn: &DatabaseConnection,
     search_word: &str,
     amount: u32,
+    offset: u32,
+    config: &RankingConfig,
 ) -> Result<Vec<RankedPage>, rusqlite::Error> {
-    let matches = find(conn, search_word, amount)?;
+    let query = match parse(search_word) {
+        Some(query) => query,
+        None => return Ok(vec![]),
+    };
+    let matches = query.evaluate(conn)?;
+    let terms = query.terms();
 
-    let mut ranking = vec![];
+    let relevance = bm25_scores(conn, &terms, &config.bm25)?;
+    let relevance_of = |id: &i64| relevance.get(id).copied().unwrap_or(0.0);
 
-    for single_match in matches {
-        let link_to_count = single_match
-            .links_to
-            .clone()
-            .unwrap_or("".to_string())
-            .split(":::")
-            .collect::<Vec<_>>()
-            .len();
+    // the stored pageranks or the authorities relative to the average page of the subgraph around the most relevant pages
+    let link_scores: HashMap<i64, f64> = match config.mode {
+        RankingMode::PageRank => get_relative_pageranks(conn, matches.iter().copied())?,
+        RankingMode::Hits => {
+            let root = top_k(
+                matches.iter().map(|id| (*id, relevance_of(id))),
+                HITS_ROOT_SIZE,
+            );
+            let ids = root.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+            let scores = focused_hits(conn, &ids, &HitsConfig::default())?;
+            let size = scores.len() as f64;
 
-        let link_from_count = calculate_links_from(&conn, single_match.id)?;
+            scores
+                .into_iter()
+                .map(|(id, (_, authority))| (id, authority * size))
+                .collect()
+        }
+    };
 
-        let search_word_appearance = compute_search_word_appearance(search_word, &single_match.url)
-            + compute_search_word_appearance(
-                search_word,
-                &single_match.content.clone().unwrap_or("".to_string()),
-            );
+    let scores = matches.iter().map(|id| {
+        let link_score = link_scores.get(id).copied().unwrap_or(0.0);
 
-        let regex = Regex::new(format!(r"(?i)\W+{search_word}\W+").as_str()).unwrap();
-        let has_whole_word = regex.is_match(&single_match.url)
-            || regex.is_match(&single_match.content.clone().unwrap_or("".to_string()));
-
-        ranking.push(RankedPage {
-            page: single_match,
-            rank: compute_rank(
-                link_to_count,
-                link_from_count,
-                search_word_appearance,
-                has_whole_word,
-            ),
+        // filters like `site:` don't make a page more relevant, all their matches are ranked by their links
+        match terms.is_empty() {
+            true => (*id, link_score),
+            false => (*id, combine_scores(relevance_of(id), link_score)),
+        }
+    });
+
+    top_k(scores, offset.saturating_add(amount) as usize)
+        .into_iter()
+        .skip(offset as usize)
+        .map(|(id, rank)| {
+            Ok(RankedPage {
+                page: get_page(conn, id)?,
+                rank,
+            })
         })
+        .collect()
+}
+
+/// A scored page in the heap of `top_k`, ordered by score and then by id (lower ids win ties)
+#[derive(PartialEq)]
+struct Scored(f64, i64);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(other.1.cmp(&self.1))
+    }
+}
+
+/// returns the `k` pages with the highest score, best first
+///
+/// only `k` pages are kept at a time, so scoring many candidates doesn't need much memory,
+/// `k` comes from the user and can be far larger than the number of candidates
+fn top_k(scores: impl Iterator<Item = (i64, f64)>, k: usize) -> Vec<(i64, f64)> {
+    let mut heap = BinaryHeap::with_capacity(k.min(scores.size_hint().0) + 1);
+
+    for (id, score) in scores {
+        heap.push(Reverse(Scored(score, id)));
+
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(Scored(score, id))| (id, score))
+        .collect()
+}
+
+/// computes the BM25 score of every page that contains at least one of the terms
+fn bm25_scores(
+    conn: &DatabaseConnection,
+    terms: &[String],
+    config: &Bm25Config,
+) -> Result<HashMap<i64, f64>, rusqlite::Error> {
+    let stats = get_corpus_stats(conn)?;
+
+    let mut scores = HashMap::new();
+    let mut lengths = HashMap::new();
+
+    for term in terms.iter().unique() {
+        let postings = get_postings(conn, term)?;
+        let idf = idf(stats.documents, postings.len() as u64);
+
+        for posting in postings {
+            let length = match lengths.get(&posting.page_id) {
+                Some(length) => *length,
+                None => *lengths
+                    .entry(posting.page_id)
+                    .or_insert(get_term_count(conn, posting.page_id)?),
+            };
+
+            *scores.entry(posting.page_id).or_insert(0.0) +=
+                term_score(posting.frequency, length, stats.average_length, idf, config);
+        }
     }
 
-    ranking.sort_by(|a, b| b.rank.cmp(&a.rank));
-    Ok(ranking)
+    Ok(scores)
 }
 
 #[cfg(test)]
@@ -69,7 +213,11 @@ mod tests {
             creation::create_default_tables,
             helper::{gen_random_path, gen_vals},
         },
-        page_rank::ranker::rank_pages,
+        page_rank::{
+            bm25::Bm25Config,
+            pagerank::{compute_pagerank, PageRankConfig},
+            ranker::{rank_pages, top_k, RankingConfig, RankingMode},
+        },
     };
 
     #[test]
@@ -79,14 +227,14 @@ mod tests {
 
         gen_vals(&conn);
 
-        let result = rank_pages(&conn, "team", 10).unwrap();
+        let result = rank_pages(&conn, "team", 10, 0, &RankingConfig::default()).unwrap();
 
         assert_eq!(result.len(), 3);
 
         fs::remove_file(path).unwrap();
-        assert!(result
-            .iter()
-            .any(|res| res.page.url == "ep.ch" && res.rank == 3));
+        // all three pages contain team once, ep.ch is the shortest
+        assert_eq!(result[0].page.url, "ep.ch");
+        assert!(result[0].rank > result[1].rank);
     }
 
     #[test]
@@ -96,12 +244,218 @@ mod tests {
 
         gen_vals(&conn);
 
-        let result = rank_pages(&conn, "anim tempor fugiat deserunt est", 10).unwrap();
+        let result = rank_pages(
+            &conn,
+            "anim tempor fugiat deserunt est",
+            10,
+            0,
+            &RankingConfig::default(),
+        )
+        .unwrap();
 
         fs::remove_file(path).unwrap();
 
-        assert_eq!(result.len(), 3);
+        // test.ch and help.ch only contain "est" inside of "test"
+        assert_eq!(result.len(), 1);
         assert_eq!(result[0].page.url, "hre.he");
-        assert_eq!(result[0].rank, 40);
+        assert!(result[0].rank > 0.0);
+    }
+
+    #[test]
+    fn rank_with_bm25_parameters() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        // without length normalization all pages that contain team once are equal
+        let config = RankingConfig {
+            bm25: Bm25Config { k1: 1.2, b: 0.0 },
+            ..RankingConfig::default()
+        };
+        let result = rank_pages(&conn, "team", 10, 0, &config).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(result[0].rank, result[2].rank);
+    }
+
+    /// pages with a higher pagerank are ranked higher once it has been computed
+    #[test]
+    fn rank_with_pagerank() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+        let before = rank_pages(&conn, "team", 10, 0, &RankingConfig::default()).unwrap();
+        compute_pagerank(&conn, &PageRankConfig::default(), false).unwrap();
+        let after = rank_pages(&conn, "team", 10, 0, &RankingConfig::default()).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(before[0].page.url, "ep.ch");
+        assert!(after[0].rank > before[0].rank);
+        // nobody links to ep.ch, so the linked pages overtake it
+        assert_eq!(after[2].page.url, "ep.ch");
+    }
+
+    /// queries that only filter have no relevance, their matches are ordered by pagerank
+    #[test]
+    fn rank_filters_by_links() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+        compute_pagerank(&conn, &PageRankConfig::default(), false).unwrap();
+        let result = rank_pages(&conn, "site:ch", 10, 0, &RankingConfig::default()).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        let urls = result
+            .iter()
+            .map(|page| page.page.url.as_str())
+            .collect::<Vec<_>>();
+
+        // help.ch is linked by three pages and passes its pagerank on to test.ch, lp.ch is linked by hre.he
+        assert_eq!(urls[..3], ["test.ch", "help.ch", "lp.ch"]);
+        assert_eq!(result.len(), 5);
+        assert!(result[0].rank > result[1].rank);
+        assert!(result[4].rank > 0.0);
+    }
+
+    /// with hits the pages that are linked by good hubs are ranked higher
+    #[test]
+    fn rank_with_hits() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let config = RankingConfig {
+            mode: RankingMode::Hits,
+            ..RankingConfig::default()
+        };
+        let result = rank_pages(&conn, "crystal", 10, 0, &config).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        // test.ch, help.ch and ep.ch, help.ch is the only one that is linked by a result
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].page.url, "help.ch");
+        assert!(result[0].rank > result[1].rank);
+    }
+
+    /// search strings are never interpreted as sql or fts5 syntax
+    #[test]
+    fn rank_hostile_searches() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let searches = [
+            "it's",
+            "'; DROP TABLE Ranking; --",
+            "\" OR 1=1 --",
+            "team' OR '1'='1",
+            "%_\\",
+            "NEAR(team crystal)",
+            "team*",
+            "url:test AND NOT help",
+            "^team + -crystal",
+            "",
+            "   ",
+            "\0",
+            "😀 ümlaut",
+        ];
+
+        for search in searches {
+            for mode in [RankingMode::PageRank, RankingMode::Hits] {
+                let config = RankingConfig {
+                    mode,
+                    ..RankingConfig::default()
+                };
+
+                rank_pages(&conn, search, 10, 0, &config)
+                    .unwrap_or_else(|err| panic!("{search:?} failed: {err}"));
+            }
+        }
+
+        let long = "team ".repeat(10_000);
+        let long_result = rank_pages(&conn, &long, 10, 0, &RankingConfig::default()).unwrap();
+        let quoted = rank_pages(&conn, "\"crystal\"", 10, 0, &RankingConfig::default()).unwrap();
+        let rows = conn
+            .connection
+            .query_row("SELECT COUNT(*) FROM Ranking;", (), |row| {
+                row.get::<usize, i64>(0)
+            })
+            .unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(long_result.len(), 3);
+        // quotes are ignored like any other punctuation
+        assert_eq!(quoted.len(), 3);
+        assert_eq!(rows, 6);
+    }
+
+    /// the best pages are picked from all matches, not from the first matches
+    #[test]
+    fn rank_before_limit() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let all = rank_pages(&conn, "team", 10, 0, &RankingConfig::default()).unwrap();
+        let first = rank_pages(&conn, "team", 1, 0, &RankingConfig::default()).unwrap();
+        let second = rank_pages(&conn, "team", 1, 1, &RankingConfig::default()).unwrap();
+        let beyond = rank_pages(&conn, "team", 10, 3, &RankingConfig::default()).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        // ep.ch has the highest id of the three matches, limiting before ranking would miss it
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].page.url, "ep.ch");
+        assert_eq!(second[0].page.url, all[1].page.url);
+        assert!(beyond.is_empty());
+    }
+
+    #[test]
+    fn keeps_best_scores() {
+        let scores = [(1, 0.5), (2, 3.0), (3, 1.0), (4, 3.0), (5, 0.0)];
+
+        assert_eq!(
+            top_k(scores.into_iter(), 3),
+            vec![(2, 3.0), (4, 3.0), (3, 1.0)]
+        );
+        assert_eq!(top_k(scores.into_iter(), 0), vec![]);
+        assert_eq!(top_k(scores.into_iter(), 10).len(), 5);
+        assert_eq!(top_k(scores.into_iter(), usize::MAX).len(), 5);
+    }
+
+    /// the amount and offset of the search don't allocate memory for that many results
+    #[test]
+    fn rank_huge_amount() {
+        let path = gen_random_path();
+        let conn = create_default_tables(path.to_str().unwrap()).unwrap();
+
+        gen_vals(&conn);
+
+        let all = rank_pages(&conn, "team", u32::MAX, 0, &RankingConfig::default()).unwrap();
+        let beyond =
+            rank_pages(&conn, "team", u32::MAX, u32::MAX, &RankingConfig::default()).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(all.len(), 3);
+        assert!(beyond.is_empty());
+    }
+
+    #[test]
+    fn parses_ranking_mode() {
+        assert_eq!("hits".parse::<RankingMode>(), Ok(RankingMode::Hits));
+        assert_eq!("PageRank".parse::<RankingMode>(), Ok(RankingMode::PageRank));
+        assert!("bm25".parse::<RankingMode>().is_err());
     }
 }
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use crate::page_scraper::html_parser::PageFields;
+
+use super::tokenizer::{fold, is_word_char};
+
+/// how many words of the visible text a snippet shows
+const SNIPPET_WORDS: usize = 30;
+
+/// A piece of the snippet text, highlighted if it is one of the searched words
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+/// What a results page shows of a page: its title, url and the passage that matches the search best
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub title: Option<String>,
+    pub url: String,
+    pub fragments: Vec<Fragment>,
+}
+
+impl Snippet {
+    /// builds the snippet of the page from its fields (see `get_page_fields`) for the searched terms (see `Query::terms`)
+    ///
+    /// the passage of `SNIPPET_WORDS` words with the most different terms is picked (the earliest one on ties),
+    /// pages that don't contain any of the terms show their meta description or the start of their text
+    pub fn new(url: &str, fields: &PageFields, terms: &[String]) -> Self {
+        let words = fields
+            .text
+            .split(' ')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        let matches = words
+            .iter()
+            .map(|word| matched_terms(word, terms))
+            .collect::<Vec<_>>();
+
+        let passage = match best_window(&matches, SNIPPET_WORDS) {
+            Some(start) => {
+                let end = (start + SNIPPET_WORDS).min(words.len());
+                surround(&words[start..end].join(" "), start > 0, end < words.len())
+            }
+            None => match &fields.description {
+                Some(description) => description.clone(),
+                None => surround(
+                    &words[..words.len().min(SNIPPET_WORDS)].join(" "),
+                    false,
+                    words.len() > SNIPPET_WORDS,
+                ),
+            },
+        };
+
+        Self {
+            title: fields.title.clone(),
+            url: url.to_string(),
+            fragments: highlight(&passage, terms),
+        }
+    }
+
+    /// the snippet text without highlighting
+    pub fn text(&self) -> String {
+        self.fragments
+            .iter()
+            .map(|fragment| fragment.text.as_str())
+            .collect()
+    }
+
+    /// the snippet text with the searched words in bold for terminals
+    pub fn ansi(&self) -> String {
+        self.fragments
+            .iter()
+            .map(|fragment| match fragment.highlighted {
+                true => format!("\x1b[1m{}\x1b[0m", fragment.text),
+                false => fragment.text.clone(),
+            })
+            .collect()
+    }
+
+    /// the snippet text as escaped html with the searched words in `<mark>`
+    pub fn html(&self) -> String {
+        self.fragments
+            .iter()
+            .map(|fragment| match fragment.highlighted {
+                true => format!("<mark>{}</mark>", escape_html(&fragment.text)),
+                false => escape_html(&fragment.text),
+            })
+            .collect()
+    }
+}
+
+/// escapes text for use in html content and attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// returns the terms contained in the word, `team-crystal` contains `team` and `crystal`
+fn matched_terms<'a>(word: &str, terms: &'a [String]) -> Vec<&'a str> {
+    word_parts(word)
+        .filter(|(part, is_word)| *is_word && !part.is_empty())
+        .filter_map(|(part, _)| {
+            let part = fold(part);
+            terms
+                .iter()
+                .find(|term| **term == part)
+                .map(|term| term.as_str())
+        })
+        .collect()
+}
+
+/// returns the start of the window of `size` words with the most different terms (then most terms),
+/// `None` if no word matches
+fn best_window(matches: &[Vec<&str>], size: usize) -> Option<usize> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut total = 0;
+    let mut best: Option<(usize, usize, usize)> = None;
+
+    for (end, terms) in matches.iter().enumerate() {
+        for term in terms {
+            *counts.entry(term).or_insert(0) += 1;
+            total += 1;
+        }
+
+        // the word that left the window
+        if end >= size {
+            for term in &matches[end - size] {
+                let count = counts.get_mut(term).unwrap();
+                *count -= 1;
+
+                if *count == 0 {
+                    counts.remove(term);
+                }
+
+                total -= 1;
+            }
+        }
+
+        let start = (end + 1).saturating_sub(size);
+        let score = (counts.len(), total);
+
+        if total > 0
+            && best
+                .map(|(_, distinct, all)| score > (distinct, all))
+                .unwrap_or(true)
+        {
+            best = Some((start, score.0, score.1));
+        }
+    }
+
+    best.map(|(start, _, _)| {
+        // words before the first match don't add anything, show more of the text after it instead
+        let first_match = (start..matches.len())
+            .find(|index| !matches[*index].is_empty())
+            .unwrap_or(start);
+
+        first_match
+            .min(matches.len().saturating_sub(size))
+            .max(start)
+    })
+}
+
+/// adds `…` where the passage was cut out of the text
+fn surround(passage: &str, cut_start: bool, cut_end: bool) -> String {
+    format!(
+        "{}{passage}{}",
+        if cut_start { "… " } else { "" },
+        if cut_end { " …" } else { "" }
+    )
+}
+
+/// splits the text into runs of letters and digits (`true`) and the characters between them (`false`)
+fn word_parts(text: &str) -> impl Iterator<Item = (&str, bool)> {
+    let mut rest = text;
+
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let is_word = is_word_char(first);
+        let end = rest
+            .char_indices()
+            .find(|(_, c)| is_word_char(*c) != is_word)
+            .map(|(index, _)| index)
+            .unwrap_or(rest.len());
+
+        let (part, remaining) = rest.split_at(end);
+        rest = remaining;
+
+        Some((part, is_word))
+    })
+}
+
+/// splits the passage into highlighted searched words and the text between them
+fn highlight(passage: &str, terms: &[String]) -> Vec<Fragment> {
+    let mut fragments: Vec<Fragment> = vec![];
+
+    for (part, is_word) in word_parts(passage) {
+        let highlighted = is_word && terms.contains(&fold(part));
+
+        match fragments.last_mut() {
+            Some(last) if !last.highlighted && !highlighted => last.text.push_str(part),
+            _ => fragments.push(Fragment {
+                text: part.to_string(),
+                highlighted,
+            }),
+        }
+    }
+
+    fragments
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::page_scraper::html_parser::{Document, PageFields};
+
+    use super::{best_window, escape_html, Fragment, Snippet};
+
+    fn snippet(content: &str, terms: &[&str]) -> Snippet {
+        let terms = terms
+            .iter()
+            .map(|term| term.to_string())
+            .collect::<Vec<_>>();
+
+        Snippet::new(
+            "https://cats.ch/",
+            &Document::parse_str(content).fields(),
+            &terms,
+        )
+    }
+
+    #[test]
+    fn highlights_terms() {
+        let snippet = snippet(
+            "<html><head><title>Cats</title></head><body><nav>Cats</nav><p>Team-Crystal likes <b>cats</b>.</p></body></html>",
+            &["crystal", "cats"],
+        );
+
+        assert_eq!(snippet.title, Some("Cats".to_string()));
+        assert_eq!(snippet.text(), "Team-Crystal likes cats.");
+        assert_eq!(
+            snippet.fragments,
+            vec![
+                Fragment {
+                    text: "Team-".to_string(),
+                    highlighted: false
+                },
+                Fragment {
+                    text: "Crystal".to_string(),
+                    highlighted: true
+                },
+                Fragment {
+                    text: " likes ".to_string(),
+                    highlighted: false
+                },
+                Fragment {
+                    text: "cats".to_string(),
+                    highlighted: true
+                },
+                Fragment {
+                    text: ".".to_string(),
+                    highlighted: false
+                },
+            ]
+        );
+        assert_eq!(
+            snippet.ansi(),
+            "Team-\x1b[1mCrystal\x1b[0m likes \x1b[1mcats\x1b[0m."
+        );
+        assert_eq!(
+            snippet.html(),
+            "Team-<mark>Crystal</mark> likes <mark>cats</mark>."
+        );
+    }
+
+    /// the terms have no diacritics (see `tokenize`), the words of the page keep them
+    #[test]
+    fn highlights_accented_words() {
+        let snippet = snippet("<p>Cafés in Zürich</p>", &["zurich"]);
+
+        assert_eq!(snippet.html(), "Cafés in <mark>Zürich</mark>");
+    }
+
+    /// the passage with the most different terms wins over the one where a single term repeats
+    #[test]
+    fn picks_best_passage() {
+        let filler = "filler ".repeat(40);
+        let content = format!("<p>dog dog dog dog {filler} a cat and a dog {filler} end</p>");
+
+        let snippet = snippet(&content, &["cat", "dog"]);
+
+        assert!(snippet.text().starts_with("… cat and a dog"));
+        assert!(snippet.text().ends_with(" …"));
+        assert_eq!(snippet.text().split(' ').count(), 32);
+    }
+
+    #[test]
+    fn falls_back_without_matches() {
+        let with_description = snippet(
+            "<head><meta name=\"description\" content=\"All about cats\"></head><p>Text</p>",
+            &["dog"],
+        );
+        let without_description = snippet("<p>Some text</p>", &["dog"]);
+        let without_content = Snippet::new("https://cats.ch/", &PageFields::default(), &[]);
+
+        assert_eq!(with_description.text(), "All about cats");
+        assert_eq!(without_description.text(), "Some text");
+        assert_eq!(without_content.title, None);
+        assert_eq!(without_content.text(), "");
+    }
+
+    #[test]
+    fn windows() {
+        let matches = vec![vec![], vec!["a"], vec![], vec!["b"], vec![]];
+
+        assert_eq!(best_window(&matches, 2), Some(1));
+        assert_eq!(best_window(&matches, 3), Some(1));
+        assert_eq!(best_window(&matches, 10), Some(0));
+        assert_eq!(best_window(&[vec![], vec![]], 2), None);
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+}
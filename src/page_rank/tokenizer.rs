@@ -0,0 +1,170 @@
+use crate::page_scraper::html_parser::{Document, PageFields};
+
+/// the ranges of characters that are folded to the same letter by `remove_diacritic`,
+/// every entry is the first codepoint shifted left by 3 bits and the length of the range - 1 in the lower 3 bits
+///
+/// the table is the one of sqlite's unicode61 tokenizer, so the terms agree with the fts5 index (`remove_diacritics 2`)
+const DIACRITIC_RANGES: [u16; 126] = [
+    0, 1797, 1848, 1859, 1891, 1928, 1940, 1995, 2024, 2040, 2060, 2110, 2168, 2206, 2264, 2286,
+    2344, 2383, 2472, 2488, 2516, 2596, 2668, 2732, 2782, 2842, 2894, 2954, 2984, 3000, 3028, 3336,
+    3456, 3696, 3712, 3728, 3744, 3766, 3832, 3896, 3912, 3928, 3944, 3968, 4008, 4040, 4056, 4106,
+    4138, 4170, 4202, 4234, 4266, 4296, 4312, 4344, 4408, 4424, 4442, 4472, 4488, 4504, 6148, 6198,
+    6264, 6280, 6360, 6429, 6505, 6529, 61448, 61468, 61512, 61534, 61592, 61610, 61642, 61672,
+    61688, 61704, 61726, 61784, 61800, 61816, 61836, 61880, 61896, 61914, 61948, 61998, 62062,
+    62122, 62154, 62184, 62200, 62218, 62252, 62302, 62364, 62410, 62442, 62478, 62536, 62554,
+    62584, 62604, 62640, 62648, 62656, 62664, 62730, 62766, 62830, 62890, 62924, 62974, 63032,
+    63050, 63082, 63118, 63182, 63242, 63274, 63310, 63368, 63390,
+];
+
+/// the letter every range of `DIACRITIC_RANGES` is folded to, combining marks (`\0`) are removed
+const DIACRITIC_BASES: &[u8; 126] =
+    b"\0aceinouyyacdeeghijklnorstuuwyzouaiouuagkoojgnaaeiorusthaeoooy\0\0\0\0\0\0\0\0abcddeeefghhiiklllmnoprrrsstuuvwwxyzhtwyaaaaeeeioooouuuy";
+
+/// splits the text into lowercase words without diacritics, everything that isn't a letter or a digit separates words
+///
+/// "Team-Crystal.ch" becomes `["team", "crystal", "ch"]`, "Zürich" becomes `["zurich"]`
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !is_word_char(c))
+        .map(fold)
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// letters, digits and the combining marks that belong to them
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || is_diacritic(c)
+}
+
+/// lowercases the word and removes its diacritics the way the fts5 index does
+pub fn fold(word: &str) -> String {
+    word.chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(remove_diacritic)
+        .collect()
+}
+
+/// returns the letter without its diacritics, `None` for combining marks
+fn remove_diacritic(c: char) -> Option<char> {
+    let key = (c as u32) << 3 | 7;
+    let range = DIACRITIC_RANGES.partition_point(|start| u32::from(*start) <= key) - 1;
+    let start = u32::from(DIACRITIC_RANGES[range]);
+
+    if c as u32 > (start >> 3) + (start & 7) {
+        return Some(c);
+    }
+
+    match DIACRITIC_BASES[range] {
+        0 => None,
+        base => Some(base as char),
+    }
+}
+
+/// combining marks that belong to the word they are in (like sqlite's `sqlite3Fts5UnicodeIsdiacritic`)
+fn is_diacritic(c: char) -> bool {
+    const MASK0: u32 = 0x08029FDF;
+    const MASK1: u32 = 0x000361F8;
+
+    match c as u32 {
+        c @ 768..=799 => MASK0 & (1 << (c - 768)) != 0,
+        c @ 800..=817 => MASK1 & (1 << (c - 800)) != 0,
+        _ => false,
+    }
+}
+
+/// returns the terms a page is indexed with: the words of its url, title, meta description and keywords and
+/// its visible text without boilerplate (see `Document::fields`)
+pub fn page_terms(url: &str, content: &str) -> Vec<String> {
+    field_terms(url, &Document::parse_str(content).fields())
+}
+
+/// like `page_terms` for a page that is already parsed
+pub fn field_terms(url: &str, fields: &PageFields) -> Vec<String> {
+    let mut terms = tokenize(url);
+
+    for text in fields
+        .title
+        .iter()
+        .chain(fields.description.iter())
+        .chain(fields.keywords.iter())
+    {
+        terms.extend(tokenize(text));
+    }
+
+    terms.extend(tokenize(&fields.text));
+
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rusqlite::Connection;
+
+    use super::{fold, page_terms, tokenize};
+
+    #[test]
+    fn splits_words() {
+        assert_eq!(
+            tokenize("Team-Crystal.ch:::google.ch, it's Zürich 2022!"),
+            vec!["team", "crystal", "ch", "google", "ch", "it", "s", "zurich", "2022"]
+        );
+        assert!(tokenize(" -- ").is_empty());
+    }
+
+    /// the terms agree with the fts5 index, which removes diacritics
+    #[test]
+    fn folds_diacritics() {
+        assert_eq!(
+            tokenize("Zürich CAFÉ Ærøskøbing Łódź"),
+            vec!["zurich", "cafe", "ærøskøbing", "łodz"]
+        );
+        // a combining mark (u + ◌̈) doesn't split the word
+        assert_eq!(tokenize("Zu\u{308}rich"), vec!["zurich"]);
+        assert_eq!(fold("Ñandú"), "nandu");
+    }
+
+    #[test]
+    fn agrees_with_fts5() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE Words USING fts5(text, tokenize = 'unicode61 remove_diacritics 2');
+            CREATE VIRTUAL TABLE Terms USING fts5vocab(Words, row);",
+        )
+        .unwrap();
+
+        let text = "Zürich CAFÉ Ærøskøbing Łódź Zu\u{308}rich Ñandú Ångström Crème-brûlée";
+        conn.execute("INSERT INTO Words (text) VALUES (?1);", [text])
+            .unwrap();
+
+        let indexed = conn
+            .prepare("SELECT term FROM Terms ORDER BY term;")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            tokenize(text)
+                .into_iter()
+                .sorted()
+                .dedup()
+                .collect::<Vec<_>>(),
+            indexed
+        );
+    }
+
+    /// markup, scripts, styles and menus aren't indexed
+    #[test]
+    fn indexes_visible_text() {
+        let terms = page_terms(
+            "https://cats.ch/",
+            "<html><head><title>Cats</title><meta name=\"keywords\" content=\"kitten\"><style>p { color: red }</style></head><body><nav>Home</nav><p class=\"concatenate\">Cats purr</p><script>var dog;</script></body></html>",
+        );
+
+        assert_eq!(
+            terms,
+            vec!["https", "cats", "ch", "cats", "kitten", "cats", "purr"]
+        );
+    }
+}
@@ -1,3 +1,7 @@
+pub mod charset;
+pub mod fetcher;
 pub mod html;
 pub mod html_getter;
 pub mod html_parser;
+pub mod mime_sniff;
+pub mod url_normalizer;
@@ -0,0 +1,182 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, X_USER_DEFINED};
+
+/// only this many bytes are searched for a `<meta charset>`
+const PRESCAN_SIZE: usize = 1024;
+
+/// Decodes the bytes of a page to an utf-8 string
+///
+/// the encoding is taken from (in this order)
+/// 1. the byte order mark
+/// 2. the charset of the Content-Type header
+/// 3. a `<meta charset>` or `<meta http-equiv="Content-Type">` at the start of the page
+/// 4. a statistical detector
+///
+/// bytes that aren't valid in the encoding are replaced by U+FFFD, returns the text and the used encoding
+pub fn decode(bytes: &[u8], content_type: Option<&str>) -> (String, &'static Encoding) {
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_from_content_type))
+        .or_else(|| prescan_meta(bytes))
+        .unwrap_or_else(|| detect(bytes));
+
+    // `decode` removes the byte order mark
+    let (text, encoding, _) = encoding.decode(bytes);
+
+    (text.into_owned(), encoding)
+}
+
+/// returns the encoding named by the `charset` parameter of a Content-Type
+pub fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+
+        if !key.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+
+        Encoding::for_label(
+            value
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'')
+                .as_bytes(),
+        )
+    })
+}
+
+/// searches the start of the page for a charset declared in a `<meta>` tag
+///
+/// like browsers do, utf-16 declarations are read as utf-8 (the bytes could be read as ascii after all)
+pub fn prescan_meta(bytes: &[u8]) -> Option<&'static Encoding> {
+    let start =
+        String::from_utf8_lossy(&bytes[..bytes.len().min(PRESCAN_SIZE)]).to_ascii_lowercase();
+
+    let mut rest = start.as_str();
+
+    while let Some(position) = rest.find("<meta") {
+        rest = &rest[position + "<meta".len()..];
+
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+
+        let value = match tag.find("charset") {
+            Some(position) => tag[position + "charset".len()..].trim_start(),
+            None => continue,
+        };
+
+        let value = match value.strip_prefix('=') {
+            Some(value) => value.trim_start().trim_start_matches(['"', '\'']),
+            None => continue,
+        };
+
+        let label = &value[..value
+            .find(|c: char| c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace())
+            .unwrap_or(value.len())];
+
+        if let Some(encoding) = Encoding::for_label(label.as_bytes()) {
+            if encoding == UTF_16BE || encoding == UTF_16LE {
+                return Some(UTF_8);
+            }
+            if encoding == X_USER_DEFINED {
+                return Some(encoding_rs::WINDOWS_1252);
+            }
+
+            return Some(encoding);
+        }
+    }
+
+    None
+}
+
+/// guesses the encoding from the bytes (used if the page doesn't declare one)
+pub fn detect(bytes: &[u8]) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+
+    detector.guess(None, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+    use super::{charset_from_content_type, decode, detect, prescan_meta};
+
+    #[test]
+    fn charset_of_content_type() {
+        assert_eq!(
+            charset_from_content_type("text/html; charset=ISO-8859-1"),
+            Some(WINDOWS_1252)
+        );
+        assert_eq!(
+            charset_from_content_type("text/html;Charset=\"shift_jis\""),
+            Some(SHIFT_JIS)
+        );
+        assert_eq!(charset_from_content_type("text/html"), None);
+        assert_eq!(
+            charset_from_content_type("text/html; charset=nonsense"),
+            None
+        );
+    }
+
+    #[test]
+    fn charset_of_meta() {
+        assert_eq!(
+            prescan_meta(b"<html><head><META CHARSET=\"windows-1252\">"),
+            Some(WINDOWS_1252)
+        );
+        assert_eq!(
+            prescan_meta(
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\">"
+            ),
+            Some(SHIFT_JIS)
+        );
+        assert_eq!(prescan_meta(b"<meta charset=utf-16>"), Some(UTF_8));
+        assert_eq!(prescan_meta(b"<meta name=\"viewport\">"), None);
+    }
+
+    #[test]
+    fn decodes_latin1() {
+        // "Grüezi" in windows-1252
+        let bytes = b"<!doctype html><html><body>Gr\xFCezi</body></html>";
+
+        let (text, encoding) = decode(bytes, Some("text/html; charset=iso-8859-1"));
+
+        assert_eq!(encoding, WINDOWS_1252);
+        assert!(text.contains("Grüezi"));
+    }
+
+    #[test]
+    fn decodes_shift_jis_from_meta() {
+        // "日本語" in shift_jis
+        let bytes = b"<html><head><meta charset=\"shift_jis\"></head><body>\x93\xFA\x96\x7B\x8C\xEA</body></html>";
+
+        let (text, encoding) = decode(bytes, Some("text/html"));
+
+        assert_eq!(encoding, SHIFT_JIS);
+        assert!(text.contains("日本語"));
+    }
+
+    #[test]
+    fn bom_wins() {
+        let (text, encoding) = decode(
+            b"\xEF\xBB\xBF<html>\xC3\xBC</html>",
+            Some("text/html; charset=windows-1252"),
+        );
+
+        assert_eq!(encoding, UTF_8);
+        assert_eq!(text, "<html>ü</html>");
+    }
+
+    #[test]
+    fn detects_undeclared() {
+        assert_eq!(detect("<p>Grüezi mitenand</p>".as_bytes()), UTF_8);
+        assert_eq!(
+            detect(b"<p>Sch\xF6ne Gr\xFC\xDFe aus Z\xFCrich, wir w\xFCnschen frohe Feiertage</p>"),
+            WINDOWS_1252
+        );
+
+        // invalid bytes are replaced instead of panicking
+        let (text, _) = decode(b"<p>\xFF\xFE\xFD</p>", Some("text/html; charset=utf-8"));
+        assert!(text.contains('\u{FFFD}'));
+    }
+}
@@ -0,0 +1,556 @@
+pub mod connector;
+pub mod dns_cache;
+
+use std::time::{Duration, Instant};
+
+use hyper::{body::HttpBody, header, Body, Client, Request, Response, StatusCode};
+use tokio::time::timeout;
+
+use self::{connector::Connector, dns_cache::CachingResolver};
+
+use super::{
+    charset::decode,
+    html::{Html, HtmlGetterError},
+    html_getter::{parse_retry_after, parse_uri, redirect_target, MAX_REDIRECTS, USER_AGENT},
+    mime_sniff::may_be_html,
+};
+
+/// status codes whose `Location` header is followed
+const REDIRECT_CODES: [StatusCode; 5] = [
+    StatusCode::MOVED_PERMANENTLY,
+    StatusCode::FOUND,
+    StatusCode::SEE_OTHER,
+    StatusCode::TEMPORARY_REDIRECT,
+    StatusCode::PERMANENT_REDIRECT,
+];
+
+/// how long unused connections are kept in the pool
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// unused connections kept per host
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+
+/// Limits that keep a single page from blocking a worker
+#[derive(Debug, Clone)]
+pub struct FetchLimits {
+    /// how long opening a connection may take
+    pub connect_timeout: Duration,
+    /// how long the server may stay silent (before the headers or between two parts of the body)
+    pub read_timeout: Duration,
+    /// how long fetching a page may take, including all redirects
+    pub total_timeout: Duration,
+    /// bodies are cut off after this many bytes
+    pub max_body_size: usize,
+    /// how many redirects are followed before giving up
+    pub max_redirects: usize,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            total_timeout: Duration::from_secs(60),
+            max_body_size: 5 * 1024 * 1024,
+            max_redirects: MAX_REDIRECTS,
+        }
+    }
+}
+
+/// Sends all requests of a crawl
+///
+/// the fetcher owns a connection pool (keep-alive, HTTP/2 where the server supports it) and a DNS cache,
+/// it should be created once and cloned into every worker, clones share the pool and the cache
+#[derive(Clone)]
+pub struct Fetcher {
+    client: Client<Connector, Body>,
+    limits: FetchLimits,
+}
+
+impl Fetcher {
+    /// # Panics
+    /// Panics if the tls backend can't be initialized
+    pub fn new(limits: FetchLimits) -> Self {
+        let connector = Connector::new(CachingResolver::new(), limits.connect_timeout).unwrap();
+
+        let client = Client::builder()
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+            .build(connector);
+
+        Self { client, limits }
+    }
+
+    /// sends a GET request to the link, redirects aren't followed
+    ///
+    /// returns `HtmlGetterError::Timeout` if the response headers don't arrive within the read timeout
+    pub async fn get(&self, link: &str) -> Result<Response<Body>, HtmlGetterError> {
+        let request = Request::get(parse_uri(link)?)
+            .header(header::USER_AGENT, USER_AGENT)
+            .body(Body::empty())
+            .map_err(|_| HtmlGetterError::UrlError(link.to_string()))?;
+
+        timeout(self.limits.read_timeout, self.client.request(request))
+            .await
+            .map_err(|_| HtmlGetterError::Timeout(self.limits.read_timeout))?
+            .map_err(|err| {
+                if is_timeout(&err) {
+                    HtmlGetterError::Timeout(self.limits.connect_timeout)
+                } else {
+                    HtmlGetterError::GetError(err)
+                }
+            })
+    }
+
+    /// reads the body up to `limit` bytes, the rest isn't downloaded
+    ///
+    /// returns `HtmlGetterError::Timeout` if the server stays silent for longer than the read timeout
+    pub async fn read_body(
+        &self,
+        mut body: Body,
+        limit: usize,
+    ) -> Result<Vec<u8>, HtmlGetterError> {
+        let mut bytes = Vec::new();
+
+        while bytes.len() < limit {
+            let chunk = match timeout(self.limits.read_timeout, body.data())
+                .await
+                .map_err(|_| HtmlGetterError::Timeout(self.limits.read_timeout))?
+            {
+                Some(chunk) => chunk.map_err(HtmlGetterError::GetError)?,
+                None => break,
+            };
+
+            bytes.extend_from_slice(&chunk[..chunk.len().min(limit - bytes.len())]);
+        }
+
+        Ok(bytes)
+    }
+
+    /// # Returns valid html from a link or an error if the page isn't html
+    ///
+    /// redirects (301, 302, 303, 307 and 308) are followed up to `max_redirects` times,
+    /// the returned html contains the final url and the followed redirects
+    ///
+    /// returns `HtmlGetterError::Timeout` if the page takes longer than the total timeout
+    pub async fn fetch(&self, link: &str) -> Result<Html, HtmlGetterError> {
+        timeout(self.limits.total_timeout, self.fetch_page(link))
+            .await
+            .map_err(|_| HtmlGetterError::Timeout(self.limits.total_timeout))?
+    }
+
+    /// requests the current url of the chain, a redirect is returned instead of followed
+    ///
+    /// used by the crawler, which checks the robots.txt and waits for the host before every redirect it follows,
+    /// all requests of the chain share the total timeout (the waiting in between isn't counted)
+    pub async fn fetch_once(&self, chain: &mut RedirectChain) -> Result<Fetched, HtmlGetterError> {
+        let start = Instant::now();
+        let result = timeout(chain.remaining, self.request(chain.url())).await;
+
+        chain.remaining = chain.remaining.saturating_sub(start.elapsed());
+
+        result.map_err(|_| HtmlGetterError::Timeout(self.limits.total_timeout))?
+    }
+
+    /// starts following the redirects of the link, limited to `max_redirects` and the total timeout
+    pub fn redirect_chain(&self, link: &str) -> Result<RedirectChain, HtmlGetterError> {
+        Ok(RedirectChain {
+            url: parse_uri(link)?.to_string(),
+            followed: vec![],
+            max_redirects: self.limits.max_redirects,
+            remaining: self.limits.total_timeout,
+        })
+    }
+
+    async fn fetch_page(&self, link: &str) -> Result<Html, HtmlGetterError> {
+        let mut chain = self.redirect_chain(link)?;
+
+        loop {
+            match self.request(chain.url()).await? {
+                Fetched::Page(html) => return Ok(chain.finish(html)),
+                Fetched::Redirect(target) => chain.follow(target)?,
+            }
+        }
+    }
+
+    async fn request(&self, url: &str) -> Result<Fetched, HtmlGetterError> {
+        let response = self.get(url).await?;
+
+        let status = response.status();
+
+        if REDIRECT_CODES.contains(&status) {
+            let target = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or(HtmlGetterError::StatusCode(status))?;
+
+            return redirect_target(url, target)
+                .map(Fetched::Redirect)
+                .ok_or_else(|| HtmlGetterError::UrlError(target.to_string()));
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            return Err(HtmlGetterError::TooManyRequests(
+                status,
+                response
+                    .headers()
+                    .get(header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after),
+            ));
+        }
+
+        if status.is_server_error() {
+            return Err(HtmlGetterError::ServerError(status));
+        }
+
+        if status != StatusCode::OK {
+            return Err(HtmlGetterError::StatusCode(status));
+        }
+
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        // don't download bodies that are known not to be html, unknown types are sniffed
+        if !may_be_html(content_type.as_deref()) {
+            return Err(HtmlGetterError::NotHTML(content_type));
+        }
+
+        let bytes = self
+            .read_body(response.into_body(), self.limits.max_body_size)
+            .await?;
+
+        let (text, _) = decode(&bytes, content_type.as_deref());
+
+        Html::with_content_type(&text, content_type.as_deref()).map(Fetched::Page)
+    }
+}
+
+/// The answer to a single request, see `Fetcher::fetch_once`
+pub enum Fetched {
+    Page(Html),
+    /// the url the page redirects to
+    Redirect(String),
+}
+
+/// The redirects followed while fetching a page
+pub struct RedirectChain {
+    url: String,
+    /// the urls that redirected, starts with the requested url
+    followed: Vec<String>,
+    max_redirects: usize,
+    /// the part of the total timeout that the next requests may take
+    remaining: Duration,
+}
+
+impl RedirectChain {
+    /// the url that has to be requested next
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// continues with the url the current one redirects to
+    ///
+    /// returns an error if the target was already visited or more than `max_redirects` redirects were followed
+    pub fn follow(&mut self, target: String) -> Result<(), HtmlGetterError> {
+        self.followed.push(std::mem::replace(&mut self.url, target));
+
+        if self.followed.contains(&self.url) {
+            return Err(HtmlGetterError::RedirectLoop(self.url.clone()));
+        }
+        if self.followed.len() > self.max_redirects {
+            return Err(HtmlGetterError::TooManyRedirects(self.max_redirects));
+        }
+
+        Ok(())
+    }
+
+    /// stores the final url and the followed redirects in the page
+    pub fn finish(self, html: Html) -> Html {
+        html.fetched_from(&self.url, self.followed)
+    }
+}
+
+/// checks if the error was caused by a timed out connection attempt
+fn is_timeout(err: &hyper::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            return err.kind() == std::io::ErrorKind::TimedOut;
+        }
+
+        source = err.source();
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use hyper::{
+        header,
+        service::{make_service_fn, service_fn},
+        Body, Response, Server, StatusCode,
+    };
+
+    use crate::page_scraper::html::HtmlGetterError;
+
+    use super::{FetchLimits, Fetched, Fetcher};
+
+    /// starts a local server that redirects `/hop/n` to `/hop/n-1`, `/hop/0` to `/page` and `/loop` to itself
+    ///
+    /// `/slowhop/n` redirects like `/hop/n` after 400 ms, `/slow` answers after a second, `/large` sends 100 KiB and `/error` fails with 500
+    ///
+    /// the returned counter contains the amount of accepted connections
+    fn serve_redirects() -> (SocketAddr, Arc<AtomicUsize>) {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&connections);
+
+        let make_service = make_service_fn(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+
+            async {
+                Ok::<_, Infallible>(service_fn(|request: hyper::Request<Body>| async move {
+                    let path = request.uri().path().to_string();
+
+                    let hop = path
+                        .strip_prefix("/hop/")
+                        .or_else(|| path.strip_prefix("/slowhop/"));
+
+                    let location = match hop {
+                        Some("0") => Some("/page".to_string()),
+                        Some(n) => Some(format!(
+                            "{}{}",
+                            &path[..path.len() - n.len()],
+                            n.parse::<u32>().unwrap() - 1
+                        )),
+                        None if path == "/loop" => Some("/loop".to_string()),
+                        None => None,
+                    };
+
+                    if path == "/slow" {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                    if path.starts_with("/slowhop/") {
+                        tokio::time::sleep(Duration::from_millis(400)).await;
+                    }
+
+                    if path == "/error" {
+                        return Ok(Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .body(Body::empty())
+                            .unwrap());
+                    }
+
+                    let body = match path.as_str() {
+                        "/large" => format!("<!doctype html><html>{}", "a".repeat(100 * 1024)),
+                        _ => "<!doctype html><html></html>".to_string(),
+                    };
+
+                    let response = match location {
+                        Some(location) => Response::builder()
+                            .status(StatusCode::MOVED_PERMANENTLY)
+                            .header(header::LOCATION, location),
+                        None => Response::builder().header(header::CONTENT_TYPE, "text/html"),
+                    };
+
+                    Ok::<_, Infallible>(response.body(Body::from(body)).unwrap())
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = server.local_addr();
+
+        tokio::spawn(server);
+
+        (address, connections)
+    }
+
+    #[tokio::test]
+    async fn follows_redirects() {
+        let (address, _) = serve_redirects();
+
+        let html = Fetcher::new(FetchLimits {
+            max_redirects: 2,
+            ..Default::default()
+        })
+        .fetch(&format!("http://{address}/hop/1"))
+        .await
+        .unwrap();
+
+        assert_eq!(html.url.unwrap(), format!("http://{address}/page"));
+        assert_eq!(
+            html.redirects,
+            vec![
+                format!("http://{address}/hop/1"),
+                format!("http://{address}/hop/0")
+            ]
+        );
+
+        let html = Fetcher::new(FetchLimits {
+            max_redirects: 2,
+            ..Default::default()
+        })
+        .fetch(&format!("http://{address}/page"))
+        .await
+        .unwrap();
+        assert!(html.redirects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn redirect_limits() {
+        let (address, _) = serve_redirects();
+
+        assert!(matches!(
+            Fetcher::new(FetchLimits {
+                max_redirects: 2,
+                ..Default::default()
+            })
+            .fetch(&format!("http://{address}/hop/2"))
+            .await,
+            Err(HtmlGetterError::TooManyRedirects(2))
+        ));
+        assert!(matches!(
+            Fetcher::new(FetchLimits {
+                max_redirects: 0,
+                ..Default::default()
+            })
+            .fetch(&format!("http://{address}/hop/0"))
+            .await,
+            Err(HtmlGetterError::TooManyRedirects(0))
+        ));
+        assert!(matches!(
+            Fetcher::new(FetchLimits {
+                max_redirects: 10,
+                ..Default::default()
+            })
+            .fetch(&format!("http://{address}/loop"))
+            .await,
+            Err(HtmlGetterError::RedirectLoop(url)) if url == format!("http://{address}/loop")
+        ));
+    }
+
+    /// all requests (of all clones) go over the same kept-alive connection
+    #[tokio::test]
+    async fn reuses_connections() {
+        let (address, connections) = serve_redirects();
+
+        let fetcher = Fetcher::new(FetchLimits {
+            max_redirects: 10,
+            ..Default::default()
+        });
+
+        fetcher
+            .fetch(&format!("http://{address}/hop/3"))
+            .await
+            .unwrap();
+        fetcher
+            .clone()
+            .fetch(&format!("http://{address}/page"))
+            .await
+            .unwrap();
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn limits_body_size() {
+        let (address, _) = serve_redirects();
+
+        let html = Fetcher::new(FetchLimits {
+            max_body_size: 1024,
+            ..Default::default()
+        })
+        .fetch(&format!("http://{address}/large"))
+        .await
+        .unwrap();
+
+        assert_eq!(html.text.len(), 1024);
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let (address, _) = serve_redirects();
+
+        assert!(matches!(
+            Fetcher::new(FetchLimits {
+                read_timeout: Duration::from_millis(200),
+                ..Default::default()
+            })
+            .fetch(&format!("http://{address}/slow"))
+            .await,
+            Err(HtmlGetterError::Timeout(limit)) if limit == Duration::from_millis(200)
+        ));
+        assert!(matches!(
+            Fetcher::new(FetchLimits {
+                total_timeout: Duration::from_millis(200),
+                ..Default::default()
+            })
+            .fetch(&format!("http://{address}/slow"))
+            .await,
+            Err(HtmlGetterError::Timeout(_))
+        ));
+        assert!(Fetcher::new(FetchLimits::default())
+            .fetch(&format!("http://{address}/slow"))
+            .await
+            .is_ok());
+    }
+
+    /// the total timeout limits all requests of a page, not each redirect on its own
+    #[tokio::test]
+    async fn times_out_over_redirects() {
+        let (address, _) = serve_redirects();
+
+        let fetcher = Fetcher::new(FetchLimits {
+            total_timeout: Duration::from_millis(1000),
+            ..Default::default()
+        });
+
+        let mut chain = fetcher
+            .redirect_chain(&format!("http://{address}/slowhop/3"))
+            .unwrap();
+
+        let err = loop {
+            match fetcher.fetch_once(&mut chain).await {
+                Ok(Fetched::Redirect(target)) => chain.follow(target).unwrap(),
+                Ok(Fetched::Page(_)) => panic!("the redirects took longer than the total timeout"),
+                Err(err) => break err,
+            }
+        };
+
+        assert!(matches!(err, HtmlGetterError::Timeout(_)));
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_transient() {
+        let (address, _) = serve_redirects();
+
+        let err = Fetcher::new(FetchLimits::default())
+            .fetch(&format!("http://{address}/error"))
+            .await
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            err,
+            HtmlGetterError::ServerError(StatusCode::INTERNAL_SERVER_ERROR)
+        ));
+        assert!(err.is_transient());
+        assert!(!HtmlGetterError::NotHTML(None).is_transient());
+    }
+}
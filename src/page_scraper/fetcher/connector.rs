@@ -0,0 +1,143 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::{
+    client::{
+        connect::{Connected, Connection},
+        HttpConnector,
+    },
+    service::Service,
+    Uri,
+};
+use hyper_tls::{HttpsConnector, MaybeHttpsStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+use super::dns_cache::CachingResolver;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// how long idle tcp connections are kept alive
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Opens http and https connections, https connections offer HTTP/2 through ALPN
+///
+/// hyper-tls doesn't tell hyper which protocol was negotiated, so this wrapper does
+#[derive(Clone)]
+pub struct Connector {
+    https: HttpsConnector<HttpConnector<CachingResolver>>,
+}
+
+impl Connector {
+    pub fn new(
+        resolver: CachingResolver,
+        connect_timeout: Duration,
+    ) -> Result<Self, native_tls::Error> {
+        let mut http = HttpConnector::new_with_resolver(resolver);
+        http.enforce_http(false);
+        http.set_keepalive(Some(TCP_KEEPALIVE));
+        http.set_connect_timeout(Some(connect_timeout));
+        http.set_nodelay(true);
+
+        let tls = native_tls::TlsConnector::builder()
+            .request_alpns(&["h2", "http/1.1"])
+            .build()?;
+
+        Ok(Self {
+            https: HttpsConnector::from((http, tls.into())),
+        })
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Stream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.https.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.https.call(uri);
+
+        Box::pin(async move {
+            let stream = connecting.await?;
+
+            let h2 = match &stream {
+                MaybeHttpsStream::Https(tls) => {
+                    matches!(tls.get_ref().negotiated_alpn(), Ok(Some(protocol)) if protocol == b"h2")
+                }
+                MaybeHttpsStream::Http(_) => false,
+            };
+
+            Ok(Stream { inner: stream, h2 })
+        })
+    }
+}
+
+/// A connection opened by the `Connector`
+pub struct Stream {
+    inner: MaybeHttpsStream<TcpStream>,
+    /// the server agreed to speak HTTP/2
+    h2: bool,
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        let connected = self.inner.connected();
+
+        if self.h2 {
+            connected.negotiated_h2()
+        } else {
+            connected
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    future::{self, Future},
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use hyper::{client::connect::dns::Name, service::Service};
+
+/// how long resolved addresses are reused (the system resolver doesn't tell the real ttl)
+const CACHE_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// how many hosts are remembered, expired (and then the oldest) entries are removed once the cache is full
+const MAX_ENTRIES: usize = 10_000;
+
+type Addresses = std::vec::IntoIter<SocketAddr>;
+/// resolved addresses per host together with the time they were resolved
+type Entries = HashMap<String, (Instant, Vec<SocketAddr>)>;
+
+/// A DNS resolver that remembers the addresses of every host for `CACHE_DURATION`
+///
+/// lookups are done with the system resolver on the blocking thread pool, like hyper's default resolver does
+#[derive(Clone)]
+pub struct CachingResolver {
+    entries: Arc<Mutex<Entries>>,
+    capacity: usize,
+}
+
+impl Default for CachingResolver {
+    fn default() -> Self {
+        Self::with_capacity(MAX_ENTRIES)
+    }
+}
+
+impl CachingResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a resolver that remembers at most `capacity` hosts
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Arc::default(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// returns the cached addresses of the host if they haven't expired
+    pub fn cached(&self, host: &str) -> Option<Vec<SocketAddr>> {
+        let entries = self.entries.lock().unwrap();
+
+        entries
+            .get(host)
+            .filter(|(resolved, _)| resolved.elapsed() < CACHE_DURATION)
+            .map(|(_, addresses)| addresses.clone())
+    }
+
+    /// stores the addresses of the host, makes room by removing expired entries or the oldest one if the cache is full
+    pub fn insert(&self, host: &str, addresses: Vec<SocketAddr>) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.capacity && !entries.contains_key(host) {
+            entries.retain(|_, (resolved, _)| resolved.elapsed() < CACHE_DURATION);
+        }
+
+        if entries.len() >= self.capacity && !entries.contains_key(host) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (resolved, _))| *resolved)
+                .map(|(host, _)| host.clone());
+
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(host.to_string(), (Instant::now(), addresses));
+    }
+}
+
+impl Service<Name> for CachingResolver {
+    type Response = Addresses;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Addresses, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let host = name.as_str().to_string();
+
+        if let Some(addresses) = self.cached(&host) {
+            return Box::pin(future::ready(Ok(addresses.into_iter())));
+        }
+
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            let lookup = host.clone();
+
+            // the port is replaced by the connector
+            let addresses = tokio::task::spawn_blocking(move || {
+                (lookup.as_str(), 0)
+                    .to_socket_addrs()
+                    .map(|addresses| addresses.collect::<Vec<_>>())
+            })
+            .await
+            .map_err(io::Error::other)??;
+
+            resolver.insert(&host, addresses.clone());
+
+            Ok(addresses.into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use hyper::{client::connect::dns::Name, service::Service};
+
+    use super::CachingResolver;
+
+    #[tokio::test]
+    async fn resolves_and_caches() {
+        let mut resolver = CachingResolver::new();
+
+        assert_eq!(resolver.cached("localhost"), None);
+
+        let addresses = resolver
+            .call("localhost".parse::<Name>().unwrap())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert!(addresses.iter().any(|address| address.ip().is_loopback()));
+        assert_eq!(resolver.cached("localhost").unwrap(), addresses);
+    }
+
+    #[tokio::test]
+    async fn uses_cached_addresses() {
+        let mut resolver = CachingResolver::new();
+
+        let address: SocketAddr = "10.1.2.3:0".parse().unwrap();
+        resolver.insert("cached.invalid", vec![address]);
+
+        let addresses = resolver
+            .call("cached.invalid".parse::<Name>().unwrap())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(addresses, vec![address]);
+    }
+
+    #[test]
+    fn limits_size() {
+        let resolver = CachingResolver::with_capacity(2);
+        let address: SocketAddr = "10.1.2.3:0".parse().unwrap();
+
+        resolver.insert("a.invalid", vec![address]);
+        std::thread::sleep(Duration::from_millis(1));
+        resolver.insert("b.invalid", vec![address]);
+        resolver.insert("c.invalid", vec![address]);
+
+        // the oldest host made room
+        assert_eq!(resolver.cached("a.invalid"), None);
+        assert_eq!(resolver.cached("b.invalid").unwrap(), vec![address]);
+        assert_eq!(resolver.cached("c.invalid").unwrap(), vec![address]);
+        assert_eq!(resolver.entries.lock().unwrap().len(), 2);
+    }
+}
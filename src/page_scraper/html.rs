@@ -1,31 +1,184 @@
-use super::html_parser::is_html;
+use std::{error::Error, fmt, time::Duration};
+
+use hyper::StatusCode;
+
+use super::mime_sniff::{sniff, SniffedType};
 
 pub struct Html {
     pub text: String,
+    /// url the page was finally loaded from (after following all redirects), only known for fetched pages
+    pub url: Option<String>,
+    /// urls that redirected to the page in the order they were followed, starts with the requested url
+    pub redirects: Vec<String>,
+    /// the Content-Type the page was served with
+    pub content_type: Option<String>,
     _private: (),
 }
 
-/// Creates new Html instance from a string
-///
-/// # Panics
-/// Panics if the string isn't valid html
 impl Html {
-    pub fn new(text: &str) -> Self {
-        if !is_html(text) {
-            panic!("Not valid HTML!");
+    /// Creates new Html instance from a string
+    ///
+    /// returns `HtmlGetterError::NotHTML` if the content doesn't look like html
+    #[allow(dead_code)]
+    pub fn new(text: &str) -> Result<Self, HtmlGetterError> {
+        Self::with_content_type(text, None)
+    }
+
+    /// Creates new Html instance from a string that was served with the given `Content-Type`
+    ///
+    /// the content is only sniffed if the Content-Type is missing or unknown
+    pub fn with_content_type(
+        text: &str,
+        content_type: Option<&str>,
+    ) -> Result<Self, HtmlGetterError> {
+        if sniff(content_type, text.as_bytes()) != SniffedType::Html {
+            return Err(HtmlGetterError::NotHTML(
+                content_type.map(|content_type| content_type.to_string()),
+            ));
         }
 
-        Html {
+        Ok(Html {
             text: text.to_string(),
+            url: None,
+            redirects: vec![],
+            content_type: content_type.map(|content_type| content_type.to_string()),
             _private: (),
-        }
+        })
+    }
+
+    /// sets the url the page was loaded from and the redirects that lead to it
+    pub fn fetched_from(mut self, url: &str, redirects: Vec<String>) -> Self {
+        self.url = Some(url.to_string());
+        self.redirects = redirects;
+        self
     }
 }
 
+/// Why a page couldn't be fetched
 #[derive(Debug)]
 pub enum HtmlGetterError {
-    NotHTML,
-    GetError,
-    StatusCode,
-    UrlError,
+    /// the page isn't html, contains the Content-Type the server sent
+    NotHTML(Option<String>),
+    /// the request couldn't be sent or the response couldn't be read (connection, tls or io error)
+    GetError(hyper::Error),
+    /// the server answered with a status code that isn't handled
+    StatusCode(StatusCode),
+    /// the url can't be requested, contains the url
+    UrlError(String),
+    /// the server asked to slow down (429 or 503), contains the status code and the `Retry-After` delay if it was sent
+    TooManyRequests(StatusCode, Option<Duration>),
+    /// a redirect pointed back to a url that was already visited while following redirects, contains that url
+    RedirectLoop(String),
+    /// more redirects than allowed were followed, contains the limit
+    TooManyRedirects(usize),
+    /// the server answered with a 5xx status code (other than 503)
+    ServerError(StatusCode),
+    /// the server took too long to connect, answer or send the page, contains the exceeded limit
+    Timeout(Duration),
+}
+
+impl HtmlGetterError {
+    /// errors that may go away if the request is sent again later
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            HtmlGetterError::GetError(_)
+                | HtmlGetterError::ServerError(_)
+                | HtmlGetterError::Timeout(_)
+                | HtmlGetterError::TooManyRequests(..)
+        )
+    }
+
+    /// returns the status code the server answered with if the error was caused by it
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            HtmlGetterError::StatusCode(status)
+            | HtmlGetterError::ServerError(status)
+            | HtmlGetterError::TooManyRequests(status, _) => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HtmlGetterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HtmlGetterError::NotHTML(Some(content_type)) => {
+                write!(f, "not html (Content-Type: {content_type})")
+            }
+            HtmlGetterError::NotHTML(None) => write!(f, "not html"),
+            HtmlGetterError::GetError(err) => write!(f, "request failed: {err}"),
+            HtmlGetterError::StatusCode(status) => write!(f, "unexpected status code {status}"),
+            HtmlGetterError::UrlError(url) => write!(f, "invalid url: {url}"),
+            HtmlGetterError::TooManyRequests(_, Some(retry_after)) => write!(
+                f,
+                "too many requests, retry after {}s",
+                retry_after.as_secs()
+            ),
+            HtmlGetterError::TooManyRequests(_, None) => write!(f, "too many requests"),
+            HtmlGetterError::RedirectLoop(url) => write!(f, "redirect loop at {url}"),
+            HtmlGetterError::TooManyRedirects(limit) => {
+                write!(f, "more than {limit} redirects")
+            }
+            HtmlGetterError::ServerError(status) => write!(f, "server error {status}"),
+            HtmlGetterError::Timeout(limit) => {
+                write!(f, "timed out after {}ms", limit.as_millis())
+            }
+        }
+    }
+}
+
+impl Error for HtmlGetterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HtmlGetterError::GetError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, time::Duration};
+
+    use hyper::StatusCode;
+
+    use super::{Html, HtmlGetterError};
+
+    #[test]
+    fn keeps_content_type() {
+        let html = Html::with_content_type("<p>a</p>", Some("text/html; charset=utf-8")).unwrap();
+        assert_eq!(
+            html.content_type.as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+
+        assert!(matches!(
+            Html::with_content_type("{}", Some("application/json")),
+            Err(HtmlGetterError::NotHTML(Some(content_type))) if content_type == "application/json"
+        ));
+    }
+
+    #[test]
+    fn describes_errors() {
+        assert_eq!(
+            HtmlGetterError::StatusCode(StatusCode::NOT_FOUND).to_string(),
+            "unexpected status code 404 Not Found"
+        );
+        assert_eq!(
+            HtmlGetterError::Timeout(Duration::from_secs(2)).to_string(),
+            "timed out after 2000ms"
+        );
+        assert_eq!(
+            HtmlGetterError::ServerError(StatusCode::BAD_GATEWAY).status(),
+            Some(StatusCode::BAD_GATEWAY)
+        );
+        assert_eq!(
+            HtmlGetterError::TooManyRequests(StatusCode::SERVICE_UNAVAILABLE, None).status(),
+            Some(StatusCode::SERVICE_UNAVAILABLE)
+        );
+        assert!(HtmlGetterError::RedirectLoop("http://a.ch/".to_string())
+            .source()
+            .is_none());
+    }
 }
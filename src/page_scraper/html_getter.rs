@@ -1,70 +1,113 @@
-use hyper::{Client, StatusCode, Uri};
-use hyper_tls::HttpsConnector;
+use std::time::{Duration, SystemTime};
+
+use hyper::Uri;
+use url::Url;
 
 use super::{
+    fetcher::{FetchLimits, Fetcher},
     html::{Html, HtmlGetterError},
-    html_parser::is_html,
+    url_normalizer::resolve_link,
 };
 
-/// # Returns valid html from a link or an error if the page isn't html
-///
-/// # Panics
-/// Panics if the uri can't be parsed or the bytes can't be read
-pub async fn html_getter(link: &str) -> Result<Html, HtmlGetterError> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, hyper::Body>(https);
-
-    let mut uri: Uri;
-    if let Ok(result) = link.parse::<Uri>() {
-        uri = result;
-    } else {
-        return Err(HtmlGetterError::UrlError);
-    }
+/// User-Agent header sent with every request
+pub const USER_AGENT: &str = concat!("guugle/", env!("CARGO_PKG_VERSION"));
 
-    if uri.scheme().is_none() {
-        if let Ok(result) = (format!("http://{}", link)).parse() {
-            uri = result;
-        } else {
-            return Err(HtmlGetterError::UrlError);
-        }
+/// how many redirects are followed by default before giving up
+pub const MAX_REDIRECTS: usize = 10;
+
+/// Parses a link into an uri, links without a scheme are treated as http
+pub fn parse_uri(link: &str) -> Result<Uri, HtmlGetterError> {
+    let uri = link
+        .parse::<Uri>()
+        .map_err(|_| HtmlGetterError::UrlError(link.to_string()))?;
+
+    if uri.scheme().is_some() {
+        return Ok(uri);
     }
 
-    let response = client.get(uri).await;
+    format!("http://{}", link)
+        .parse()
+        .map_err(|_| HtmlGetterError::UrlError(link.to_string()))
+}
 
-    if let Ok(ok) = response {
-        if ok.status() != StatusCode::OK {
-            return Err(HtmlGetterError::StatusCode);
-        }
+/// Parses the value of a `Retry-After` header (either seconds or a http date)
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
 
-        let mut response = ok;
+    let date = httpdate::parse_http_date(value.trim()).ok()?;
 
-        if !response.headers().contains_key("content-type")
-            && !response.headers()["content-type"]
-                .to_str()
-                .unwrap()
-                .contains("html")
-        {
-            println!("{:?}", response.headers());
-            return Err(HtmlGetterError::NotHTML);
-        }
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
 
-        let bytes = hyper::body::to_bytes(response.body_mut()).await.unwrap();
+/// Resolves the `Location` of a redirect against the url that was requested
+///
+/// returns `None` if the target isn't a http(s) url
+pub fn redirect_target(url: &str, location: &str) -> Option<String> {
+    let base = Url::parse(url).ok()?;
 
-        let text = String::from_utf8(bytes.to_vec()).unwrap();
+    let target = resolve_link(&base, location, false)?;
 
-        if !is_html(&text) {
-            println!("{text}");
-            return Err(HtmlGetterError::NotHTML);
-        }
+    matches!(Url::parse(&target).ok()?.scheme(), "http" | "https").then_some(target)
+}
 
-        return Ok(Html::new(&text));
-    } else {
-        return Err(HtmlGetterError::GetError);
-    }
+/// # Returns valid html from a link or an error if the page isn't html
+///
+/// follows up to `MAX_REDIRECTS` redirects, every call opens new connections so a shared `Fetcher` should be used for more than one page
+pub async fn html_getter(link: &str) -> Result<Html, HtmlGetterError> {
+    Fetcher::new(FetchLimits::default()).fetch(link).await
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn retry_after() {
+        assert_eq!(
+            super::parse_retry_after("120"),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            super::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+
+        let in_future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(600));
+        let delay = super::parse_retry_after(&in_future).unwrap();
+        assert!(delay > Duration::from_secs(500) && delay <= Duration::from_secs(600));
+
+        assert_eq!(super::parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn redirect_targets() {
+        assert_eq!(
+            super::redirect_target("http://a.com/x/y", "/z").unwrap(),
+            "http://a.com/z"
+        );
+        assert_eq!(
+            super::redirect_target("http://a.com/docs", "https://A.com:443/docs/").unwrap(),
+            "https://a.com/docs/"
+        );
+        assert_eq!(
+            super::redirect_target("http://a.com/", "other#part").unwrap(),
+            "http://a.com/other"
+        );
+        assert_eq!(
+            super::redirect_target("http://a.com/", "mailto:a@a.com"),
+            None
+        );
+        assert_eq!(
+            super::redirect_target("http://a.com/", "ftp://a.com/file"),
+            None
+        );
+    }
+
     #[tokio::test]
     async fn html_getter_http() {
         let uri = "http://example.com/";
@@ -1,49 +1,323 @@
+use scraper::{node::Node, ElementRef, Selector};
+
 use super::{
     html::{Html, HtmlGetterError},
     html_getter::html_getter,
+    mime_sniff::{sniff, SniffedType},
 };
 
-/// Parses a string containing html and returns all links
-pub fn get_links(html: &Html) -> Vec<String> {
-    let mut start_pos;
-    let mut end_pos;
+/// elements whose text isn't shown to the reader
+const INVISIBLE_ELEMENTS: [&str; 6] = ["script", "style", "noscript", "template", "head", "title"];
 
-    let mut string = html.text.clone();
+/// elements that start on a new line, the text before and after them isn't part of the same word
+const BLOCK_ELEMENTS: [&str; 42] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "br",
+    "caption",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "option",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
 
-    let mut links = vec![];
+/// elements around the content of a page that repeat on every page of a site (menus, banners, ...)
+const BOILERPLATE_ELEMENTS: [&str; 5] = ["nav", "header", "footer", "aside", "menu"];
 
-    loop {
-        // go to the next link tag and remove previous
-        if let Some(i) = string.find("<a") {
-            start_pos = i;
-        } else {
-            break;
+/// boilerplate inside of the content, a `<header>` or `<footer>` there belongs to the article (title, author, ...)
+const CONTENT_BOILERPLATE_ELEMENTS: [&str; 3] = ["nav", "aside", "menu"];
+
+/// elements that hold the content of a page, if a page has one the rest of it is boilerplate
+const CONTENT_ELEMENTS: &str = "main, article, [role=main]";
+
+/// A link found in a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// value of the `href` attribute with all character references decoded
+    pub href: String,
+    /// visible text of the link
+    pub text: String,
+    /// value of the `rel` attribute
+    pub rel: Option<String>,
+}
+
+impl Link {
+    /// true for the links the crawler adds from a redirecting page to the page it redirects to
+    pub fn is_redirect(&self) -> bool {
+        self.rel.as_deref() == Some("redirect")
+    }
+}
+
+/// The parts of a page that are stored for searching and displaying results
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageFields {
+    pub title: Option<String>,
+    /// the meta description
+    pub description: Option<String>,
+    /// the meta keywords
+    pub keywords: Vec<String>,
+    /// the text of all `<h1>` to `<h6>` elements
+    pub headings: Vec<String>,
+    /// the visible text without boilerplate, see `Document::main_text`
+    pub text: String,
+    /// the language from `<html lang>`, see `Document::lang`
+    pub lang: Option<String>,
+}
+
+/// A html document parsed with a spec-compliant parser (html5ever)
+///
+/// comments, scripts and malformed markup are handled the same way a browser does
+pub struct Document {
+    dom: scraper::Html,
+}
+
+impl Document {
+    pub fn parse(html: &Html) -> Self {
+        Self::parse_str(&html.text)
+    }
+
+    /// parses stored content that isn't wrapped in a `Html` anymore
+    pub fn parse_str(text: &str) -> Self {
+        Self {
+            dom: scraper::Html::parse_document(text),
         }
+    }
+
+    /// returns all `<a>` and `<area>` elements that have a `href` attribute
+    pub fn links(&self) -> Vec<Link> {
+        self.select("a[href], area[href]")
+            .map(|element| Link {
+                href: element.value().attr("href").unwrap_or("").to_string(),
+                text: collapse_whitespace(&element.text().collect::<String>()),
+                rel: element.value().attr("rel").map(|rel| rel.to_string()),
+            })
+            .collect()
+    }
+
+    /// returns the `href` of the first `<base>` element
+    pub fn base_href(&self) -> Option<String> {
+        self.select("base[href]")
+            .next()?
+            .value()
+            .attr("href")
+            .map(|href| href.to_string())
+    }
+
+    /// returns the content of the `<title>` element
+    pub fn title(&self) -> Option<String> {
+        let title = collapse_whitespace(&self.select("title").next()?.text().collect::<String>());
+
+        (!title.is_empty()).then_some(title)
+    }
+
+    /// returns the content of the `<meta>` element with the given `name`, `property` or `http-equiv` (case-insensitive)
+    pub fn meta(&self, name: &str) -> Option<String> {
+        self.select("meta[content]")
+            .find(|element| {
+                ["name", "property", "http-equiv"].iter().any(|attribute| {
+                    element
+                        .value()
+                        .attr(attribute)
+                        .map(|value| value.eq_ignore_ascii_case(name))
+                        .unwrap_or(false)
+                })
+            })?
+            .value()
+            .attr("content")
+            .map(|content| content.trim().to_string())
+    }
+
+    /// returns the `lang` attribute of the `<html>` element
+    pub fn lang(&self) -> Option<String> {
+        self.dom
+            .root_element()
+            .value()
+            .attr("lang")
+            .map(|lang| lang.trim().to_lowercase())
+            .filter(|lang| !lang.is_empty())
+    }
+
+    /// returns the words of the `keywords` meta element
+    pub fn keywords(&self) -> Vec<String> {
+        self.meta("keywords")
+            .map(|keywords| {
+                keywords
+                    .split(',')
+                    .map(collapse_whitespace)
+                    .filter(|keyword| !keyword.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// returns the text of all headings in the order they appear
+    pub fn headings(&self) -> Vec<String> {
+        self.select("h1, h2, h3, h4, h5, h6")
+            .map(|element| collapse_whitespace(&element.text().collect::<String>()))
+            .filter(|heading| !heading.is_empty())
+            .collect()
+    }
 
-        string = string[start_pos + "<a".len()..].to_string();
+    /// returns the text a reader sees without menus, banners and footers
+    ///
+    /// if the page marks its content (`<main>`, `<article>`), only the text in there is returned,
+    /// pages that are nothing but boilerplate return all of their visible text
+    pub fn main_text(&self) -> String {
+        let selector = Selector::parse(CONTENT_ELEMENTS).unwrap();
+        let content = self
+            .dom
+            .select(&selector)
+            // the text of nested content elements would be returned twice
+            .filter(|element| {
+                !element
+                    .ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .any(|ancestor| selector.matches(&ancestor))
+            })
+            .collect::<Vec<_>>();
 
-        // go to ref section of link tag and remove previous
-        if let Some(i) = string.find("href=\"") {
-            start_pos = i;
+        let text = if content.is_empty() {
+            visible_text([self.dom.root_element()], &BOILERPLATE_ELEMENTS)
         } else {
-            break;
+            visible_text(content, &CONTENT_BOILERPLATE_ELEMENTS)
+        };
+
+        match text.is_empty() {
+            true => visible_text([self.dom.root_element()], &[]),
+            false => text,
         }
+    }
 
-        string = string[start_pos + "href=\"".len()..].to_string();
+    /// extracts everything that is stored of the page
+    pub fn fields(&self) -> PageFields {
+        PageFields {
+            title: self.title(),
+            description: self
+                .meta("description")
+                .map(|description| collapse_whitespace(&description))
+                .filter(|description| !description.is_empty()),
+            keywords: self.keywords(),
+            headings: self.headings(),
+            text: self.main_text(),
+            lang: self.lang(),
+        }
+    }
 
-        // find ending tag of url
-        if let Some(i) = string.find("\"") {
-            end_pos = i;
-        } else {
-            break;
+    /// returns all elements matching the css selector
+    ///
+    /// # Panics
+    /// Panics if the selector is invalid
+    pub fn select<'a>(&'a self, selector: &str) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        let selector = Selector::parse(selector).unwrap();
+
+        self.dom.select(&selector).collect::<Vec<_>>().into_iter()
+    }
+}
+
+/// returns the visible text below the elements, text inside of the `skipped` elements is left out
+fn visible_text<'a>(roots: impl IntoIterator<Item = ElementRef<'a>>, skipped: &[&str]) -> String {
+    let mut text = String::new();
+
+    for root in roots {
+        let invisible = root
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|ancestor| is_invisible(ancestor, &[]));
+
+        if !invisible {
+            push_visible_text(root, skipped, &mut text);
+            text.push(' ');
         }
+    }
+
+    collapse_whitespace(&text)
+}
+
+/// appends the visible text of the element, words are only separated at the edges of block elements
+/// (`<b>Gu</b>ugle` is one word)
+fn push_visible_text(element: ElementRef<'_>, skipped: &[&str], text: &mut String) {
+    if is_invisible(element, skipped) {
+        return;
+    }
+
+    let block = BLOCK_ELEMENTS.contains(&element.value().name());
 
-        links.push(string[..end_pos].to_string());
+    if block {
+        text.push(' ');
     }
 
-    links
+    for child in element.children() {
+        match child.value() {
+            Node::Text(content) => text.push_str(content),
+            Node::Element(_) => {
+                push_visible_text(ElementRef::wrap(child).unwrap(), skipped, text);
+            }
+            _ => {}
+        }
+    }
+
+    if block {
+        text.push(' ');
+    }
+}
+
+fn is_invisible(element: ElementRef<'_>, skipped: &[&str]) -> bool {
+    let element = element.value();
+
+    INVISIBLE_ELEMENTS.contains(&element.name())
+        || skipped.contains(&element.name())
+        || element.attr("hidden").is_some()
+}
+
+/// replaces every run of whitespace by a single space
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parses a string containing html and returns all links
+pub fn get_links(html: &Html) -> Vec<String> {
+    Document::parse(html)
+        .links()
+        .into_iter()
+        .map(|link| link.href)
+        .collect()
 }
 
+#[allow(dead_code)]
 pub async fn get_links_from_url(url: &str) -> Result<Vec<String>, HtmlGetterError> {
     let html = html_getter(url).await?;
 
@@ -52,13 +326,10 @@ pub async fn get_links_from_url(url: &str) -> Result<Vec<String>, HtmlGetterErro
     Ok(links)
 }
 
-/// Parses a string and checks if it is valid html (correct doctype)
+/// Checks if a string without a known Content-Type looks like html
+#[allow(dead_code)]
 pub fn is_html(html: &str) -> bool {
-    let start_string = "<!doctype html>";
-
-    html.to_ascii_lowercase()
-        .trim_start()
-        .starts_with(start_string)
+    sniff(None, html.as_bytes()) == SniffedType::Html
 }
 
 #[cfg(test)]
@@ -74,12 +345,23 @@ mod tests {
     }
 
     #[test]
-    fn invalid_html() {
+    fn valid_html_without_doctype() {
         let html = r#"
         <html itemscope="" itemtype="http://schema.org/WebPage" lang="de-CH"><head><meta content="text/html; charset=UTF-8" http-equiv="Content-Type"><meta content="/images/branding/googleg/1x/googleg_standard_color_128dp.png" itemprop="image"><title>Google</title>
         "#;
 
-        assert!(!super::is_html(html))
+        assert!(super::is_html(html));
+        assert!(super::is_html(
+            "\u{feff}<!-- page --><HTML><BODY>old</BODY></HTML>"
+        ));
+    }
+
+    #[test]
+    fn invalid_html() {
+        assert!(!super::is_html(r#"{"html": "<html></html>"}"#));
+        assert!(!super::is_html("%PDF-1.4"));
+        assert!(super::Html::new("plain text").is_err());
+        assert!(super::Html::with_content_type("plain text", Some("text/html")).is_ok());
     }
 
     #[test]
@@ -96,12 +378,180 @@ mod tests {
         </html>
         "#;
 
-        let links = super::get_links(&super::Html::new(html));
+        let links = super::get_links(&super::Html::new(html).unwrap());
 
         assert!(links.contains(&"www.google.ch".to_string()));
         assert!(links.contains(&"https://team-crystal.ch".to_string()));
     }
 
+    #[test]
+    fn get_links_with_any_quoting() {
+        let html = r#"
+        <!DOCTYPE html>
+        <html>
+        <body>
+        <a class="x" href='single.html'>Single</a>
+        <a href=unquoted.html>Unquoted</a>
+        <A HREF="upper.html" rel="nofollow">Upper <b>case</b></A>
+        <a href="a.com/?a=1&amp;b=2&#38;c=&#x33;">Entities</a>
+        <!-- <a href="comment.html"></a> -->
+        <script>document.write('<a href="script.html"></a>');</script>
+        <a name="no-href">Anchor</a>
+        </body>
+        </html>
+        "#;
+
+        let document = super::Document::parse(&super::Html::new(html).unwrap());
+        let links = document.links();
+
+        assert_eq!(
+            links
+                .iter()
+                .map(|link| link.href.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "single.html",
+                "unquoted.html",
+                "upper.html",
+                "a.com/?a=1&b=2&c=3"
+            ]
+        );
+        assert_eq!(links[2].text, "Upper case");
+        assert_eq!(links[2].rel.as_deref(), Some("nofollow"));
+    }
+
+    #[test]
+    fn base_href() {
+        let html = super::Html::new(
+            r#"<!doctype html><html><head><BASE target="_blank" href="https://a.com/x/?a&amp;b"></head></html>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            super::Document::parse(&html).base_href().unwrap(),
+            "https://a.com/x/?a&b"
+        );
+        assert_eq!(
+            super::Document::parse(&super::Html::new("<!doctype html><html></html>").unwrap())
+                .base_href(),
+            None
+        );
+    }
+
+    #[test]
+    fn document_fields() {
+        let html = r#"
+        <!doctype html>
+        <html lang="de-CH">
+        <head>
+            <title>  Example
+                Domain </title>
+            <meta name="Description" content=" An example ">
+            <meta property="og:title" content="Example">
+            <style>body { color: red; }</style>
+        </head>
+        <body>
+            <h1>Example   Domain</h1>
+            <script>var hidden = "not visible";</script>
+            <p>Some <em>text</em>.</p>
+        </body>
+        </html>
+        "#;
+
+        let document = super::Document::parse(&super::Html::new(html).unwrap());
+
+        assert_eq!(document.title().unwrap(), "Example Domain");
+        assert_eq!(document.meta("description").unwrap(), "An example");
+        assert_eq!(document.meta("og:title").unwrap(), "Example");
+        assert_eq!(document.meta("keywords"), None);
+        assert_eq!(document.lang().unwrap(), "de-ch");
+        assert_eq!(document.main_text(), "Example Domain Some text.");
+    }
+
+    /// inline elements don't split words, block elements do
+    #[test]
+    fn separates_blocks_only() {
+        assert_eq!(
+            super::Document::parse_str("<p><b>Gu</b>ugle finds <i>cats</i>.</p><p>Next</p>")
+                .main_text(),
+            "Guugle finds cats. Next"
+        );
+        assert_eq!(
+            super::Document::parse_str("<ul><li>One</li><li>Two</li></ul>Three<br>Four")
+                .main_text(),
+            "One Two Three Four"
+        );
+    }
+
+    #[test]
+    fn page_fields() {
+        let html = r#"
+        <html>
+        <head>
+            <title>Cats</title>
+            <meta name="description" content="All  about cats">
+            <meta name="keywords" content="cats, kittens ,, purring">
+        </head>
+        <body>
+            <header><h1>Cat Blog</h1></header>
+            <nav><a href="/">Home</a></nav>
+            <main>
+                <header><h2>Why cats   purr</h2></header>
+                <p>They are <span hidden>not</span> happy.</p>
+                <aside>Related posts</aside>
+                <article><p>Nested article.</p></article>
+            </main>
+            <footer>Copyright</footer>
+        </body>
+        </html>
+        "#;
+
+        let document = super::Document::parse_str(html);
+
+        assert_eq!(
+            document.fields(),
+            super::PageFields {
+                title: Some("Cats".to_string()),
+                description: Some("All about cats".to_string()),
+                keywords: vec!["cats", "kittens", "purring"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                headings: vec!["Cat Blog".to_string(), "Why cats purr".to_string()],
+                text: "Why cats purr They are happy. Nested article.".to_string(),
+                lang: None,
+            }
+        );
+
+        // pages without marked content keep everything but the boilerplate
+        assert_eq!(
+            super::Document::parse_str(
+                "<body><nav>Menu</nav><div>Content</div><footer>Footer</footer></body>"
+            )
+            .main_text(),
+            "Content"
+        );
+    }
+
+    /// forms are content (search pages, logins, ...) and pages that only have boilerplate aren't empty
+    #[test]
+    fn main_text_of_sparse_pages() {
+        assert_eq!(
+            super::Document::parse_str(
+                "<body><form><label>Search the catalogue</label><input name=\"q\"></form></body>"
+            )
+            .main_text(),
+            "Search the catalogue"
+        );
+        assert_eq!(
+            super::Document::parse_str(
+                "<body><header>Cat Blog</header><nav>Home</nav><footer>Copyright</footer></body>"
+            )
+            .main_text(),
+            "Cat Blog Home Copyright"
+        );
+    }
+
     #[tokio::test]
     async fn get_links_from_url() {
         let url = "example.com";
@@ -120,7 +570,7 @@ mod tests {
 
         assert_eq!(
             links,
-            Vec::from(["https://franciscofunes.netlify.app/", "https://www.equaldev.com/", "https://creatumeme.netlify.app/", "https://flippingcard.netlify.app/", "https://dejalo-ir.herokuapp.com/", "https://regameapp.vercel.app/", "https://www.canva.com/design/DAEaKUy8pIc/_Ojr-mEqVtj3v1hiM_cPfg/view?utm_content=DAEaKUy8pIc&amp;utm_campaign=designshare&amp;utm_medium=link&amp;utm_source=publishpresent", "https://www.canva.com/design/DAEmkswvTqY/CTTmlrWqqg34YeZzoekonQ/view?utm_content=DAEmkswvTqY&utm_campaign=designshare&utm_medium=link&utm_source=publishsharelink", "https://www.canva.com/design/DAE2AP2m6aA/fffDzntOgOx7pLjGMH7msQ/view#7", "https://www.canva.com/design/DAE3VxjZRNE/Jcp8FgPK33SwrCNtR94KGw/view?utm_content=DAE3VxjZRNE&utm_campaign=designshare&utm_medium=link&utm_source=viewer", "https://www.canva.com/design/DAErbz8ncho/ngoanfXpz_xuerZ24ZqUhQ/view?utm_content=DAErbz8ncho&utm_campaign=designshare&utm_medium=link&utm_source=publishsharelink", "mailto:f.funes@bue.edu.ar", "https://wa.link/6kbz3s", "https://es.linkedin.com/in/francisco-funes/", "https://instagram.com/francisco_ign_/", "https://github.com/francisco-funes/", "https://codepen.io/franfunes"])
+            Vec::from(["https://franciscofunes.netlify.app/", "https://www.equaldev.com/", "https://creatumeme.netlify.app/", "https://flippingcard.netlify.app/", "https://dejalo-ir.herokuapp.com/", "https://regameapp.vercel.app/", "https://www.canva.com/design/DAEaKUy8pIc/_Ojr-mEqVtj3v1hiM_cPfg/view?utm_content=DAEaKUy8pIc&utm_campaign=designshare&utm_medium=link&utm_source=publishpresent", "https://www.canva.com/design/DAEmkswvTqY/CTTmlrWqqg34YeZzoekonQ/view?utm_content=DAEmkswvTqY&utm_campaign=designshare&utm_medium=link&utm_source=publishsharelink", "https://www.canva.com/design/DAE2AP2m6aA/fffDzntOgOx7pLjGMH7msQ/view#7", "https://www.canva.com/design/DAE3VxjZRNE/Jcp8FgPK33SwrCNtR94KGw/view?utm_content=DAE3VxjZRNE&utm_campaign=designshare&utm_medium=link&utm_source=viewer", "https://www.canva.com/design/DAErbz8ncho/ngoanfXpz_xuerZ24ZqUhQ/view?utm_content=DAErbz8ncho&utm_campaign=designshare&utm_medium=link&utm_source=publishsharelink", "mailto:f.funes@bue.edu.ar", "https://wa.link/6kbz3s", "https://es.linkedin.com/in/francisco-funes/", "https://instagram.com/francisco_ign_/", "https://github.com/francisco-funes/", "https://codepen.io/franfunes"])
         );
     }
 }
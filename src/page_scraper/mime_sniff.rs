@@ -0,0 +1,201 @@
+//! Content detection in the spirit of the WHATWG MIME sniffing standard
+//! (<https://mimesniff.spec.whatwg.org/>), reduced to what a crawler needs to know
+
+/// only this many bytes at the start of a resource are looked at
+const RESOURCE_HEADER_SIZE: usize = 1445;
+
+/// tags that identify html, they have to be followed by a space or `>`
+const HTML_PATTERNS: [&[u8]; 17] = [
+    b"<!doctype html",
+    b"<html",
+    b"<head",
+    b"<script",
+    b"<iframe",
+    b"<h1",
+    b"<div",
+    b"<font",
+    b"<table",
+    b"<a",
+    b"<style",
+    b"<title",
+    b"<b",
+    b"<body",
+    b"<br",
+    b"<p",
+    b"<!--",
+];
+
+/// essences of Content-Types that don't tell what a resource is, its bytes are sniffed instead
+const UNKNOWN_TYPES: [&str; 4] = ["", "unknown/unknown", "application/unknown", "*/*"];
+
+/// What kind of content a resource contains
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SniffedType {
+    Html,
+    Xml,
+    PlainText,
+    /// anything else, contains the MIME type essence if it is known
+    Other(Option<String>),
+}
+
+/// Determines the type of a resource from its `Content-Type` header and its first bytes
+///
+/// an explicit Content-Type is trusted, the bytes are only looked at if the type is missing or unknown
+pub fn sniff(content_type: Option<&str>, bytes: &[u8]) -> SniffedType {
+    let essence = content_type.map(mime_essence).unwrap_or_default();
+
+    match essence.as_str() {
+        essence if UNKNOWN_TYPES.contains(&essence) => sniff_unknown(bytes),
+        "text/html" | "application/xhtml+xml" => SniffedType::Html,
+        "text/plain" => SniffedType::PlainText,
+        "text/xml" | "application/xml" => SniffedType::Xml,
+        essence if essence.ends_with("+xml") => SniffedType::Xml,
+        _ => SniffedType::Other(Some(essence)),
+    }
+}
+
+/// checks if a resource with the Content-Type can turn out to be html (before its body is downloaded)
+///
+/// only html types and missing or unknown types (which are sniffed) can
+pub fn may_be_html(content_type: Option<&str>) -> bool {
+    let essence = content_type.map(mime_essence).unwrap_or_default();
+
+    UNKNOWN_TYPES.contains(&essence.as_str()) || sniff(content_type, b"") == SniffedType::Html
+}
+
+/// returns the lowercase `type/subtype` of a Content-Type header without parameters
+pub fn mime_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// the "rules for identifying an unknown MIME type"
+///
+/// unlike the standard, a leading byte order mark doesn't prevent html from being detected
+fn sniff_unknown(bytes: &[u8]) -> SniffedType {
+    let bytes = &bytes[..bytes.len().min(RESOURCE_HEADER_SIZE)];
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+
+    let start = bytes
+        .iter()
+        .position(|byte| !is_whitespace(*byte))
+        .unwrap_or(bytes.len());
+    let trimmed = &bytes[start..];
+
+    for pattern in HTML_PATTERNS {
+        if trimmed.len() > pattern.len()
+            && trimmed[..pattern.len()].eq_ignore_ascii_case(pattern)
+            && (trimmed[pattern.len()] == b' ' || trimmed[pattern.len()] == b'>')
+        {
+            return SniffedType::Html;
+        }
+
+        // a comment doesn't need a tag-terminating byte
+        if pattern == b"<!--" && trimmed.starts_with(pattern) {
+            return SniffedType::Html;
+        }
+    }
+
+    if trimmed.starts_with(b"<?xml") {
+        return SniffedType::Xml;
+    }
+
+    if bytes.starts_with(b"%PDF-") {
+        return SniffedType::Other(Some("application/pdf".to_string()));
+    }
+
+    if bytes.iter().any(|byte| is_binary(*byte)) {
+        return SniffedType::Other(None);
+    }
+
+    SniffedType::PlainText
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+/// bytes that never appear in text
+fn is_binary(byte: u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0B | 0x0E..=0x1A | 0x1C..=0x1F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{may_be_html, mime_essence, sniff, SniffedType};
+
+    #[test]
+    fn trusts_content_type() {
+        assert_eq!(
+            sniff(Some("text/html; charset=utf-8"), b"hello"),
+            SniffedType::Html
+        );
+        assert_eq!(
+            sniff(Some("Application/XHTML+XML"), b"<?xml version=\"1.0\"?>"),
+            SniffedType::Html
+        );
+        assert_eq!(
+            sniff(Some("text/plain"), b"<html></html>"),
+            SniffedType::PlainText
+        );
+        assert_eq!(sniff(Some("image/svg+xml"), b"<svg>"), SniffedType::Xml);
+        assert_eq!(
+            sniff(Some("application/json"), b"{}"),
+            SniffedType::Other(Some("application/json".to_string()))
+        );
+    }
+
+    #[test]
+    fn sniffs_html_without_doctype() {
+        assert_eq!(sniff(None, b"<!DOCTYPE html>"), SniffedType::Html);
+        assert_eq!(
+            sniff(
+                None,
+                b"<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\">"
+            ),
+            SniffedType::Html
+        );
+        assert_eq!(
+            sniff(None, b"\n  <HTML lang=\"en\"><head>"),
+            SniffedType::Html
+        );
+        assert_eq!(sniff(None, b"\xEF\xBB\xBF<html>"), SniffedType::Html);
+        assert_eq!(sniff(None, b"<!-- generated --><html>"), SniffedType::Html);
+        assert_eq!(sniff(Some("*/*"), b"<p>text</p>"), SniffedType::Html);
+        // `<a` has to be a whole tag name
+        assert_eq!(sniff(None, b"<abbr>"), SniffedType::PlainText);
+    }
+
+    #[test]
+    fn sniffs_other_content() {
+        assert_eq!(
+            sniff(None, b"<?xml version=\"1.0\"?><rss>"),
+            SniffedType::Xml
+        );
+        assert_eq!(
+            sniff(None, b"%PDF-1.7\n"),
+            SniffedType::Other(Some("application/pdf".to_string()))
+        );
+        assert_eq!(sniff(None, b"\x89PNG\r\n\x1A\n"), SniffedType::Other(None));
+        assert_eq!(sniff(None, b"just some text"), SniffedType::PlainText);
+    }
+
+    #[test]
+    fn may_be_html_before_download() {
+        assert!(may_be_html(Some("text/html; charset=utf-8")));
+        assert!(may_be_html(None));
+        assert!(may_be_html(Some("application/unknown")));
+        assert!(!may_be_html(Some("text/plain")));
+        assert!(!may_be_html(Some("application/pdf")));
+    }
+
+    #[test]
+    fn essence() {
+        assert_eq!(mime_essence(" Text/HTML ; charset=UTF-8"), "text/html");
+        assert_eq!(mime_essence(""), "");
+    }
+}
@@ -0,0 +1,256 @@
+use itertools::Itertools;
+use url::Url;
+
+use super::{
+    html::Html,
+    html_parser::{Document, Link},
+};
+
+/// query parameters that only track where a visitor came from and don't change the page
+const TRACKING_PARAMETERS: [&str; 12] = [
+    "utm_source",
+    "utm_medium",
+    "utm_campaign",
+    "utm_term",
+    "utm_content",
+    "utm_id",
+    "gclid",
+    "fbclid",
+    "msclkid",
+    "mc_cid",
+    "mc_eid",
+    "_ga",
+];
+
+/// Parses and normalizes an absolute url, links without a scheme are treated as http
+///
+/// - scheme and host are lowercased, the default port is removed
+/// - `.` and `..` path segments are resolved
+/// - the fragment is removed
+/// - tracking parameters are removed if `strip_tracking` is set
+pub fn normalize_url(url: &str, strip_tracking: bool) -> Option<String> {
+    let url = url.trim();
+
+    let parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            Url::parse(&format!("http://{url}")).ok()?
+        }
+        Err(_) => return None,
+    };
+
+    Some(normalize(parsed, strip_tracking))
+}
+
+/// Resolves a link against the url of the page it was found on and normalizes it
+pub fn resolve_link(base: &Url, href: &str, strip_tracking: bool) -> Option<String> {
+    let href = href.trim();
+
+    // only pointing to a position on the same page
+    if href.is_empty() || href.starts_with('#') {
+        return None;
+    }
+
+    let resolved = base.join(href).ok()?;
+
+    Some(normalize(resolved, strip_tracking))
+}
+
+/// Returns all links of the page as normalized, absolute and unique urls
+///
+/// relative links are resolved against `<base href>` if the page has one, otherwise against the page url
+#[allow(dead_code)]
+pub fn resolve_links(page_url: &str, html: &Html, strip_tracking: bool) -> Vec<String> {
+    resolve_outlinks(page_url, html, strip_tracking)
+        .into_iter()
+        .map(|link| link.href)
+        .collect()
+}
+
+/// Like `resolve_links`, but keeps the anchor text and `rel` of every link
+///
+/// if a page links to the same url more than once, the first link is kept
+pub fn resolve_outlinks(page_url: &str, html: &Html, strip_tracking: bool) -> Vec<Link> {
+    let page_url = match normalize_url(page_url, false).and_then(|url| Url::parse(&url).ok()) {
+        Some(page_url) => page_url,
+        None => return vec![],
+    };
+
+    let document = Document::parse(html);
+
+    let base = document
+        .base_href()
+        .and_then(|base_href| page_url.join(&base_href).ok())
+        .unwrap_or(page_url);
+
+    document
+        .links()
+        .into_iter()
+        .filter_map(|link| {
+            Some(Link {
+                href: resolve_link(&base, &link.href, strip_tracking)?,
+                ..link
+            })
+        })
+        .unique_by(|link| link.href.clone())
+        .collect()
+}
+
+fn normalize(mut url: Url, strip_tracking: bool) -> String {
+    url.set_fragment(None);
+
+    if strip_tracking {
+        if let Some(query) = url.query() {
+            // the other parameters keep their encoding, `%20` and `+` aren't the same for every server
+            let query = query
+                .split('&')
+                .filter(|segment| !is_tracking_parameter(segment))
+                .join("&");
+
+            url.set_query(Some(&query));
+        }
+    }
+
+    // `http://a.com/?` and `http://a.com/` are the same page
+    if url.query() == Some("") {
+        url.set_query(None);
+    }
+
+    url.to_string()
+}
+
+/// checks if a raw `key=value` segment of a query is a tracking parameter
+fn is_tracking_parameter(segment: &str) -> bool {
+    url::form_urlencoded::parse(segment.as_bytes())
+        .next()
+        .is_some_and(|(key, _)| TRACKING_PARAMETERS.contains(&key.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::page_scraper::html::Html;
+
+    use super::{normalize_url, resolve_link, resolve_links, resolve_outlinks};
+
+    #[test]
+    fn normalizes_urls() {
+        assert_eq!(
+            normalize_url("HTTP://Example.COM", false).unwrap(),
+            "http://example.com/"
+        );
+        assert_eq!(
+            normalize_url("http://example.com:80/a/./b/../c#top", false).unwrap(),
+            "http://example.com/a/c"
+        );
+        assert_eq!(
+            normalize_url("https://example.com:443/?", false).unwrap(),
+            "https://example.com/"
+        );
+        assert_eq!(
+            normalize_url("https://example.com:8443/", false).unwrap(),
+            "https://example.com:8443/"
+        );
+        assert_eq!(
+            normalize_url("example.com", false).unwrap(),
+            "http://example.com/"
+        );
+    }
+
+    #[test]
+    fn strips_tracking_parameters() {
+        let url = "https://www.canva.com/design/view?utm_content=DAE&id=3&utm_campaign=designshare";
+
+        assert_eq!(
+            normalize_url(url, true).unwrap(),
+            "https://www.canva.com/design/view?id=3"
+        );
+        assert_eq!(normalize_url(url, false).unwrap(), url);
+        assert_eq!(
+            normalize_url("https://a.com/?utm_source=x", true).unwrap(),
+            "https://a.com/"
+        );
+        assert_eq!(
+            normalize_url("https://a.com/?q=a%20b&path=b%2Fc&utm_source=x&x=1+2", true).unwrap(),
+            "https://a.com/?q=a%20b&path=b%2Fc&x=1+2"
+        );
+    }
+
+    #[test]
+    fn resolves_relative_links() {
+        let base = Url::parse("http://a.com/docs/page.html").unwrap();
+
+        assert_eq!(
+            resolve_link(&base, "/about", false).unwrap(),
+            "http://a.com/about"
+        );
+        assert_eq!(
+            resolve_link(&base, "../x", false).unwrap(),
+            "http://a.com/x"
+        );
+        assert_eq!(
+            resolve_link(&base, "other.html#part", false).unwrap(),
+            "http://a.com/docs/other.html"
+        );
+        assert_eq!(
+            resolve_link(&base, "//b.com", false).unwrap(),
+            "http://b.com/"
+        );
+        assert_eq!(
+            resolve_link(&base, "www.google.ch", false).unwrap(),
+            "http://a.com/docs/www.google.ch"
+        );
+        assert_eq!(resolve_link(&base, "#top", false), None);
+        assert_eq!(resolve_link(&base, "", false), None);
+    }
+
+    #[test]
+    fn resolves_links_of_page() {
+        let html = Html::new(
+            r##"
+            <!doctype html>
+            <html>
+            <head><base href="/blog/"></head>
+            <body>
+            <a href="post?id=1&amp;page=2">Post</a>
+            <a href="HTTPS://Other.com:443/">Other</a>
+            <a href="post?id=1&page=2#comments">Post</a>
+            <a href="#top">Top</a>
+            </body>
+            </html>
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolve_links("http://a.com/index.html", &html, false),
+            vec!["http://a.com/blog/post?id=1&page=2", "https://other.com/"]
+        );
+    }
+
+    #[test]
+    fn keeps_anchor_text() {
+        let html = Html::new(
+            r#"
+            <!doctype html>
+            <html>
+            <body>
+            <a href="/a" rel="nofollow">First <b>link</b></a>
+            <a href="/a">Second link</a>
+            <a href="/b">B</a>
+            </body>
+            </html>
+            "#,
+        )
+        .unwrap();
+
+        let links = resolve_outlinks("http://a.com/", &html, false);
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].href, "http://a.com/a");
+        assert_eq!(links[0].text, "First link");
+        assert_eq!(links[0].rel.as_deref(), Some("nofollow"));
+        assert_eq!(links[1].href, "http://a.com/b");
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hyper::{header, Uri};
+
+use crate::page_scraper::{
+    fetcher::{FetchLimits, Fetcher},
+    html::HtmlGetterError,
+    html_getter::parse_uri,
+};
+
+use super::parser::{parse_robots, RobotsRules};
+
+/// product token that is matched against the `User-agent` lines
+pub const AGENT_TOKEN: &str = "guugle";
+/// how long fetched rules are used before the robots.txt is requested again
+const CACHE_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+/// only this many bytes of a robots.txt are parsed
+const MAX_ROBOTS_SIZE: usize = 500 * 1024;
+/// redirects followed while fetching a robots.txt
+const MAX_REDIRECTS: usize = 5;
+
+/// Caches the robots.txt rules per host (scheme + authority)
+pub struct RobotsCache {
+    entries: Mutex<HashMap<String, (Instant, RobotsRules)>>,
+    /// origins whose robots.txt is being fetched, later callers wait for the first fetch
+    fetching: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    fetcher: Fetcher,
+}
+
+impl RobotsCache {
+    pub fn new() -> Self {
+        Self::with_fetcher(Fetcher::new(FetchLimits::default()))
+    }
+
+    /// creates a cache that fetches the robots.txt files with the (shared) fetcher
+    pub fn with_fetcher(fetcher: Fetcher) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            fetching: Mutex::new(HashMap::new()),
+            fetcher,
+        }
+    }
+
+    /// returns the rules for the host of the url, the robots.txt is fetched if the host isn't cached yet
+    ///
+    /// only one robots.txt request per origin is made at a time, the other callers wait for its rules,
+    /// returns None if the url can't be parsed
+    pub async fn rules_for(&self, url: &str) -> Option<RobotsRules> {
+        let origin = origin(&parse_uri(url).ok()?)?;
+
+        if let Some(rules) = self.cached(&origin) {
+            return Some(rules);
+        }
+
+        let fetching = Arc::clone(
+            self.fetching
+                .lock()
+                .unwrap()
+                .entry(origin.clone())
+                .or_default(),
+        );
+        let _guard = fetching.lock().await;
+
+        // another caller may have fetched the rules while this one was waiting
+        let rules = match self.cached(&origin) {
+            Some(rules) => rules,
+            None => {
+                let rules = fetch_rules(&self.fetcher, &origin).await;
+                self.insert(&origin, rules.clone());
+                rules
+            }
+        };
+
+        // the last caller of the origin removes its entry, the map and this caller hold the other references
+        let mut entries = self.fetching.lock().unwrap();
+        if Arc::strong_count(&fetching) == 2 {
+            entries.remove(&origin);
+        }
+
+        Some(rules)
+    }
+
+    /// checks if the url may be crawled, urls that can't be parsed are allowed
+    pub async fn is_allowed(&self, url: &str) -> bool {
+        let uri = match parse_uri(url) {
+            Ok(uri) => uri,
+            Err(_) => return true,
+        };
+
+        let path = uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+
+        match self.rules_for(url).await {
+            Some(rules) => rules.is_allowed(path),
+            None => true,
+        }
+    }
+
+    /// checks if the rules of the host of the url are cached, `is_allowed` doesn't send a request then
+    ///
+    /// urls that can't be parsed don't need rules
+    pub fn has_rules(&self, url: &str) -> bool {
+        match parse_uri(url).ok().and_then(|uri| origin(&uri)) {
+            Some(origin) => self.cached(&origin).is_some(),
+            None => true,
+        }
+    }
+
+    /// returns the crawl delay of the host of the url if its rules are cached
+    pub fn crawl_delay(&self, url: &str) -> Option<Duration> {
+        let origin = origin(&parse_uri(url).ok()?)?;
+
+        self.cached(&origin)?.crawl_delay
+    }
+
+    /// returns the cached rules for the origin if they haven't expired
+    pub fn cached(&self, origin: &str) -> Option<RobotsRules> {
+        let entries = self.entries.lock().unwrap();
+
+        entries
+            .get(origin)
+            .filter(|(fetched, _)| fetched.elapsed() < CACHE_DURATION)
+            .map(|(_, rules)| rules.clone())
+    }
+
+    /// stores the rules for the origin
+    pub fn insert(&self, origin: &str, rules: RobotsRules) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(origin.to_string(), (Instant::now(), rules));
+    }
+}
+
+impl Default for RobotsCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// returns scheme and authority of the uri, e.g. `https://example.com:8080`
+pub fn origin(uri: &Uri) -> Option<String> {
+    Some(format!(
+        "{}://{}",
+        uri.scheme_str()?.to_lowercase(),
+        uri.authority()?.as_str().to_lowercase()
+    ))
+}
+
+/// Fetches and parses the robots.txt of the origin
+///
+/// a missing robots.txt (4xx) allows everything, an unreachable one (5xx, network error) disallows everything
+async fn fetch_rules(fetcher: &Fetcher, origin: &str) -> RobotsRules {
+    let mut location = format!("{origin}/robots.txt");
+
+    for _ in 0..=MAX_REDIRECTS {
+        let response = match fetcher.get(&location).await {
+            Ok(response) => response,
+            Err(HtmlGetterError::UrlError(_)) => return RobotsRules::allow_all(),
+            Err(_) => return RobotsRules::disallow_all(),
+        };
+
+        let status = response.status();
+
+        if status.is_redirection() {
+            match response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+            {
+                Some(next) if next.starts_with('/') => location = format!("{origin}{next}"),
+                Some(next) => location = next.to_string(),
+                None => return RobotsRules::allow_all(),
+            }
+
+            continue;
+        }
+
+        if status.is_success() {
+            let bytes = match fetcher
+                .read_body(response.into_body(), MAX_ROBOTS_SIZE)
+                .await
+            {
+                Ok(bytes) => bytes,
+                Err(_) => return RobotsRules::disallow_all(),
+            };
+
+            return parse_robots(&String::from_utf8_lossy(&bytes), AGENT_TOKEN);
+        }
+
+        if status.is_client_error() {
+            return RobotsRules::allow_all();
+        }
+
+        return RobotsRules::disallow_all();
+    }
+
+    // too many redirects are treated like a missing robots.txt
+    RobotsRules::allow_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+
+    use crate::robots::parser::{parse_robots, RobotsRules};
+
+    use super::{origin, RobotsCache, AGENT_TOKEN};
+
+    /// starts a local server that answers the robots.txt slowly and counts its requests
+    fn serve_robots() -> (SocketAddr, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+
+        let make_service = make_service_fn(move |_| {
+            let counter = Arc::clone(&counter);
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_| {
+                    let counter = Arc::clone(&counter);
+
+                    async move {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+
+                        Ok::<_, Infallible>(Response::new(Body::from(
+                            "User-agent: *\nDisallow: /private",
+                        )))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = server.local_addr();
+
+        tokio::spawn(server);
+
+        (address, requests)
+    }
+
+    #[test]
+    fn origin_of_uri() {
+        assert_eq!(
+            origin(&"HTTPS://Example.com:8080/a/b?c".parse().unwrap()).unwrap(),
+            "https://example.com:8080"
+        );
+        assert_eq!(origin(&"/a/b".parse().unwrap()), None);
+    }
+
+    #[tokio::test]
+    async fn uses_cached_rules() {
+        let cache = RobotsCache::new();
+
+        cache.insert(
+            "http://example.com",
+            parse_robots("User-agent: *\nDisallow: /private", AGENT_TOKEN),
+        );
+
+        assert!(cache.is_allowed("http://example.com/").await);
+        assert!(cache.is_allowed("http://example.com/public").await);
+        assert!(!cache.is_allowed("http://example.com/private/page").await);
+        assert_eq!(cache.crawl_delay("http://example.com/"), None);
+        assert_eq!(cache.cached("http://example.org"), None);
+        assert!(cache.has_rules("http://example.com/a"));
+        assert!(!cache.has_rules("http://example.org/a"));
+    }
+
+    #[tokio::test]
+    async fn rules_for_cached_host() {
+        let cache = RobotsCache::new();
+
+        cache.insert("https://example.com", RobotsRules::disallow_all());
+        cache.insert(
+            "https://example.org",
+            parse_robots("User-agent: *\nCrawl-delay: 3", AGENT_TOKEN),
+        );
+
+        assert_eq!(
+            cache.rules_for("https://example.com/index.html").await,
+            Some(RobotsRules::disallow_all())
+        );
+        assert_eq!(
+            cache.crawl_delay("https://example.org/a"),
+            Some(Duration::from_secs(3))
+        );
+    }
+
+    /// tasks that reach a new host at the same time share one robots.txt request
+    #[tokio::test]
+    async fn fetches_robots_once() {
+        let (address, requests) = serve_robots();
+        let cache = Arc::new(RobotsCache::new());
+
+        let tasks = (0..16)
+            .map(|i| {
+                let cache = Arc::clone(&cache);
+
+                tokio::spawn(async move {
+                    cache
+                        .is_allowed(&format!("http://{address}/private/{i}"))
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            assert!(!task.await.unwrap());
+        }
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(cache.fetching.lock().unwrap().is_empty());
+    }
+}
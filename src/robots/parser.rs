@@ -0,0 +1,292 @@
+use std::time::Duration;
+
+/// A single `Allow` or `Disallow` line of a robots.txt group
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub allow: bool,
+    pub pattern: String,
+}
+
+/// The rules of a robots.txt that apply to one user agent
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsRules {
+    pub rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl RobotsRules {
+    /// rules that allow every path (used if no robots.txt exists)
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// rules that disallow every path (used if the robots.txt is unreachable)
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".to_string(),
+            }],
+            crawl_delay: None,
+        }
+    }
+
+    /// checks if the path (including the query) may be crawled
+    ///
+    /// the longest matching rule wins, if an allow and a disallow rule are equally long the allow rule wins
+    pub fn is_allowed(&self, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+
+        let mut best: Option<&Rule> = None;
+
+        for rule in self.rules.iter() {
+            if !pattern_matches(&rule.pattern, path) {
+                continue;
+            }
+
+            best = match best {
+                Some(current)
+                    if current.pattern.len() > rule.pattern.len()
+                        || (current.pattern.len() == rule.pattern.len() && current.allow) =>
+                {
+                    Some(current)
+                }
+                _ => Some(rule),
+            };
+        }
+
+        best.map(|rule| rule.allow).unwrap_or(true)
+    }
+}
+
+/// Parses a robots.txt and returns the rules that apply to the user agent
+///
+/// groups that name the user agent take precedence over the `*` group, multiple matching groups are merged
+pub fn parse_robots(text: &str, user_agent: &str) -> RobotsRules {
+    let user_agent = user_agent.to_lowercase();
+
+    let mut specific = RobotsRules::default();
+    let mut specific_found = false;
+    let mut wildcard = RobotsRules::default();
+    let mut wildcard_found = false;
+
+    // user agents of the group that is currently parsed
+    let mut group_agents: Vec<String> = vec![];
+    // set as soon as the group has a rule, the next user-agent line starts a new group
+    let mut in_rules = false;
+
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        if key == "user-agent" {
+            if in_rules {
+                group_agents.clear();
+                in_rules = false;
+            }
+
+            let agent = value.to_lowercase();
+
+            // an empty user agent names no crawler (`starts_with` would match every crawler)
+            if agent.is_empty() {
+                continue;
+            }
+
+            if agent == "*" {
+                wildcard_found = true;
+            } else if user_agent.starts_with(&agent) {
+                specific_found = true;
+            }
+
+            group_agents.push(agent);
+            continue;
+        }
+
+        if !["allow", "disallow", "crawl-delay"].contains(&key.as_str()) {
+            continue;
+        }
+
+        in_rules = true;
+
+        let mut targets = vec![];
+
+        if group_agents
+            .iter()
+            .any(|agent| agent != "*" && user_agent.starts_with(agent.as_str()))
+        {
+            targets.push(&mut specific);
+        }
+        if group_agents.iter().any(|agent| agent == "*") {
+            targets.push(&mut wildcard);
+        }
+
+        for target in targets {
+            match key.as_str() {
+                "crawl-delay" => {
+                    if let Ok(seconds) = value.parse::<f64>() {
+                        if seconds.is_finite() && seconds >= 0.0 {
+                            target.crawl_delay = Some(Duration::from_secs_f64(seconds));
+                        }
+                    }
+                }
+                // an empty rule doesn't restrict anything
+                _ if value.is_empty() => {}
+                _ => target.rules.push(Rule {
+                    allow: key == "allow",
+                    pattern: value.to_string(),
+                }),
+            }
+        }
+    }
+
+    if specific_found {
+        specific
+    } else if wildcard_found {
+        wildcard
+    } else {
+        RobotsRules::allow_all()
+    }
+}
+
+/// checks if a robots.txt path pattern matches the path
+///
+/// `*` matches any sequence of characters, a trailing `$` anchors the pattern at the end of the path
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let path = path.as_bytes();
+
+    // all positions in the path that can be reached by the pattern read so far
+    let mut positions = vec![0];
+
+    for byte in pattern.bytes() {
+        if byte == b'*' {
+            positions = (positions[0]..=path.len()).collect();
+        } else {
+            positions = positions
+                .into_iter()
+                .filter(|&position| position < path.len() && path[position] == byte)
+                .map(|position| position + 1)
+                .collect();
+        }
+
+        if positions.is_empty() {
+            return false;
+        }
+    }
+
+    !anchored || positions.contains(&path.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_robots, pattern_matches, RobotsRules};
+
+    #[test]
+    fn patterns() {
+        assert!(pattern_matches("/", "/index.html"));
+        assert!(pattern_matches("/fish", "/fish.html"));
+        assert!(!pattern_matches("/fish", "/Fish.asp"));
+        assert!(pattern_matches("/*.php", "/folder/filename.php?parameters"));
+        assert!(!pattern_matches("/*.php$", "/filename.php?parameters"));
+        assert!(pattern_matches("/*.php$", "/folder/filename.php"));
+        assert!(pattern_matches("/fish*", "/fish"));
+        assert!(!pattern_matches("/fish/", "/fish"));
+    }
+
+    #[test]
+    fn longest_match_wins() {
+        let robots = "
+        User-agent: *
+        Disallow: /private
+        Allow: /private/public
+        Disallow: /*.gif$
+        ";
+
+        let rules = parse_robots(robots, "guugle");
+
+        assert!(!rules.is_allowed("/private/secret.html"));
+        assert!(rules.is_allowed("/private/public/index.html"));
+        assert!(!rules.is_allowed("/images/cat.gif"));
+        assert!(rules.is_allowed("/images/cat.gif?size=2"));
+        assert!(rules.is_allowed("/"));
+    }
+
+    #[test]
+    fn allow_wins_ties() {
+        let robots = "
+        User-agent: *
+        Disallow: /page
+        Allow: /page
+        ";
+
+        assert!(parse_robots(robots, "guugle").is_allowed("/page"));
+    }
+
+    #[test]
+    fn specific_group_preferred() {
+        let robots = "
+        # everyone else
+        User-agent: *
+        Disallow: /
+
+        User-agent: otherbot
+        User-agent: Guugle
+        Disallow: /admin
+        Crawl-delay: 2.5
+        ";
+
+        let rules = parse_robots(robots, "guugle");
+
+        assert!(rules.is_allowed("/index.html"));
+        assert!(!rules.is_allowed("/admin/"));
+        assert_eq!(rules.crawl_delay, Some(Duration::from_millis(2500)));
+
+        let rules = parse_robots(robots, "somebot");
+
+        assert!(!rules.is_allowed("/index.html"));
+        assert_eq!(rules.crawl_delay, None);
+    }
+
+    #[test]
+    fn empty_user_agent_ignored() {
+        let robots = "
+        User-agent:
+        Disallow: /
+
+        User-agent: *
+        Disallow: /private
+        ";
+
+        let rules = parse_robots(robots, "guugle");
+
+        assert!(rules.is_allowed("/index.html"));
+        assert!(!rules.is_allowed("/private"));
+    }
+
+    #[test]
+    fn empty_robots() {
+        assert_eq!(parse_robots("", "guugle"), RobotsRules::allow_all());
+        assert!(parse_robots("User-agent: *\nDisallow:\n", "guugle").is_allowed("/x"));
+    }
+
+    #[test]
+    fn disallow_all() {
+        let rules = RobotsRules::disallow_all();
+
+        assert!(!rules.is_allowed("/"));
+        assert!(!rules.is_allowed("/index.html"));
+        assert!(rules.is_allowed("/robots.txt"));
+    }
+}